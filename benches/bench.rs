@@ -2,7 +2,11 @@
 extern crate prometrics;
 extern crate test;
 
-use prometrics::metrics::{Counter, Gauge, HistogramBuilder, Summary};
+use prometrics::metrics::{
+    Counter, CounterBuilder, Gauge, HistogramBuilder, Summary, SummaryBuilder,
+};
+use prometrics::Gatherer;
+use std::thread;
 use std::time::Duration;
 
 #[bench]
@@ -13,6 +17,38 @@ fn counter_increment(b: &mut test::Bencher) {
     })
 }
 
+#[bench]
+fn counter_increment_contended(b: &mut test::Bencher) {
+    let counter = Counter::new("bench").unwrap();
+    b.iter(|| {
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        counter.increment();
+                    }
+                });
+            }
+        });
+    })
+}
+
+#[bench]
+fn sharded_counter_increment_contended(b: &mut test::Bencher) {
+    let counter = CounterBuilder::new("bench").sharded(8).finish().unwrap();
+    b.iter(|| {
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        counter.increment();
+                    }
+                });
+            }
+        });
+    })
+}
+
 #[bench]
 fn counter_add_float(b: &mut test::Bencher) {
     let counter = Counter::new("bench").unwrap();
@@ -55,6 +91,30 @@ fn histogram_observe(b: &mut test::Bencher) {
     })
 }
 
+#[bench]
+fn histogram_observe_loop(b: &mut test::Bencher) {
+    let histogram = HistogramBuilder::with_linear_buckets("bench", 0.0, 1.0, 10)
+        .finish()
+        .unwrap();
+    let values = (0..100).map(|i| i as f64 * 0.1).collect::<Vec<_>>();
+    b.iter(|| {
+        for &v in &values {
+            histogram.observe(v);
+        }
+    })
+}
+
+#[bench]
+fn histogram_observe_many(b: &mut test::Bencher) {
+    let histogram = HistogramBuilder::with_linear_buckets("bench", 0.0, 1.0, 10)
+        .finish()
+        .unwrap();
+    let values = (0..100).map(|i| i as f64 * 0.1).collect::<Vec<_>>();
+    b.iter(|| {
+        histogram.observe_many(&values);
+    })
+}
+
 #[bench]
 fn summary_observe(b: &mut test::Bencher) {
     let summary = Summary::new("bench", Duration::from_millis(10)).unwrap();
@@ -62,3 +122,104 @@ fn summary_observe(b: &mut test::Bencher) {
         summary.observe(3.3);
     })
 }
+
+#[bench]
+fn summary_quantiles_large_window(b: &mut test::Bencher) {
+    let summary = SummaryBuilder::new("bench", Duration::from_secs(3600))
+        .quantile(0.5)
+        .quantile(0.99)
+        .finish()
+        .unwrap();
+    for i in 0..100_000 {
+        summary.observe(f64::from(i % 1000));
+    }
+    b.iter(|| summary.quantiles())
+}
+
+#[bench]
+fn summary_observe_contended(b: &mut test::Bencher) {
+    let summary = Summary::new("bench", Duration::from_millis(10)).unwrap();
+    b.iter(|| {
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        summary.observe(3.3);
+                    }
+                });
+            }
+        });
+    })
+}
+
+#[bench]
+fn counter_build_with_labels(b: &mut test::Bencher) {
+    b.iter(|| {
+        CounterBuilder::new("bench")
+            .label("foo", "bar")
+            .label("baz", "qux")
+            .finish()
+            .unwrap()
+    })
+}
+
+#[bench]
+fn to_text_labeled(b: &mut test::Bencher) {
+    let gatherer = Gatherer::new();
+    let _counters = (0..1000)
+        .map(|i| {
+            CounterBuilder::new("bench")
+                .label("method", "GET")
+                .label("path", "/foo/bar")
+                .label("id", &i.to_string())
+                .registry(gatherer.registry())
+                .finish()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let families = gatherer.gather();
+    b.iter(|| families.to_text())
+}
+
+#[bench]
+fn counters_to_string(b: &mut test::Bencher) {
+    let gatherer = Gatherer::new();
+    let counters = (0..10_000)
+        .map(|i| {
+            CounterBuilder::new("bench")
+                .label("id", &i.to_string())
+                .registry(gatherer.registry())
+                .finish()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let mut buf = Vec::new();
+    b.iter(|| {
+        buf.clear();
+        for c in counters.iter() {
+            buf.extend_from_slice(c.to_string().as_bytes());
+            buf.push(b'\n');
+        }
+    })
+}
+
+#[bench]
+fn counters_write_sample(b: &mut test::Bencher) {
+    let gatherer = Gatherer::new();
+    let counters = (0..10_000)
+        .map(|i| {
+            CounterBuilder::new("bench")
+                .label("id", &i.to_string())
+                .registry(gatherer.registry())
+                .finish()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let mut buf = Vec::new();
+    b.iter(|| {
+        buf.clear();
+        for c in counters.iter() {
+            c.write_sample(&mut buf).unwrap();
+        }
+    })
+}
@@ -1,16 +1,12 @@
 extern crate prometrics;
-#[macro_use]
-extern crate trackable;
 
 use prometrics::metrics::ProcessMetricsCollector;
-use trackable::error::Failure;
+use prometrics::Gatherer;
 
 fn main() {
-    prometrics::default_registry().register(ProcessMetricsCollector::new());
+    let gatherer = Gatherer::new();
+    gatherer.registry().register(ProcessMetricsCollector::new());
 
-    let mut gatherer = track_try_unwrap!(prometrics::default_gatherer()
-        .lock()
-        .map_err(|e| Failure::from_error(e.to_string())));
     for metric in gatherer.gather() {
         println!("{}", metric);
     }
@@ -1,29 +1,64 @@
 use std::cmp;
 use std::fmt;
+use std::sync::Arc;
 
-use bucket::AggregatedCumulativeBuckets;
+use bucket::{AggregatedCumulativeBuckets, CumulativeBucket};
 use label::Labels;
-use metric::{MetricName, MetricValue};
-use metrics::{Counter, Gauge, Histogram, Summary};
+use metric::{write_labels_with_reserved, BucketBound, MetricName, TextOptions};
+use metrics::{Counter, FrozenHistogram, Gauge, Histogram, Summary};
 use quantile::Quantile;
 use timestamp::Timestamp;
 
+// A metric which can be re-aggregated by a subset of its labels.
+pub(crate) trait Group: Sized {
+    fn labels(&self) -> &Labels;
+
+    fn retain_labels(&mut self, names: &[&str]);
+
+    fn try_merge(&mut self, other: &Self) -> bool;
+
+    // Drops the labels other than `names` from each metric, and merges the ones which have the same labels.
+    fn group_by(metrics: &mut Vec<Self>, names: &[&str]) {
+        for m in metrics.iter_mut() {
+            m.retain_labels(names);
+        }
+        metrics.sort_by(|a, b| {
+            a.labels()
+                .with_labels(|x| b.labels().with_labels(|y| x.cmp(y)))
+        });
+        let mut grouped: Vec<Self> = Vec::with_capacity(metrics.len());
+        for m in metrics.drain(..) {
+            if let Some(g) = grouped.last_mut() {
+                if g.try_merge(&m) {
+                    continue;
+                }
+            }
+            grouped.push(m);
+        }
+        *metrics = grouped;
+    }
+}
+
 /// A metric for aggregating counters that have the same name and labels.
 #[derive(Debug, Clone)]
 pub struct AggregatedCounter {
+    name: MetricName,
     inner: Counter,
     timestamp: Option<i64>,
     value: f64,
+
+    // The labels which override the ones of the aggregated metrics (see `Gatherer::gather_grouped_by`).
+    labels: Option<Arc<Labels>>,
 }
 impl AggregatedCounter {
     /// Returns the name of this metric.
     pub fn metric_name(&self) -> &MetricName {
-        self.inner.metric_name()
+        &self.name
     }
 
     /// Returns the labels of this metric.
     pub fn labels(&self) -> &Labels {
-        self.inner.labels()
+        self.labels.as_ref().map_or(self.inner.labels(), |l| l)
     }
 
     /// Returns the latest timestamp among the counters in this aggregation.
@@ -40,15 +75,23 @@ impl AggregatedCounter {
         let value = counter.value();
         let timestamp = counter.timestamp().get();
         AggregatedCounter {
+            name: counter.metric_name().clone(),
             inner: counter,
             timestamp,
             value,
+            labels: None,
         }
     }
 
+    pub(crate) fn set_metric_name(&mut self, name: MetricName) {
+        self.name = name;
+    }
+
     pub(crate) fn try_merge(&mut self, other: &Self) -> bool {
         let is_same_metric = self.metric_name() == other.metric_name()
-            && self.labels().iter().eq(other.labels().iter());
+            && self
+                .labels()
+                .with_labels(|x| other.labels().with_labels(|y| x.eq(y)));
         if is_same_metric {
             self.value += other.value;
             self.timestamp = cmp::max(self.timestamp, other.timestamp);
@@ -58,36 +101,65 @@ impl AggregatedCounter {
         }
     }
 }
-impl fmt::Display for AggregatedCounter {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl AggregatedCounter {
+    pub(crate) fn write_text<W: fmt::Write>(
+        &self,
+        f: &mut W,
+        options: &TextOptions,
+    ) -> fmt::Result {
         write!(f, "{}", self.metric_name())?;
         if !self.labels().is_empty() {
             write!(f, "{}", self.labels())?;
         }
-        write!(f, " {}", MetricValue(self.value()))?;
-        if let Some(timestamp) = self.timestamp {
+        write!(f, " {}", options.value(self.value()))?;
+        if let Some(timestamp) = self.timestamp.filter(|_| options.timestamps) {
             write!(f, " {}", timestamp)?;
         }
         Ok(())
     }
 }
+impl Group for AggregatedCounter {
+    fn labels(&self) -> &Labels {
+        AggregatedCounter::labels(self)
+    }
+
+    fn retain_labels(&mut self, names: &[&str]) {
+        self.labels = Some(Arc::new(self.labels().retained(names)));
+    }
+
+    fn try_merge(&mut self, other: &Self) -> bool {
+        AggregatedCounter::try_merge(self, other)
+    }
+}
+impl fmt::Display for AggregatedCounter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_text(f, &TextOptions::default())
+    }
+}
 
 /// A metric for aggregating gauges that have the same name and labels.
 #[derive(Debug, Clone)]
 pub struct AggregatedGauge {
+    name: MetricName,
     inner: Gauge,
     timestamp: Option<i64>,
     value: f64,
+
+    // The exact value if all of the aggregated gauges are integer gauges.
+    integer_value: Option<i64>,
+
+    // The labels which override the ones of the aggregated metrics (see `Gatherer::gather_grouped_by`).
+    labels: Option<Arc<Labels>>,
 }
 impl AggregatedGauge {
     /// Returns the name of this metric.
     pub fn metric_name(&self) -> &MetricName {
-        self.inner.metric_name()
+        &self.name
     }
 
     /// Returns the labels of this metric.
     pub fn labels(&self) -> &Labels {
-        self.inner.labels()
+        self.labels.as_ref().map_or(self.inner.labels(), |l| l)
     }
 
     /// Returns the latest timestamp among the counters in this aggregation.
@@ -102,19 +174,33 @@ impl AggregatedGauge {
 
     pub(crate) fn new(gauge: Gauge) -> Self {
         let value = gauge.value();
+        let integer_value = gauge.integer_value();
         let timestamp = gauge.timestamp().get();
         AggregatedGauge {
+            name: gauge.metric_name().clone(),
             inner: gauge,
             timestamp,
             value,
+            integer_value,
+            labels: None,
         }
     }
 
+    pub(crate) fn set_metric_name(&mut self, name: MetricName) {
+        self.name = name;
+    }
+
     pub(crate) fn try_merge(&mut self, other: &Self) -> bool {
         let is_same_metric = self.metric_name() == other.metric_name()
-            && self.labels().iter().eq(other.labels().iter());
+            && self
+                .labels()
+                .with_labels(|x| other.labels().with_labels(|y| x.eq(y)));
         if is_same_metric {
             self.value += other.value;
+            self.integer_value = match (self.integer_value, other.integer_value) {
+                (Some(a), Some(b)) => a.checked_add(b),
+                _ => None,
+            };
             self.timestamp = cmp::max(self.timestamp, other.timestamp);
             true
         } else {
@@ -122,34 +208,64 @@ impl AggregatedGauge {
         }
     }
 }
-impl fmt::Display for AggregatedGauge {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl AggregatedGauge {
+    pub(crate) fn write_text<W: fmt::Write>(
+        &self,
+        f: &mut W,
+        options: &TextOptions,
+    ) -> fmt::Result {
         write!(f, "{}", self.metric_name())?;
         if !self.labels().is_empty() {
             write!(f, "{}", self.labels())?;
         }
-        write!(f, " {}", MetricValue(self.value()))?;
-        if let Some(timestamp) = self.timestamp {
+        if let Some(value) = self.integer_value {
+            write!(f, " {}", value)?;
+        } else {
+            write!(f, " {}", options.value(self.value()))?;
+        }
+        if let Some(timestamp) = self.timestamp.filter(|_| options.timestamps) {
             write!(f, " {}", timestamp)?;
         }
         Ok(())
     }
 }
+impl Group for AggregatedGauge {
+    fn labels(&self) -> &Labels {
+        AggregatedGauge::labels(self)
+    }
+
+    fn retain_labels(&mut self, names: &[&str]) {
+        self.labels = Some(Arc::new(self.labels().retained(names)));
+    }
+
+    fn try_merge(&mut self, other: &Self) -> bool {
+        AggregatedGauge::try_merge(self, other)
+    }
+}
+impl fmt::Display for AggregatedGauge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_text(f, &TextOptions::default())
+    }
+}
 
 /// A metric for aggregating histograms that have the same name and labels.
 #[derive(Debug, Clone)]
 pub struct AggregatedHistogram {
+    name: MetricName,
     inners: Vec<Histogram>,
+
+    // The labels which override the ones of the aggregated metrics (see `Gatherer::gather_grouped_by`).
+    labels: Option<Arc<Labels>>,
 }
 impl AggregatedHistogram {
     /// Returns the name of this metric.
     pub fn metric_name(&self) -> &MetricName {
-        self.inners[0].metric_name()
+        &self.name
     }
 
     /// Returns the labels of this metric.
     pub fn labels(&self) -> &Labels {
-        self.inners[0].labels()
+        self.labels.as_ref().map_or(self.inners[0].labels(), |l| l)
     }
 
     /// Returns the latest timestamp among the histograms in this aggregation.
@@ -163,11 +279,54 @@ impl AggregatedHistogram {
         )
     }
 
+    /// Returns the histograms which constitute this aggregation.
+    pub fn components(&self) -> &[Histogram] {
+        &self.inners
+    }
+
     /// Returns the cumulative buckets of this aggregation.
     pub fn cumulative_buckets(&self) -> AggregatedCumulativeBuckets {
         AggregatedCumulativeBuckets::new(&self.inners)
     }
 
+    /// Returns the upper bounds and the non-cumulative counts of the buckets of this aggregation.
+    ///
+    /// The result is derived from `cumulative_buckets`,
+    /// so each count is the number of the observations which fell between the previous bound and the bound.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metric::Metrics;
+    /// use prometrics::metrics::HistogramBuilder;
+    ///
+    /// let gatherer = Gatherer::new();
+    /// let histogram = HistogramBuilder::new("foo")
+    ///     .buckets(vec![1.0, 2.0])
+    ///     .registry(gatherer.registry())
+    ///     .finish()
+    ///     .unwrap();
+    /// histogram.observe(0.5);
+    /// histogram.observe(1.5);
+    /// histogram.observe(1.5);
+    ///
+    /// let families = gatherer.gather().into_vec();
+    /// if let Metrics::Histogram(ref v) = *families[0].metrics() {
+    ///     assert_eq!(v[0].bucket_counts(), [(1.0, 1), (2.0, 2), (std::f64::INFINITY, 0)]);
+    /// }
+    /// ```
+    pub fn bucket_counts(&self) -> Vec<(f64, u64)> {
+        let mut prev = 0;
+        self.cumulative_buckets()
+            .map(|b| {
+                let count = b.cumulative_count() - prev;
+                prev = b.cumulative_count();
+                (b.upper_bound(), count)
+            })
+            .collect()
+    }
+
     /// Returns the sum of the observation counts in this aggregation.
     pub fn count(&self) -> u64 {
         self.inners.iter().map(|h| h.count()).sum()
@@ -180,13 +339,21 @@ impl AggregatedHistogram {
 
     pub(crate) fn new(histogram: Histogram) -> Self {
         AggregatedHistogram {
+            name: histogram.metric_name().clone(),
             inners: vec![histogram],
+            labels: None,
         }
     }
 
+    pub(crate) fn set_metric_name(&mut self, name: MetricName) {
+        self.name = name;
+    }
+
     pub(crate) fn try_merge(&mut self, other: &Self) -> bool {
         let is_same_metric = self.metric_name() == other.metric_name()
-            && self.labels().iter().eq(other.labels().iter());
+            && self
+                .labels()
+                .with_labels(|x| other.labels().with_labels(|y| x.eq(y)));
         if is_same_metric {
             self.inners.extend_from_slice(&other.inners);
             true
@@ -195,37 +362,72 @@ impl AggregatedHistogram {
         }
     }
 }
-impl fmt::Display for AggregatedHistogram {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl AggregatedHistogram {
+    pub(crate) fn sorts_reserved_label(&self) -> bool {
+        self.inners[0].sorts_reserved_label()
+    }
+
+    // Takes a snapshot of each histogram in this aggregation, and merges them.
+    pub(crate) fn freeze(&self) -> FrozenHistogram {
+        let mut counts = Vec::new();
+        let mut sum = 0.0;
+        for histogram in &self.inners {
+            let snapshot = histogram.freeze();
+            let mut prev = 0;
+            for b in snapshot.buckets() {
+                counts.push((b.upper_bound(), b.cumulative_count() - prev));
+                prev = b.cumulative_count();
+            }
+            sum += snapshot.sum();
+        }
+        counts.sort_by(|a, b| a.0.partial_cmp(&b.0).expect("Never fails"));
+
+        let mut buckets: Vec<CumulativeBucket> = Vec::new();
+        let mut cumulative_count = 0;
+        for (upper_bound, count) in counts {
+            cumulative_count += count;
+            if buckets.last().map(|b| b.upper_bound()) == Some(upper_bound) {
+                buckets.pop();
+            }
+            buckets.push(CumulativeBucket::new(upper_bound, cumulative_count));
+        }
+        FrozenHistogram::new(buckets, sum)
+    }
+
+    pub(crate) fn write_text<W: fmt::Write>(
+        &self,
+        f: &mut W,
+        options: &TextOptions,
+    ) -> fmt::Result {
         let labels = if !self.labels().is_empty() {
             self.labels().to_string()
         } else {
             "".to_string()
         };
-        let timestamp = if let Some(t) = self.timestamp().get() {
-            format!(" {}", t)
-        } else {
-            "".to_string()
+        let timestamp = match self.timestamp().get() {
+            Some(t) if options.timestamps => format!(" {}", t),
+            _ => "".to_string(),
         };
 
-        for bucket in self.cumulative_buckets() {
-            write!(
-                f,
-                "{}_bucket{{le=\"{}\"",
-                self.metric_name(),
-                MetricValue(bucket.upper_bound())
-            )?;
-            for label in self.labels().iter() {
-                write!(f, ",{}={:?}", label.name(), label.value())?;
-            }
-            writeln!(f, "}} {}{}", bucket.cumulative_count(), timestamp)?;
+        let snapshot = self.freeze();
+        for bucket in snapshot.buckets() {
+            write!(f, "{}_bucket", self.metric_name())?;
+            self.labels().with_labels(|labels| {
+                write_labels_with_reserved(
+                    f,
+                    ("le", BucketBound(bucket.upper_bound())),
+                    labels,
+                    self.sorts_reserved_label(),
+                )
+            })?;
+            writeln!(f, " {}{}", bucket.cumulative_count(), timestamp)?;
         }
         writeln!(
             f,
             "{}_sum{} {}{}",
             self.metric_name(),
             labels,
-            MetricValue(self.sum()),
+            options.value(snapshot.sum()),
             timestamp
         )?;
         write!(
@@ -233,27 +435,49 @@ impl fmt::Display for AggregatedHistogram {
             "{}_count{} {}{}",
             self.metric_name(),
             labels,
-            self.count(),
+            snapshot.count(),
             timestamp
         )?;
         Ok(())
     }
 }
+impl Group for AggregatedHistogram {
+    fn labels(&self) -> &Labels {
+        AggregatedHistogram::labels(self)
+    }
+
+    fn retain_labels(&mut self, names: &[&str]) {
+        self.labels = Some(Arc::new(self.labels().retained(names)));
+    }
+
+    fn try_merge(&mut self, other: &Self) -> bool {
+        AggregatedHistogram::try_merge(self, other)
+    }
+}
+impl fmt::Display for AggregatedHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_text(f, &TextOptions::default())
+    }
+}
 
 /// A metric for aggregating summaries that have the same name and labels.
 #[derive(Debug, Clone)]
 pub struct AggregatedSummary {
+    name: MetricName,
     inners: Vec<Summary>,
+
+    // The labels which override the ones of the aggregated metrics (see `Gatherer::gather_grouped_by`).
+    labels: Option<Arc<Labels>>,
 }
 impl AggregatedSummary {
     /// Returns the name of this metric.
     pub fn metric_name(&self) -> &MetricName {
-        self.inners[0].metric_name()
+        &self.name
     }
 
     /// Returns the labels of this metric.
     pub fn labels(&self) -> &Labels {
-        self.inners[0].labels()
+        self.labels.as_ref().map_or(self.inners[0].labels(), |l| l)
     }
 
     /// Returns the latest timestamp among the summaries in this aggregation.
@@ -281,17 +505,18 @@ impl AggregatedSummary {
     pub fn quantiles(&self) -> Vec<(Quantile, f64)> {
         let mut aggregated_samples = Vec::new();
         for summary in &self.inners {
-            summary.with_current_samples(|_, samples| {
-                aggregated_samples.extend(samples.iter().map(|&(_, v)| v).filter(|v| !v.is_nan()));
-            });
+            aggregated_samples.extend(
+                summary
+                    .current_samples()
+                    .into_iter()
+                    .filter(|v| !v.is_nan()),
+            );
         }
         aggregated_samples.sort_by(|a, b| a.partial_cmp(b).expect("Never fails"));
 
         if aggregated_samples.is_empty() {
             return Vec::new();
         }
-        let count = aggregated_samples.len();
-
         let mut quantiles = self
             .inners
             .iter()
@@ -300,24 +525,33 @@ impl AggregatedSummary {
             .collect::<Vec<_>>();
         quantiles.sort();
         quantiles.dedup();
+        let interpolated = self.inners[0].interpolates();
         quantiles
             .iter()
             .map(|&quantile| {
-                let index = cmp::min(count, (quantile.as_f64() * count as f64).floor() as usize);
-                (quantile, aggregated_samples[index])
+                let value = quantile.value_of(&aggregated_samples, interpolated);
+                (quantile, value)
             })
             .collect()
     }
 
     pub(crate) fn new(summary: Summary) -> Self {
         AggregatedSummary {
+            name: summary.metric_name().clone(),
             inners: vec![summary],
+            labels: None,
         }
     }
 
+    pub(crate) fn set_metric_name(&mut self, name: MetricName) {
+        self.name = name;
+    }
+
     pub(crate) fn try_merge(&mut self, other: &Self) -> bool {
         let is_same_metric = self.metric_name() == other.metric_name()
-            && self.labels().iter().eq(other.labels().iter());
+            && self
+                .labels()
+                .with_labels(|x| other.labels().with_labels(|y| x.eq(y)));
         if is_same_metric {
             self.inners.extend_from_slice(&other.inners);
             true
@@ -326,37 +560,44 @@ impl AggregatedSummary {
         }
     }
 }
-impl fmt::Display for AggregatedSummary {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl AggregatedSummary {
+    pub(crate) fn sorts_reserved_label(&self) -> bool {
+        self.inners[0].sorts_reserved_label()
+    }
+
+    pub(crate) fn write_text<W: fmt::Write>(
+        &self,
+        f: &mut W,
+        options: &TextOptions,
+    ) -> fmt::Result {
         let labels = if !self.labels().is_empty() {
             self.labels().to_string()
         } else {
             "".to_string()
         };
-        let timestamp = if let Some(t) = self.timestamp().get() {
-            format!(" {}", t)
-        } else {
-            "".to_string()
+        let timestamp = match self.timestamp().get() {
+            Some(t) if options.timestamps => format!(" {}", t),
+            _ => "".to_string(),
         };
 
         for (quantile, value) in self.quantiles() {
-            write!(
-                f,
-                "{}{{quantile=\"{}\"",
-                self.metric_name(),
-                quantile.as_f64()
-            )?;
-            for label in self.labels().iter() {
-                write!(f, ",{}={:?}", label.name(), label.value())?;
-            }
-            writeln!(f, "}} {}{}", MetricValue(value), timestamp)?;
+            write!(f, "{}", self.metric_name())?;
+            self.labels().with_labels(|labels| {
+                write_labels_with_reserved(
+                    f,
+                    ("quantile", quantile),
+                    labels,
+                    self.sorts_reserved_label(),
+                )
+            })?;
+            writeln!(f, " {}{}", options.value(value), timestamp)?;
         }
         writeln!(
             f,
             "{}_sum{} {}{}",
             self.metric_name(),
             labels,
-            MetricValue(self.sum()),
+            options.value(self.sum()),
             timestamp
         )?;
         write!(
@@ -370,3 +611,21 @@ impl fmt::Display for AggregatedSummary {
         Ok(())
     }
 }
+impl Group for AggregatedSummary {
+    fn labels(&self) -> &Labels {
+        AggregatedSummary::labels(self)
+    }
+
+    fn retain_labels(&mut self, names: &[&str]) {
+        self.labels = Some(Arc::new(self.labels().retained(names)));
+    }
+
+    fn try_merge(&mut self, other: &Self) -> bool {
+        AggregatedSummary::try_merge(self, other)
+    }
+}
+impl fmt::Display for AggregatedSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_text(f, &TextOptions::default())
+    }
+}
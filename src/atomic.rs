@@ -1,6 +1,7 @@
 // so the API is "complete" even if not all functions are used
 #![allow(dead_code)]
 
+use std::fmt;
 use std::sync::atomic::{self, Ordering::Relaxed};
 
 #[derive(Debug)]
@@ -40,6 +41,10 @@ impl AtomicU64 {
     pub fn set(&self, v: u64) {
         self.0.store(v, Relaxed);
     }
+
+    pub fn swap(&self, v: u64) -> u64 {
+        self.0.swap(v, Relaxed)
+    }
 }
 
 #[derive(Debug)]
@@ -58,8 +63,8 @@ impl AtomicI64 {
         self.add(1);
     }
 
-    pub fn add(&self, v: i64) {
-        self.0.fetch_add(v, Relaxed);
+    pub fn add(&self, v: i64) -> i64 {
+        self.0.fetch_add(v, Relaxed).wrapping_add(v)
     }
 
     pub fn update<F>(&self, f: F)
@@ -82,7 +87,6 @@ impl AtomicI64 {
 }
 
 /// Add (and inc) is not a dedicated atomic instruction, use busy-loop
-#[derive(Debug)]
 pub struct AtomicF64(atomic::AtomicU64);
 
 impl AtomicF64 {
@@ -98,19 +102,23 @@ impl AtomicF64 {
         self.add(1.0);
     }
 
-    pub fn add(&self, v: f64) {
-        self.update(|old| old + v);
+    pub fn add(&self, v: f64) -> f64 {
+        self.update(|old| old + v)
     }
 
-    pub fn update<F>(&self, f: F)
+    /// Updates the value by `f` and returns the new value.
+    pub fn update<F>(&self, f: F) -> f64
     where
         F: Fn(f64) -> f64,
     {
         let mut old = self.0.load(Relaxed);
         loop {
-            let new = f(f64::from_bits(old)).to_bits();
-            match self.0.compare_exchange_weak(old, new, Relaxed, Relaxed) {
-                Ok(_) => break,
+            let new = f(f64::from_bits(old));
+            match self
+                .0
+                .compare_exchange_weak(old, new.to_bits(), Relaxed, Relaxed)
+            {
+                Ok(_) => return new,
                 Err(v) => old = v, // try again
             }
         }
@@ -119,6 +127,77 @@ impl AtomicF64 {
     pub fn set(&self, v: f64) {
         self.0.store(v.to_bits(), Relaxed);
     }
+
+    pub fn swap(&self, v: f64) -> f64 {
+        f64::from_bits(self.0.swap(v.to_bits(), Relaxed))
+    }
+}
+
+impl fmt::Debug for AtomicF64 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("AtomicF64").field(&self.get()).finish()
+    }
+}
+
+/// `f64` accumulator which uses compensated (Kahan-Babuska-Neumaier) summation
+///
+/// The rounding error of each addition is tracked in a separate compensation term,
+/// so long running sums do not drift as with naive summation.
+pub struct AtomicCompensatedF64 {
+    sum: atomic::AtomicU64,
+    compensation: AtomicF64,
+}
+
+impl AtomicCompensatedF64 {
+    pub fn new(v: f64) -> Self {
+        AtomicCompensatedF64 {
+            sum: v.to_bits().into(),
+            compensation: AtomicF64::new(0.0),
+        }
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.sum.load(Relaxed)) + self.compensation.get()
+    }
+
+    /// Returns the current value and resets this to `v`.
+    pub fn swap(&self, v: f64) -> f64 {
+        let sum = f64::from_bits(self.sum.swap(v.to_bits(), Relaxed));
+        sum + self.compensation.swap(0.0)
+    }
+
+    pub fn add(&self, v: f64) {
+        let mut old = self.sum.load(Relaxed);
+        loop {
+            let old_sum = f64::from_bits(old);
+            let new_sum = old_sum + v;
+            match self
+                .sum
+                .compare_exchange_weak(old, new_sum.to_bits(), Relaxed, Relaxed)
+            {
+                Ok(_) => {
+                    // The exact rounding error of the committed addition
+                    let error = if old_sum.abs() >= v.abs() {
+                        (old_sum - new_sum) + v
+                    } else {
+                        (v - new_sum) + old_sum
+                    };
+                    if error != 0.0 && error.is_finite() {
+                        self.compensation.add(error);
+                    }
+                    break;
+                }
+                Err(v) => old = v, // try again
+            }
+        }
+    }
+}
+impl fmt::Debug for AtomicCompensatedF64 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("AtomicCompensatedF64")
+            .field(&self.get())
+            .finish()
+    }
 }
 
 #[cfg(test)]
@@ -136,4 +215,33 @@ mod test {
         value.update(|v| v + 1.0);
         assert_eq!(value.get(), 123456790.0);
     }
+
+    #[test]
+    fn atomic_compensated_f64_works() {
+        let value = AtomicCompensatedF64::new(1e16);
+        let mut naive = 1e16;
+        for _ in 0..10_000 {
+            value.add(1.0);
+            naive += 1.0;
+        }
+        assert_eq!(naive, 1e16);
+        assert_eq!(value.get(), 1e16 + 10_000.0);
+
+        let value = AtomicCompensatedF64::new(0.0);
+        for _ in 0..1_000_000 {
+            value.add(0.1);
+        }
+        assert!((value.get() - 100_000.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn debug_shows_logical_values() {
+        assert_eq!(format!("{:?}", AtomicU64::new(3)), "AtomicU64(3)");
+        assert_eq!(format!("{:?}", AtomicI64::new(-3)), "AtomicI64(-3)");
+        assert_eq!(format!("{:?}", AtomicF64::new(1.5)), "AtomicF64(1.5)");
+        assert_eq!(
+            format!("{:?}", AtomicCompensatedF64::new(2.5)),
+            "AtomicCompensatedF64(2.5)"
+        );
+    }
 }
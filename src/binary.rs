@@ -0,0 +1,159 @@
+//! A compact binary encoding used for persisting snapshots of metrics.
+//!
+//! Integers and floats are encoded in little-endian, and strings are prefixed with their lengths.
+use std::str;
+
+use {ErrorKind, Result};
+
+#[derive(Debug, Default)]
+pub(crate) struct Encoder {
+    buf: Vec<u8>,
+}
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn put_u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn put_u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn put_u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn put_i64(&mut self, v: i64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn put_f64(&mut self, v: f64) {
+        self.put_u64(v.to_bits());
+    }
+
+    pub fn put_len(&mut self, len: usize) {
+        assert!(len <= u32::MAX as usize, "Too large: {}", len);
+        self.put_u32(len as u32);
+    }
+
+    pub fn put_str(&mut self, s: &str) {
+        self.put_len(s.len());
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+
+    pub fn put_option_str(&mut self, s: Option<&str>) {
+        if let Some(s) = s {
+            self.put_u8(1);
+            self.put_str(s);
+        } else {
+            self.put_u8(0);
+        }
+    }
+
+    pub fn put_option_i64(&mut self, v: Option<i64>) {
+        if let Some(v) = v {
+            self.put_u8(1);
+            self.put_i64(v);
+        } else {
+            self.put_u8(0);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Decoder<'a> {
+    buf: &'a [u8],
+}
+impl<'a> Decoder<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Decoder { buf }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'a [u8]> {
+        track_assert!(
+            size <= self.buf.len(),
+            ErrorKind::InvalidInput,
+            "Unexpected end of input: required={}, remaining={}",
+            size,
+            self.buf.len()
+        );
+        let (bytes, rest) = self.buf.split_at(size);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    pub fn get_u8(&mut self) -> Result<u8> {
+        let bytes = track!(self.take(1))?;
+        Ok(bytes[0])
+    }
+
+    pub fn get_u32(&mut self) -> Result<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(track!(self.take(4))?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn get_u64(&mut self) -> Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(track!(self.take(8))?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn get_i64(&mut self) -> Result<i64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(track!(self.take(8))?);
+        Ok(i64::from_le_bytes(bytes))
+    }
+
+    pub fn get_f64(&mut self) -> Result<f64> {
+        track!(self.get_u64()).map(f64::from_bits)
+    }
+
+    pub fn get_len(&mut self) -> Result<usize> {
+        track!(self.get_u32()).map(|n| n as usize)
+    }
+
+    pub fn get_bool(&mut self) -> Result<bool> {
+        match track!(self.get_u8())? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => track_panic!(ErrorKind::InvalidInput, "Unexpected flag: {}", b),
+        }
+    }
+
+    pub fn get_str(&mut self) -> Result<&'a str> {
+        let len = track!(self.get_len())?;
+        let bytes = track!(self.take(len))?;
+        match str::from_utf8(bytes) {
+            Ok(s) => Ok(s),
+            Err(e) => track_panic!(ErrorKind::InvalidInput, "Invalid UTF-8 string: {}", e),
+        }
+    }
+
+    pub fn get_option_str(&mut self) -> Result<Option<&'a str>> {
+        if track!(self.get_bool())? {
+            track!(self.get_str()).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn get_option_i64(&mut self) -> Result<Option<i64>> {
+        if track!(self.get_bool())? {
+            track!(self.get_i64()).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
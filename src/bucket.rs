@@ -46,15 +46,27 @@ impl Bucket {
     pub(crate) fn increment(&self) {
         self.count.inc();
     }
+
+    #[inline]
+    pub(crate) fn add(&self, count: u64) {
+        self.count.add(count);
+    }
 }
 
 /// Cumulative bucket.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct CumulativeBucket {
     cumulative_count: u64,
     upper_bound: f64,
 }
 impl CumulativeBucket {
+    pub(crate) fn new(upper_bound: f64, cumulative_count: u64) -> Self {
+        CumulativeBucket {
+            cumulative_count,
+            upper_bound,
+        }
+    }
+
     /// Returns the cumulative count of samples.
     pub fn cumulative_count(&self) -> u64 {
         self.cumulative_count
@@ -96,6 +108,10 @@ impl<'a> Iterator for CumulativeBuckets<'a> {
 }
 
 /// An iterator which iterates cumulative buckets in an aggregation of histograms.
+///
+/// The upper bounds of the resulting buckets are the union of those of the aggregated histograms.
+/// The cumulative count of each resulting bucket is the sum of the cumulative counts of
+/// the largest buckets, in each histogram, whose upper bounds are less than or equal to it.
 #[derive(Debug)]
 pub struct AggregatedCumulativeBuckets<'a> {
     cumulative_count: u64,
@@ -133,7 +149,7 @@ impl<'a> Iterator for AggregatedCumulativeBuckets<'a> {
 
         for buckets in &mut self.iters {
             let upper_bound = buckets.peek().expect("Never fails").upper_bound();
-            if min.is_infinite() || (upper_bound - min).abs() < std::f64::EPSILON {
+            if upper_bound == min {
                 let bucket = buckets.next().expect("Never fails");
                 self.cumulative_count += bucket.count();
             }
@@ -1,6 +1,33 @@
-use metric::Metric;
+use metric::{Metric, MetricKind, MetricName};
+
+/// A boxed iterator over collected metrics.
+pub type BoxedMetrics = Box<dyn Iterator<Item = Metric>>;
+
+/// A type-erased collector.
+///
+/// This is useful for handling heterogeneous collectors (e.g., `Vec<BoxedCollect>`).
+pub type BoxedCollect = Box<dyn Collect<Metrics = BoxedMetrics> + Send>;
 
 /// This trait allows for collecting metrics.
+///
+/// Closures of the form `FnMut() -> Option<impl Iterator<Item = Metric>>` also implement this trait,
+/// so ad-hoc collectors can be registered without defining a dedicated type.
+///
+/// # Examples
+///
+/// ```
+/// use std::iter;
+/// use prometrics::Gatherer;
+/// use prometrics::metrics::GaugeBuilder;
+///
+/// let gatherer = Gatherer::new();
+/// let gauge = GaugeBuilder::new("foo").finish().unwrap();
+/// gauge.set(1.0);
+///
+/// let cloned = gauge.clone();
+/// gatherer.registry().register(move || Some(iter::once(cloned.clone().into())));
+/// assert_eq!(gatherer.gather().to_text(), "# TYPE foo gauge\nfoo 1\n");
+/// ```
 pub trait Collect {
     /// An iterator over collected metrics.
     type Metrics: Iterator<Item = Metric>;
@@ -9,4 +36,82 @@ pub trait Collect {
     ///
     /// If there are no more metrics to collect, this method will return `None`.
     fn collect(&mut self) -> Option<Self::Metrics>;
+
+    /// Describes the metric families which this collector may produce.
+    ///
+    /// Each element is a tuple of the name, the kind and the help of a metric family.
+    /// `Gatherer` emits the `# HELP` and `# TYPE` lines of the described families
+    /// even when this collector momentarily produces no metrics belonging to them.
+    ///
+    /// The default implementation returns an empty vector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::iter;
+    /// use prometrics::{Collect, Gatherer};
+    /// use prometrics::metric::{Metric, MetricKind, MetricName};
+    ///
+    /// struct Idle;
+    /// impl Collect for Idle {
+    ///     type Metrics = iter::Empty<Metric>;
+    ///     fn collect(&mut self) -> Option<Self::Metrics> {
+    ///         Some(iter::empty())
+    ///     }
+    ///     fn describe(&self) -> Vec<(MetricName, MetricKind, Option<String>)> {
+    ///         let name = MetricName::new(None, None, "jobs").unwrap();
+    ///         vec![(name, MetricKind::Gauge, Some("Running jobs".to_owned()))]
+    ///     }
+    /// }
+    ///
+    /// let gatherer = Gatherer::new();
+    /// gatherer.registry().register(Idle);
+    /// assert_eq!(gatherer.gather().to_text(), "# HELP jobs Running jobs\n# TYPE jobs gauge\n");
+    /// ```
+    fn describe(&self) -> Vec<(MetricName, MetricKind, Option<String>)> {
+        Vec::new()
+    }
+
+    /// Converts this collector into a type-erased one.
+    fn boxed(self) -> BoxedCollect
+    where
+        Self: Sized + Send + 'static,
+        Self::Metrics: 'static,
+    {
+        Box::new(Boxed(self))
+    }
+}
+impl Collect for BoxedCollect {
+    type Metrics = BoxedMetrics;
+    fn collect(&mut self) -> Option<Self::Metrics> {
+        (**self).collect()
+    }
+    fn describe(&self) -> Vec<(MetricName, MetricKind, Option<String>)> {
+        (**self).describe()
+    }
+}
+impl<F, I> Collect for F
+where
+    F: FnMut() -> Option<I>,
+    I: Iterator<Item = Metric>,
+{
+    type Metrics = I;
+    fn collect(&mut self) -> Option<Self::Metrics> {
+        self()
+    }
+}
+
+struct Boxed<C>(C);
+impl<C> Collect for Boxed<C>
+where
+    C: Collect,
+    C::Metrics: 'static,
+{
+    type Metrics = BoxedMetrics;
+    fn collect(&mut self) -> Option<Self::Metrics> {
+        self.0.collect().map(|m| Box::new(m) as BoxedMetrics)
+    }
+    fn describe(&self) -> Vec<(MetricName, MetricKind, Option<String>)> {
+        self.0.describe()
+    }
 }
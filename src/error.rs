@@ -11,6 +11,9 @@ pub enum ErrorKind {
     /// Invalid input.
     InvalidInput,
 
+    /// A collector could not be registered (e.g., the gatherer of the registry has been dropped).
+    RegistrationFailed,
+
     /// Other error.
     Other,
 }
@@ -0,0 +1,525 @@
+use std;
+use std::fmt;
+
+use binary::{Decoder, Encoder};
+use label::Label;
+use metric::{
+    write_labels_with_reserved, BucketBound, MetricFamily, MetricKind, Metrics, TextOptions,
+    ValueFormatter,
+};
+use quantile::Quantile;
+use {ErrorKind, Result};
+
+// The version of the binary format produced by `FrozenMetricFamilies::to_bytes`.
+const BINARY_FORMAT_VERSION: u8 = 1;
+
+/// A point-in-time snapshot of `MetricFamilies`.
+///
+/// Unlike `MetricFamilies`, this holds plain copies of the values of the metrics
+/// rather than references to the live metrics, so it never changes after it is made
+/// and does not keep the underlying metrics alive.
+#[derive(Debug, Clone)]
+pub struct FrozenMetricFamilies(pub(crate) Vec<FrozenMetricFamily>);
+impl FrozenMetricFamilies {
+    /// Consumes the `FrozenMetricFamilies` and returns the underlying vector.
+    pub fn into_vec(self) -> Vec<FrozenMetricFamily> {
+        self.0
+    }
+
+    /// Converts to the text format.
+    pub fn to_text(&self) -> String {
+        self.to_text_with_options(&TextOptions::default())
+    }
+
+    /// Converts to the text format without the timestamps of the metrics.
+    pub fn to_text_without_timestamps(&self) -> String {
+        self.to_text_with_options(&TextOptions {
+            timestamps: false,
+            ..TextOptions::default()
+        })
+    }
+
+    /// Converts to the text format, rendering the sample values by `formatter`.
+    ///
+    /// See `ValueFormatter` for more details.
+    pub fn to_text_with_formatter(&self, formatter: &dyn ValueFormatter) -> String {
+        self.to_text_with_options(&TextOptions {
+            formatter,
+            ..TextOptions::default()
+        })
+    }
+
+    fn to_text_with_options(&self, options: &TextOptions) -> String {
+        let mut buf = String::new();
+        for m in &self.0 {
+            m.write_text(&mut buf, options).expect("Never fails");
+        }
+        buf
+    }
+
+    /// Encodes this snapshot into a compact binary format.
+    ///
+    /// This is intended for persisting the state of metrics locally (e.g., across restarts),
+    /// not for exposing them to Prometheus.
+    /// The encoding begins with a version byte, followed by length-prefixed little-endian fields
+    /// holding the names, helps, labels, timestamps and values (including buckets and quantiles)
+    /// of the metrics. Floating point values (including non-finite ones) are stored bit by bit.
+    ///
+    /// The result can be decoded by `FrozenMetricFamilies::from_bytes`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metric::FrozenMetricFamilies;
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let gatherer = Gatherer::new();
+    /// let counter = CounterBuilder::new("foo").registry(gatherer.registry()).finish().unwrap();
+    /// counter.add_u64(3);
+    ///
+    /// let bytes = gatherer.gather().freeze().to_bytes();
+    /// let restored = FrozenMetricFamilies::from_bytes(&bytes).unwrap();
+    /// assert_eq!(restored.to_text(), "# TYPE foo counter\nfoo 3\n");
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        encoder.put_u8(BINARY_FORMAT_VERSION);
+        encoder.put_len(self.0.len());
+        for family in &self.0 {
+            family.encode(&mut encoder);
+        }
+        encoder.into_bytes()
+    }
+
+    /// Decodes a snapshot encoded by `FrozenMetricFamilies::to_bytes`.
+    ///
+    /// # Errors
+    ///
+    /// If `bytes` is not a valid encoding (e.g., truncated),
+    /// or if it has been encoded in an unsupported version of the format,
+    /// this function returns `ErrorKind::InvalidInput` error.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut decoder = Decoder::new(bytes);
+        let version = track!(decoder.get_u8())?;
+        track_assert_eq!(
+            version,
+            BINARY_FORMAT_VERSION,
+            ErrorKind::InvalidInput,
+            "Unsupported version"
+        );
+        let len = track!(decoder.get_len())?;
+        let mut families = Vec::new();
+        for _ in 0..len {
+            families.push(track!(FrozenMetricFamily::decode(&mut decoder))?);
+        }
+        track_assert!(
+            decoder.is_empty(),
+            ErrorKind::InvalidInput,
+            "Unexpected trailing bytes"
+        );
+        Ok(FrozenMetricFamilies(families))
+    }
+}
+impl AsRef<[FrozenMetricFamily]> for FrozenMetricFamilies {
+    fn as_ref(&self) -> &[FrozenMetricFamily] {
+        &self.0
+    }
+}
+impl IntoIterator for FrozenMetricFamilies {
+    type Item = FrozenMetricFamily;
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// A point-in-time snapshot of a `MetricFamily`.
+#[derive(Debug, Clone)]
+pub struct FrozenMetricFamily {
+    name: String,
+    help: Option<String>,
+    kind: MetricKind,
+    metrics: Vec<FrozenMetric>,
+}
+impl FrozenMetricFamily {
+    /// Returns the name of this metric family.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the help of this metric family.
+    pub fn help(&self) -> Option<&str> {
+        self.help.as_ref().map(|h| h.as_ref())
+    }
+
+    /// Returns the kind of this metric family.
+    pub fn kind(&self) -> MetricKind {
+        self.kind
+    }
+
+    /// Returns the metrics that belongs to this family.
+    pub fn metrics(&self) -> &[FrozenMetric] {
+        &self.metrics
+    }
+
+    pub(crate) fn new(family: &MetricFamily) -> Self {
+        let metrics = match *family.metrics() {
+            Metrics::Counter(ref v) => v
+                .iter()
+                .map(|m| FrozenMetric {
+                    labels: m.labels().iter().cloned().collect(),
+                    timestamp: m.timestamp().get(),
+                    sort_reserved_label: false,
+                    value: FrozenValue::Counter(m.value()),
+                })
+                .collect(),
+            Metrics::Gauge(ref v) => v
+                .iter()
+                .map(|m| FrozenMetric {
+                    labels: m.labels().iter().cloned().collect(),
+                    timestamp: m.timestamp().get(),
+                    sort_reserved_label: false,
+                    value: FrozenValue::Gauge(m.value()),
+                })
+                .collect(),
+            Metrics::Summary(ref v) => v
+                .iter()
+                .map(|m| FrozenMetric {
+                    labels: m.labels().iter().cloned().collect(),
+                    timestamp: m.timestamp().get(),
+                    sort_reserved_label: m.sorts_reserved_label(),
+                    value: FrozenValue::Summary {
+                        quantiles: m.quantiles(),
+                        sum: m.sum(),
+                        count: m.count(),
+                    },
+                })
+                .collect(),
+            Metrics::Histogram(ref v) => v
+                .iter()
+                .map(|m| {
+                    let snapshot = m.freeze();
+                    FrozenMetric {
+                        labels: m.labels().iter().cloned().collect(),
+                        timestamp: m.timestamp().get(),
+                        sort_reserved_label: m.sorts_reserved_label(),
+                        value: FrozenValue::Histogram {
+                            buckets: snapshot
+                                .buckets()
+                                .iter()
+                                .map(|b| (b.upper_bound(), b.cumulative_count()))
+                                .collect(),
+                            sum: snapshot.sum(),
+                            count: snapshot.count(),
+                        },
+                    }
+                })
+                .collect(),
+        };
+        FrozenMetricFamily {
+            name: family.name().to_string(),
+            help: family.help().map(|h| h.to_string()),
+            kind: family.kind(),
+            metrics,
+        }
+    }
+
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.put_str(&self.name);
+        encoder.put_option_str(self.help());
+        encoder.put_u8(match self.kind {
+            MetricKind::Counter => 0,
+            MetricKind::Gauge => 1,
+            MetricKind::Summary => 2,
+            MetricKind::Histogram => 3,
+        });
+        encoder.put_len(self.metrics.len());
+        for m in &self.metrics {
+            m.encode(encoder);
+        }
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self> {
+        let name = track!(decoder.get_str())?.to_owned();
+        let help = track!(decoder.get_option_str())?.map(|h| h.to_owned());
+        let kind = match track!(decoder.get_u8())? {
+            0 => MetricKind::Counter,
+            1 => MetricKind::Gauge,
+            2 => MetricKind::Summary,
+            3 => MetricKind::Histogram,
+            k => track_panic!(ErrorKind::InvalidInput, "Unknown metric kind: {}", k),
+        };
+        let len = track!(decoder.get_len())?;
+        let mut metrics = Vec::new();
+        for _ in 0..len {
+            metrics.push(track!(FrozenMetric::decode(decoder, kind); name)?);
+        }
+        Ok(FrozenMetricFamily {
+            name,
+            help,
+            kind,
+            metrics,
+        })
+    }
+
+    fn write_text<W: fmt::Write>(&self, f: &mut W, options: &TextOptions) -> fmt::Result {
+        if let Some(help) = self.help() {
+            write!(f, "# HELP {} ", self.name)?;
+            for c in help.chars() {
+                match c {
+                    '\\' => write!(f, "\\\\")?,
+                    '\n' => write!(f, "\\\\n")?,
+                    _ => write!(f, "{}", c)?,
+                }
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "# TYPE {} {}", self.name, self.kind)?;
+        for m in &self.metrics {
+            m.write_text(&self.name, f, options)?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+impl fmt::Display for FrozenMetricFamily {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_text(f, &TextOptions::default())
+    }
+}
+
+/// A point-in-time snapshot of an aggregated metric.
+#[derive(Debug, Clone)]
+pub struct FrozenMetric {
+    labels: Vec<Label>,
+    timestamp: Option<i64>,
+    sort_reserved_label: bool,
+    value: FrozenValue,
+}
+impl FrozenMetric {
+    /// Returns the labels of this metric.
+    pub fn labels(&self) -> &[Label] {
+        &self.labels
+    }
+
+    /// Returns the timestamp of this metric.
+    pub fn timestamp(&self) -> Option<i64> {
+        self.timestamp
+    }
+
+    /// Returns the value of this metric.
+    pub fn value(&self) -> &FrozenValue {
+        &self.value
+    }
+
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.put_len(self.labels.len());
+        for label in &self.labels {
+            encoder.put_str(label.name());
+            encoder.put_str(label.value());
+        }
+        encoder.put_option_i64(self.timestamp);
+        encoder.put_u8(self.sort_reserved_label as u8);
+        match self.value {
+            FrozenValue::Counter(value) | FrozenValue::Gauge(value) => encoder.put_f64(value),
+            FrozenValue::Summary {
+                ref quantiles,
+                sum,
+                count,
+            } => {
+                encoder.put_len(quantiles.len());
+                for &(quantile, value) in quantiles {
+                    encoder.put_f64(quantile.as_f64());
+                    encoder.put_f64(quantile.error());
+                    encoder.put_f64(value);
+                }
+                encoder.put_f64(sum);
+                encoder.put_u64(count);
+            }
+            FrozenValue::Histogram {
+                ref buckets,
+                sum,
+                count,
+            } => {
+                encoder.put_len(buckets.len());
+                for &(upper_bound, cumulative_count) in buckets {
+                    encoder.put_f64(upper_bound);
+                    encoder.put_u64(cumulative_count);
+                }
+                encoder.put_f64(sum);
+                encoder.put_u64(count);
+            }
+        }
+    }
+
+    fn decode(decoder: &mut Decoder, kind: MetricKind) -> Result<Self> {
+        let len = track!(decoder.get_len())?;
+        let mut labels = Vec::new();
+        for _ in 0..len {
+            let name = track!(decoder.get_str())?;
+            let value = track!(decoder.get_str())?;
+            labels.push(track!(Label::new(name, value))?);
+        }
+        let timestamp = track!(decoder.get_option_i64())?;
+        let sort_reserved_label = track!(decoder.get_bool())?;
+        let value = match kind {
+            MetricKind::Counter => FrozenValue::Counter(track!(decoder.get_f64())?),
+            MetricKind::Gauge => FrozenValue::Gauge(track!(decoder.get_f64())?),
+            MetricKind::Summary => {
+                let len = track!(decoder.get_len())?;
+                let mut quantiles = Vec::new();
+                for _ in 0..len {
+                    let quantile = track!(decoder.get_f64())?;
+                    let error = track!(decoder.get_f64())?;
+                    let quantile = track!(Quantile::with_error(quantile, error))?;
+                    quantiles.push((quantile, track!(decoder.get_f64())?));
+                }
+                FrozenValue::Summary {
+                    quantiles,
+                    sum: track!(decoder.get_f64())?,
+                    count: track!(decoder.get_u64())?,
+                }
+            }
+            MetricKind::Histogram => {
+                let len = track!(decoder.get_len())?;
+                let mut buckets = Vec::new();
+                for _ in 0..len {
+                    let upper_bound = track!(decoder.get_f64())?;
+                    buckets.push((upper_bound, track!(decoder.get_u64())?));
+                }
+                FrozenValue::Histogram {
+                    buckets,
+                    sum: track!(decoder.get_f64())?,
+                    count: track!(decoder.get_u64())?,
+                }
+            }
+        };
+        Ok(FrozenMetric {
+            labels,
+            timestamp,
+            sort_reserved_label,
+            value,
+        })
+    }
+
+    fn write_text<W: fmt::Write>(
+        &self,
+        name: &str,
+        f: &mut W,
+        options: &TextOptions,
+    ) -> fmt::Result {
+        let mut labels = String::new();
+        if !self.labels.is_empty() {
+            labels.push('{');
+            for (i, label) in self.labels.iter().enumerate() {
+                if i != 0 {
+                    labels.push(',');
+                }
+                labels.push_str(&label.to_string());
+            }
+            labels.push('}');
+        }
+        let timestamp = match self.timestamp {
+            Some(t) if options.timestamps => format!(" {}", t),
+            _ => "".to_string(),
+        };
+
+        match self.value {
+            FrozenValue::Counter(value) | FrozenValue::Gauge(value) => {
+                write!(
+                    f,
+                    "{}{} {}{}",
+                    name,
+                    labels,
+                    options.value(value),
+                    timestamp
+                )
+            }
+            FrozenValue::Summary {
+                ref quantiles,
+                sum,
+                count,
+            } => {
+                for &(quantile, value) in quantiles {
+                    write!(f, "{}", name)?;
+                    write_labels_with_reserved(
+                        f,
+                        ("quantile", quantile),
+                        &self.labels,
+                        self.sort_reserved_label,
+                    )?;
+                    writeln!(f, " {}{}", options.value(value), timestamp)?;
+                }
+                writeln!(
+                    f,
+                    "{}_sum{} {}{}",
+                    name,
+                    labels,
+                    options.value(sum),
+                    timestamp
+                )?;
+                write!(f, "{}_count{} {}{}", name, labels, count, timestamp)
+            }
+            FrozenValue::Histogram {
+                ref buckets,
+                sum,
+                count,
+            } => {
+                for &(upper_bound, cumulative_count) in buckets {
+                    write!(f, "{}_bucket", name)?;
+                    write_labels_with_reserved(
+                        f,
+                        ("le", BucketBound(upper_bound)),
+                        &self.labels,
+                        self.sort_reserved_label,
+                    )?;
+                    writeln!(f, " {}{}", cumulative_count, timestamp)?;
+                }
+                writeln!(
+                    f,
+                    "{}_sum{} {}{}",
+                    name,
+                    labels,
+                    options.value(sum),
+                    timestamp
+                )?;
+                write!(f, "{}_count{} {}{}", name, labels, count, timestamp)
+            }
+        }
+    }
+}
+
+/// The value of a `FrozenMetric`.
+#[derive(Debug, Clone)]
+pub enum FrozenValue {
+    /// The value of a counter.
+    Counter(f64),
+
+    /// The value of a gauge.
+    Gauge(f64),
+
+    /// The values of a summary.
+    Summary {
+        /// The quantile-value pairs.
+        quantiles: Vec<(Quantile, f64)>,
+
+        /// The sum of the observed values.
+        sum: f64,
+
+        /// The observation count.
+        count: u64,
+    },
+
+    /// The values of a histogram.
+    Histogram {
+        /// The upper bounds and the cumulative counts of the buckets.
+        buckets: Vec<(f64, u64)>,
+
+        /// The sum of the observed values.
+        sum: f64,
+
+        /// The observation count.
+        count: u64,
+    },
+}
@@ -5,11 +5,14 @@
 //! - [Data model](https://prometheus.io/docs/concepts/data_model/)
 //! - [Metric and label naming](https://prometheus.io/docs/practices/naming/)
 use atomic_immut::AtomicImmut;
+#[cfg(feature = "smallvec")]
+use smallvec::SmallVec;
 use std;
+use std::convert::TryFrom;
 use std::fmt;
 use std::ops::Deref;
 
-use {ErrorKind, Result};
+use {Error, ErrorKind, Result};
 
 /// Metric label.
 ///
@@ -19,7 +22,7 @@ use {ErrorKind, Result};
 /// They must match the regex `[a-zA-Z_][a-zA-Z0-9_]*`.
 /// Label names beginning with `__` are reserved for internal use.
 ///
-/// Label values may contain any Unicode (utf-8) characters.
+/// Label values may contain any Unicode (utf-8) characters except NUL (`\0`) and carriage return (`\r`).
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Label {
     name: String,
@@ -30,7 +33,8 @@ impl Label {
     ///
     /// # Errors
     ///
-    /// If `name` contains invalid characters, this function returns `ErrorKind::InvalidInput` error.
+    /// If `name` or `value` contains invalid characters,
+    /// this function returns `ErrorKind::InvalidInput` error.
     ///
     /// # Examples
     ///
@@ -50,6 +54,15 @@ impl Label {
     /// // Invalid name
     /// assert_eq!(Label::new("fo-o", "bar").err().map(|e| *e.kind()),
     ///            Some(ErrorKind::InvalidInput));
+    ///
+    /// // Invalid values
+    /// assert_eq!(Label::new("foo", "b\0ar").err().map(|e| *e.kind()),
+    ///            Some(ErrorKind::InvalidInput));
+    /// assert_eq!(Label::new("foo", "b\rar").err().map(|e| *e.kind()),
+    ///            Some(ErrorKind::InvalidInput));
+    ///
+    /// // Characters which will be escaped are allowed
+    /// assert!(Label::new("foo", "\"b\\a\nr\"").is_ok());
     /// ```
     pub fn new(name: &str, value: &str) -> Result<Self> {
         track!(
@@ -58,6 +71,53 @@ impl Label {
             name,
             value
         )?;
+        track!(
+            Self::validate_value(value),
+            "name={:?}, value={:?}",
+            name,
+            value
+        )?;
+        Ok(Label {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    /// Makes a new `Label` instance which may have a reserved name (e.g., `__name__`).
+    ///
+    /// This is intended for producing output that is consumed by other Prometheus components
+    /// (e.g., federation), and is only available if the `reserved-labels` feature is enabled.
+    /// Except for the `__` prefix, `name` and `value` are validated as `Label::new` does.
+    ///
+    /// # Errors
+    ///
+    /// If `name` or `value` contains invalid characters,
+    /// this function returns `ErrorKind::InvalidInput` error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::label::Label;
+    ///
+    /// let label = Label::new_unchecked_reserved("__name__", "foo").unwrap();
+    /// assert_eq!(label.to_string(), r#"__name__="foo""#);
+    ///
+    /// assert!(Label::new_unchecked_reserved("__fo-o", "bar").is_err());
+    /// ```
+    #[cfg(feature = "reserved-labels")]
+    pub fn new_unchecked_reserved(name: &str, value: &str) -> Result<Self> {
+        track!(
+            Self::validate_name_chars(name),
+            "name={:?}, value={:?}",
+            name,
+            value
+        )?;
+        track!(
+            Self::validate_value(value),
+            "name={:?}, value={:?}",
+            name,
+            value
+        )?;
         Ok(Label {
             name: name.to_string(),
             value: value.to_string(),
@@ -75,9 +135,13 @@ impl Label {
     }
 
     fn validate_name(name: &str) -> Result<()> {
+        track_assert!(!name.starts_with("__"), ErrorKind::InvalidInput, "Reserved");
+        track!(Self::validate_name_chars(name))
+    }
+
+    fn validate_name_chars(name: &str) -> Result<()> {
         // REGEX: [a-zA-Z_][a-zA-Z0-9_]*
         track_assert!(!name.is_empty(), ErrorKind::InvalidInput);
-        track_assert!(!name.starts_with("__"), ErrorKind::InvalidInput, "Reserved");
         match name.as_bytes()[0] as char {
             'a'..='z' | 'A'..='Z' | '_' => {}
             _ => track_panic!(ErrorKind::InvalidInput),
@@ -90,32 +154,114 @@ impl Label {
         }
         Ok(())
     }
+
+    fn validate_value(value: &str) -> Result<()> {
+        for c in value.chars() {
+            match c {
+                '\0' | '\r' => track_panic!(ErrorKind::InvalidInput, "Control character"),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+impl<'a, 'b> TryFrom<(&'a str, &'b str)> for Label {
+    type Error = Error;
+
+    /// Makes a new `Label` instance from the name-value pair.
+    ///
+    /// This is equivalent to `Label::new(name, value)`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryFrom;
+    /// use prometrics::label::Label;
+    ///
+    /// let label = Label::try_from(("foo", "bar")).unwrap();
+    /// assert_eq!(label.to_string(), r#"foo="bar""#);
+    ///
+    /// let labels = vec![("foo", "bar"), ("baz", "qux")]
+    ///     .into_iter()
+    ///     .map(Label::try_from)
+    ///     .collect::<Result<Vec<_>, _>>()
+    ///     .unwrap();
+    /// assert_eq!(labels.len(), 2);
+    ///
+    /// assert!(Label::try_from(("__foo", "bar")).is_err());
+    /// ```
+    fn try_from((name, value): (&'a str, &'b str)) -> Result<Self> {
+        track!(Label::new(name, value))
+    }
+}
+impl TryFrom<(String, String)> for Label {
+    type Error = Error;
+
+    /// Makes a new `Label` instance from the name-value pair.
+    ///
+    /// This is equivalent to `Label::new(&name, &value)`.
+    fn try_from((name, value): (String, String)) -> Result<Self> {
+        track!(Label::new(&name, &value))
+    }
 }
 impl fmt::Display for Label {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        // > `label_value` can be any sequence of UTF-8 characters,
-        // > but the backslash, the double-quote, and the line-feed
-        // > characters have to be escaped as `\\`, `\"`, and `\n`, respectively.
         write!(f, "{}=\"", self.name)?;
-        for c in self.value.chars() {
-            match c {
-                '\\' => write!(f, "\\\\")?,
-                '\n' => write!(f, "\\\\n")?,
-                '"' => write!(f, "\\\"")?,
-                _ => write!(f, "{}", c)?,
-            }
-        }
+        escape_value(&self.value, f)?;
         write!(f, "\"")
     }
 }
 
+/// Writes `value` to `out` escaping it as a label value.
+///
+/// > `label_value` can be any sequence of UTF-8 characters,
+/// > but the backslash, the double-quote, and the line-feed
+/// > characters have to be escaped as `\\`, `\"`, and `\n`, respectively.
+///
+/// # Examples
+///
+/// ```
+/// use prometrics::label::escape_value;
+///
+/// let mut s = String::new();
+/// escape_value("a\\b\"c\nd", &mut s).unwrap();
+/// assert_eq!(s, r#"a\\b\"c\nd"#);
+/// ```
+pub fn escape_value<W: fmt::Write>(value: &str, out: &mut W) -> fmt::Result {
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        let escaped = match c {
+            '\\' => "\\\\",
+            '"' => "\\\"",
+            '\n' => "\\n",
+            _ => continue,
+        };
+        out.write_str(&value[start..i])?;
+        out.write_str(escaped)?;
+        start = i + 1;
+    }
+    out.write_str(&value[start..])
+}
+
+// Most metrics have only a few labels, so they are stored inline if the `smallvec` feature is enabled.
+#[cfg(feature = "smallvec")]
+pub(crate) type LabelVec = SmallVec<[Label; 4]>;
+#[cfg(not(feature = "smallvec"))]
+pub(crate) type LabelVec = Vec<Label>;
+
 /// A map of labels (i.e., key-value pairs).
+///
+/// Labels are sorted by name unless the metric was built with `preserve_label_order`,
+/// in which case they are kept in insertion order.
 #[derive(Debug)]
-pub struct Labels(AtomicImmut<Vec<Label>>);
+pub struct Labels {
+    set: AtomicImmut<LabelSet>,
+    preserve_order: bool,
+}
 impl Labels {
     /// Returns the number of labels contained in this map.
     pub fn len(&self) -> usize {
-        self.0.load().len()
+        self.set.load().labels.len()
     }
 
     /// Returns `true` if this map has no labels, otherwise `false`.
@@ -128,28 +274,167 @@ impl Labels {
         self.iter().find(|l| l.name() == name)
     }
 
+    /// Returns the value of the label which has the name `name`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::label::Labels;
+    ///
+    /// let labels = Labels::from_pairs(vec![("foo", "bar")]).unwrap();
+    /// assert_eq!(labels.get_value("foo"), Some("bar".to_owned()));
+    /// assert_eq!(labels.get_value("baz"), None);
+    /// assert!(labels.contains("foo"));
+    /// assert!(!labels.contains("baz"));
+    /// ```
+    pub fn get_value(&self, name: &str) -> Option<String> {
+        self.with_labels(|labels| {
+            labels
+                .iter()
+                .find(|l| l.name() == name)
+                .map(|l| l.value().to_owned())
+        })
+    }
+
+    /// Returns `true` if this map has the label which has the name `name`, otherwise `false`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.with_labels(|labels| labels.iter().any(|l| l.name() == name))
+    }
+
     /// Returns an iterator which visiting all labels in this map.
+    ///
+    /// The yielded references must not be kept after the iterator is dropped,
+    /// because the labels may be modified (and the visited ones freed) through another handle
+    /// of the same metric. Use `with_labels` to read the labels without such a restriction.
     pub fn iter(&self) -> Iter {
-        let labels = self.0.load();
-        let inner = unsafe { std::mem::transmute(labels.iter()) };
+        let labels = self.set.load();
+        // NOTE: The yielded references are only valid while `Iter` holds the snapshot `labels`,
+        // but the `Iterator` trait cannot tie them to the iterator itself, so the lifetime is
+        // extended to that of `self`. This crate reads labels via `with_labels` instead.
+        let inner = unsafe {
+            std::mem::transmute::<std::slice::Iter<'_, Label>, std::slice::Iter<'_, Label>>(
+                labels.labels.iter(),
+            )
+        };
         Iter { labels, inner }
     }
 
-    pub(crate) fn new(labels: Vec<Label>) -> Self {
-        Labels(AtomicImmut::new(labels))
+    /// Invokes `f` with the slice of all labels in this map, and returns its result.
+    ///
+    /// The labels are in the same order as `iter` visits them.
+    /// Unlike `iter`, this does not construct an iterator holding the current snapshot of the labels,
+    /// so it is slightly cheaper when reading the labels of many metrics.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::label::Labels;
+    ///
+    /// let labels = Labels::from_pairs(vec![("foo", "bar"), ("baz", "qux")]).unwrap();
+    /// let names = labels.with_labels(|l| l.iter().map(|l| l.name().to_owned()).collect::<Vec<_>>());
+    /// assert_eq!(names, ["baz", "foo"]);
+    /// ```
+    pub fn with_labels<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&[Label]) -> R,
+    {
+        f(&self.set.load().labels)
+    }
+
+    /// Makes a new `Labels` instance from the given name-value pairs.
+    ///
+    /// If there are multiple pairs which have the same name, the last one is adopted.
+    ///
+    /// # Errors
+    ///
+    /// If any of the pairs is an invalid label, this function returns `ErrorKind::InvalidInput` error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use prometrics::label::Labels;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert("foo", "bar");
+    /// map.insert("baz", "qux");
+    ///
+    /// let labels = Labels::from_pairs(&map).unwrap();
+    /// assert_eq!(labels.to_string(), r#"{baz="qux",foo="bar"}"#);
+    /// ```
+    pub fn from_pairs<I, K, V>(pairs: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut labels = LabelVec::new();
+        for (name, value) in pairs {
+            let label = track!(Label::new(name.as_ref(), value.as_ref()))?;
+            labels.retain(|l| l.name != label.name);
+            labels.push(label);
+        }
+        labels.sort();
+        Ok(Labels::new(labels, false))
+    }
+
+    // Returns a copy of this map which only contains the labels named in `names`.
+    pub(crate) fn retained(&self, names: &[&str]) -> Self {
+        let labels = self.with_labels(|labels| {
+            labels
+                .iter()
+                .filter(|l| names.contains(&l.name()))
+                .cloned()
+                .collect()
+        });
+        Labels::new(labels, self.preserve_order)
+    }
+
+    // Returns a copy of this map which is not affected by the subsequent modifications of this.
+    //
+    // The current snapshot is copied as is, so its rendered text is reused.
+    pub(crate) fn detached(&self) -> Self {
+        Labels {
+            set: AtomicImmut::new(LabelSet::clone(&self.set.load())),
+            preserve_order: self.preserve_order,
+        }
+    }
+
+    pub(crate) fn new(labels: LabelVec, preserve_order: bool) -> Self {
+        Labels {
+            set: AtomicImmut::new(LabelSet::new(labels)),
+            preserve_order,
+        }
     }
 }
 impl fmt::Display for Labels {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{{")?;
-        for (i, label) in self.iter().enumerate() {
+        f.write_str(&self.set.load().text)
+    }
+}
+
+/// Labels and their rendered form (e.g., `{foo="bar",baz="qux"}`).
+///
+/// The text is rendered once per modification rather than every time the labels are formatted.
+#[derive(Debug, Clone)]
+struct LabelSet {
+    labels: LabelVec,
+    text: String,
+}
+impl LabelSet {
+    fn new(labels: LabelVec) -> Self {
+        use std::fmt::Write;
+
+        let mut text = String::new();
+        text.push('{');
+        for (i, label) in labels.iter().enumerate() {
             if i != 0 {
-                write!(f, ",")?;
+                text.push(',');
             }
-            write!(f, "{}", label)?;
+            write!(text, "{}", label).expect("Never fails");
         }
-        write!(f, "}}")?;
-        Ok(())
+        text.push('}');
+        LabelSet { labels, text }
     }
 }
 
@@ -168,26 +453,41 @@ impl<'a> LabelsMut<'a> {
             ErrorKind::InvalidInput
         );
         let label = track!(Label::new(name, value))?;
-        self.inner.0.update(move |labels| {
-            let mut labels = labels.clone();
-            labels.retain(|l| l.name != label.name);
-            labels.push(label.clone());
-            labels.sort();
-            labels
+        let preserve_order = self.inner.preserve_order;
+        self.inner.set.update(move |set| {
+            let mut labels = set.labels.clone();
+            if preserve_order {
+                if let Some(l) = labels.iter_mut().find(|l| l.name == label.name) {
+                    l.value = label.value.clone();
+                } else {
+                    labels.push(label.clone());
+                }
+            } else {
+                labels.retain(|l| l.name != label.name);
+                labels.push(label.clone());
+                labels.sort();
+            }
+            LabelSet::new(labels)
         });
         Ok(())
     }
 
     /// Removes the label which has the name `name` if it exists.
     pub fn remove(&mut self, name: &str) {
-        self.inner
-            .0
-            .update(|labels| labels.iter().filter(|l| l.name != name).cloned().collect());
+        self.inner.set.update(|set| {
+            LabelSet::new(
+                set.labels
+                    .iter()
+                    .filter(|l| l.name != name)
+                    .cloned()
+                    .collect(),
+            )
+        });
     }
 
     /// Clears the all labels.
     pub fn clear(&mut self) {
-        self.inner.0.store(Vec::new());
+        self.inner.set.store(LabelSet::new(LabelVec::new()));
     }
 
     pub(crate) fn new(labels: &'a Labels, reserved: Option<&'static str>) -> Self {
@@ -207,7 +507,7 @@ impl<'a> Deref for LabelsMut<'a> {
 /// An iterator over the labels of a `Labels`.
 #[derive(Debug)]
 pub struct Iter<'a> {
-    labels: std::sync::Arc<Vec<Label>>,
+    labels: std::sync::Arc<LabelSet>,
     inner: std::slice::Iter<'a, Label>,
 }
 impl<'a> Iterator for Iter<'a> {
@@ -216,3 +516,45 @@ impl<'a> Iterator for Iter<'a> {
         self.inner.next()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn escape(value: &str) -> String {
+        let mut s = String::new();
+        escape_value(value, &mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn escape_value_works() {
+        assert_eq!(escape(""), "");
+        assert_eq!(escape("foo"), "foo");
+        assert_eq!(escape("a\\b"), r"a\\b");
+        assert_eq!(escape("a\"b"), r#"a\"b"#);
+        assert_eq!(escape("a\nb"), r"a\nb");
+        assert_eq!(escape("\\\"\n"), r#"\\\"\n"#);
+        assert_eq!(escape("\tあ"), "\tあ");
+    }
+
+    #[test]
+    fn label_display_escapes_value() {
+        let label = Label::new("foo", "a\nb").unwrap();
+        assert_eq!(label.to_string(), r#"foo="a\nb""#);
+
+        let labels = Labels::from_pairs(vec![("foo", "a\"b")]).unwrap();
+        assert_eq!(labels.to_string(), r#"{foo="a\"b"}"#);
+    }
+
+    #[test]
+    fn with_labels_works() {
+        let labels = Labels::from_pairs(vec![("foo", "1"), ("bar", "2")]).unwrap();
+        labels.with_labels(|l| assert!(l.iter().eq(labels.iter())));
+
+        LabelsMut::new(&labels, None).insert("baz", "3").unwrap();
+        let names =
+            labels.with_labels(|l| l.iter().map(|l| l.name().to_owned()).collect::<Vec<_>>());
+        assert_eq!(names, ["bar", "baz", "foo"]);
+    }
+}
@@ -7,6 +7,8 @@
 //! # Examples
 //!
 //! ```
+//! # #[cfg(not(feature = "no-default-registry"))]
+//! # fn main() {
 //! use prometrics::default_gatherer;
 //! use prometrics::metrics::{CounterBuilder, GaugeBuilder};
 //!
@@ -23,7 +25,7 @@
 //!  counter.increment();
 //!  gauge.set(12.3);
 //!
-//!  let metrics = default_gatherer().lock().unwrap().gather();
+//!  let metrics = default_gatherer().read().unwrap().gather();
 //!  assert_eq!(
 //!     metrics.to_text(),
 //!     format!("{}\n{}\n{}\n{}\n",
@@ -31,6 +33,9 @@
 //!             "count 1",
 //!             "# TYPE gauge gauge",
 //!             "gauge{foo=\"bar\"} 12.3"));
+//! # }
+//! # #[cfg(feature = "no-default-registry")]
+//! # fn main() {}
 //! ```
 //!
 //! # References
@@ -47,12 +52,19 @@ extern crate lazy_static;
 extern crate libc;
 #[cfg(target_os = "linux")]
 extern crate procinfo;
+#[cfg(feature = "smallvec")]
+extern crate smallvec;
 #[macro_use]
 extern crate trackable;
 
-pub use collect::Collect;
+#[macro_use]
+mod macros;
+
+pub use collect::{BoxedCollect, BoxedMetrics, Collect};
 pub use error::{Error, ErrorKind};
-pub use registry::{default_gatherer, default_registry, Gatherer, Registry};
+#[cfg(not(feature = "no-default-registry"))]
+pub use registry::{default_gatherer, default_registry};
+pub use registry::{Gatherer, Lint, Registry, ScopedRegistry};
 
 pub mod bucket;
 pub mod label;
@@ -63,8 +75,10 @@ pub mod timestamp;
 
 mod aggregated_metrics;
 mod atomic;
+mod binary;
 mod collect;
 mod error;
+mod frozen_metrics;
 mod registry;
 
 /// This crate specific `Result` type.
@@ -73,10 +87,12 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[cfg(test)]
 mod test {
     use super::*;
+    use metric::{MetricKind, MetricName};
     use metrics::{CounterBuilder, GaugeBuilder, MetricBuilder};
     use registry::Gatherer;
 
     #[test]
+    #[cfg(not(feature = "no-default-registry"))]
     fn it_works() {
         let counter = CounterBuilder::new("count")
             .default_registry()
@@ -91,7 +107,7 @@ mod test {
         counter.increment();
         gauge.set(12.3);
 
-        let metrics = default_gatherer().lock().unwrap().gather();
+        let metrics = default_gatherer().read().unwrap().gather();
         assert_eq!(
             format!("\n{}", metrics.to_text()),
             r#"
@@ -105,8 +121,8 @@ gauge{foo="bar"} 12.3
 
     #[test]
     fn aggregation_works() {
-        let mut gatherer = Gatherer::new();
-        let mut builder = MetricBuilder::new();
+        let gatherer = Gatherer::new();
+        let mut builder = MetricBuilder::without_registry();
         builder.set_registry(gatherer.registry());
 
         let counter = builder.counter("count").finish().unwrap();
@@ -148,4 +164,654 @@ histogram_count 2
 "#
         );
     }
+
+    #[test]
+    fn integer_gauge_aggregation_works() {
+        let gatherer = Gatherer::new();
+        let mut builder = MetricBuilder::without_registry();
+        builder.set_registry(gatherer.registry());
+
+        let gauge0 = builder.gauge("int").integer().finish().unwrap();
+        let gauge1 = builder.gauge("int").integer().finish().unwrap();
+        gauge0.set_i64(1 << 53);
+        gauge1.set_i64(1);
+
+        let gauge2 = builder.gauge("mixed").integer().finish().unwrap();
+        let gauge3 = builder.gauge("mixed").finish().unwrap();
+        gauge2.set_i64(1);
+        gauge3.set(0.5);
+
+        assert_eq!(
+            gatherer.gather().to_text(),
+            "# TYPE int gauge\nint 9007199254740993\n# TYPE mixed gauge\nmixed 1.5\n"
+        );
+    }
+
+    #[test]
+    fn metric_names_works() {
+        let mut gatherer = Gatherer::new();
+        gatherer.set_global_namespace("app").unwrap();
+        let counter = CounterBuilder::new("foo")
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        let _gauge = GaugeBuilder::new("foo")
+            .label("bar", "baz")
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        let _another_gauge = GaugeBuilder::new("foo")
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+
+        let names = gatherer.metric_names();
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[0].0.to_string(), "app_foo");
+        assert_eq!(names[0].1, MetricKind::Counter);
+        assert_eq!(names[1].1, MetricKind::Gauge);
+
+        std::mem::drop(counter);
+        let names = gatherer.metric_names();
+        assert_eq!(names.len(), 1);
+        assert_eq!(names[0].1, MetricKind::Gauge);
+    }
+
+    #[test]
+    fn metric_names_prunes_dead_describing_collectors() {
+        use metric::{Metric, MetricName};
+        use std::sync::{Arc, Weak};
+
+        struct Described(Weak<()>);
+        impl Collect for Described {
+            type Metrics = std::iter::Empty<Metric>;
+            fn collect(&mut self) -> Option<Self::Metrics> {
+                self.0.upgrade().map(|_| std::iter::empty())
+            }
+            fn describe(&self) -> Vec<(MetricName, MetricKind, Option<String>)> {
+                let name = MetricName::new(None, None, "foo").unwrap();
+                vec![(name, MetricKind::Counter, None)]
+            }
+        }
+
+        let gatherer = Gatherer::new();
+        let alive = Arc::new(());
+        gatherer
+            .registry()
+            .register(Described(Arc::downgrade(&alive)));
+        assert_eq!(gatherer.metric_names().len(), 1);
+
+        std::mem::drop(alive);
+        assert!(gatherer.metric_names().is_empty());
+        assert_eq!(gatherer.gather().to_text(), "");
+    }
+
+    #[test]
+    fn gather_and_reset_works() {
+        let gatherer = Gatherer::new();
+        let counter = CounterBuilder::new("foo")
+            .reset_on_collect()
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+
+        counter.add_u64(2);
+        let names = gatherer.metric_names();
+        assert_eq!(names.len(), 1);
+        assert_eq!(names[0].1, MetricKind::Counter);
+        assert_eq!(gatherer.gather().to_text(), "# TYPE foo counter\nfoo 2\n");
+        assert_eq!(counter.value(), 2.0);
+
+        assert_eq!(
+            gatherer.gather_and_reset().to_text(),
+            "# TYPE foo counter\nfoo 2\n"
+        );
+        assert_eq!(counter.value(), 0.0);
+
+        std::mem::drop(counter);
+        assert!(gatherer.metric_names().is_empty());
+        assert_eq!(gatherer.gather_and_reset().to_text(), "");
+    }
+
+    #[test]
+    fn suppress_zero_works() {
+        use metrics::{HistogramBuilder, SummaryBuilder};
+
+        let mut gatherer = Gatherer::new();
+        gatherer.set_suppress_zero(true);
+        let counter = CounterBuilder::new("counter")
+            .reset_on_collect()
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        let gauge = GaugeBuilder::new("gauge")
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        let _initialized = GaugeBuilder::new("initialized")
+            .initial_value(1.0)
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        let histogram = HistogramBuilder::new("histogram")
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        let _summary = SummaryBuilder::new("summary", std::time::Duration::from_secs(60))
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        assert_eq!(
+            gatherer.gather().to_text(),
+            "# TYPE initialized gauge\ninitialized 1\n"
+        );
+
+        counter.increment();
+        gauge.set(0.0);
+        histogram.observe(1.0);
+        let names = gatherer
+            .gather()
+            .into_vec()
+            .into_iter()
+            .map(|f| f.name().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["counter", "gauge", "histogram", "initialized"]);
+
+        // The counter has been reset to zero, but it is kept for the continuity.
+        assert!(gatherer
+            .gather_and_reset()
+            .to_text()
+            .contains("\ncounter 1\n"));
+        assert!(gatherer.gather().to_text().contains("\ncounter 0\n"));
+
+        gatherer.set_suppress_zero(false);
+        assert_eq!(gatherer.gather().into_vec().len(), 5);
+    }
+
+    #[test]
+    fn self_metrics_works() {
+        let mut gatherer = Gatherer::new();
+        assert_eq!(gatherer.gather().to_text(), "");
+
+        gatherer.set_self_metrics(true);
+        let _foo = CounterBuilder::new("foo")
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        let families = gatherer.gather().into_vec();
+        assert_eq!(families.len(), 3);
+        assert_eq!(
+            families[1].name().to_string(),
+            "prometrics_gather_duration_seconds"
+        );
+        assert!(families[1].to_string().ends_with(" 0\n"));
+        assert_eq!(families[2].name().to_string(), "prometrics_gather_series");
+        assert!(families[2]
+            .to_string()
+            .ends_with("prometrics_gather_series 0\n"));
+
+        let families = gatherer.gather().into_vec();
+        assert!(families[2]
+            .to_string()
+            .ends_with("prometrics_gather_series 1\n"));
+
+        gatherer.set_self_metrics(false);
+        assert_eq!(gatherer.gather().into_vec().len(), 1);
+    }
+
+    #[test]
+    fn self_metrics_count_merged_series_once() {
+        let mut gatherer = Gatherer::new();
+        gatherer.set_self_metrics(true);
+        let _foo0 = CounterBuilder::new("foo")
+            .label("bar", "baz")
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        let _foo1 = CounterBuilder::new("foo")
+            .label("bar", "baz")
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+
+        gatherer.gather();
+        let text = gatherer.gather().to_text();
+        assert!(text.contains("\nfoo{bar=\"baz\"} 0\n"));
+        assert!(text.contains("\nprometrics_gather_series 1\n"));
+    }
+
+    #[test]
+    fn hooks_survive_panics() {
+        use std::panic::{self, AssertUnwindSafe};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let mut gatherer = Gatherer::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cloned = Arc::clone(&calls);
+        gatherer.on_before_gather(move || {
+            if cloned.fetch_add(1, Ordering::SeqCst) == 0 {
+                panic!("first gathering");
+            }
+        });
+
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| gatherer.gather())).is_err());
+        gatherer.gather();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn registration_to_dead_registry_fails() {
+        let live = Gatherer::new();
+        let dead = Gatherer::new().registry();
+
+        let e = CounterBuilder::new("foo")
+            .registry(live.registry())
+            .registry(dead.clone())
+            .finish()
+            .err()
+            .unwrap();
+        assert_eq!(*e.kind(), ErrorKind::RegistrationFailed);
+        assert!(e.to_string().contains("registries at [1]"));
+        assert_eq!(live.gather().to_text(), "");
+
+        let gauge = GaugeBuilder::new("bar")
+            .registry(live.registry())
+            .finish()
+            .unwrap();
+        gauge.set(1.0);
+        assert_eq!(live.gather().to_text(), "# TYPE bar gauge\nbar 1\n");
+    }
+
+    #[test]
+    fn series_are_sorted_by_labels() {
+        let expected = "# TYPE foo counter\nfoo{a=\"0\"} 0\nfoo{a=\"1\"} 0\nfoo{b=\"0\"} 0\n";
+        for values in &[["0", "1"], ["1", "0"]] {
+            let gatherer = Gatherer::new();
+            let mut counters = Vec::new();
+            counters.push(
+                CounterBuilder::new("foo")
+                    .label("b", "0")
+                    .registry(gatherer.registry())
+                    .finish()
+                    .unwrap(),
+            );
+            for value in values {
+                counters.push(
+                    CounterBuilder::new("foo")
+                        .label("a", value)
+                        .registry(gatherer.registry())
+                        .finish()
+                        .unwrap(),
+                );
+            }
+            assert_eq!(gatherer.gather().to_text(), expected);
+        }
+    }
+
+    #[test]
+    fn try_register_works() {
+        let counter = CounterBuilder::new("foo").finish().unwrap();
+        let primary = Gatherer::new();
+        let mut secondary = Gatherer::new();
+        let registry = primary.registry();
+        secondary.subscribe(&registry);
+
+        std::mem::drop(primary);
+        assert!(registry.try_register(counter.collector()).is_ok());
+        assert_eq!(secondary.gather().to_text(), "# TYPE foo counter\nfoo 0\n");
+
+        std::mem::drop(secondary);
+        assert_eq!(
+            registry
+                .try_register(counter.collector())
+                .err()
+                .map(|e| *e.kind()),
+            Some(ErrorKind::RegistrationFailed)
+        );
+    }
+
+    #[test]
+    fn set_registry_works() {
+        let old = Gatherer::new();
+        let new = Gatherer::new();
+        let mut builder = CounterBuilder::new("foo");
+        builder.registry(old.registry());
+        let _foo0 = builder.finish().unwrap();
+
+        builder.set_registry(new.registry());
+        let _foo1 = builder.label("bar", "baz").finish().unwrap();
+        assert_eq!(old.gather().to_text(), "# TYPE foo counter\nfoo 0\n");
+        assert_eq!(
+            new.gather().to_text(),
+            "# TYPE foo counter\nfoo{bar=\"baz\"} 0\n"
+        );
+    }
+
+    #[test]
+    fn dedup_works() {
+        let mut gatherer = Gatherer::new();
+        let counter = CounterBuilder::new("count")
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        gatherer.registry().register(counter.collector());
+        counter.increment();
+        assert_eq!(
+            gatherer.gather().to_text(),
+            "# TYPE count counter\ncount 2\n"
+        );
+
+        gatherer.set_dedup(true);
+        assert_eq!(
+            gatherer.gather().to_text(),
+            "# TYPE count counter\ncount 1\n"
+        );
+    }
+
+    #[test]
+    fn global_namespace_works() {
+        let mut gatherer = Gatherer::new();
+        assert!(gatherer.set_global_namespace("0app").is_err());
+        gatherer.set_global_namespace("app").unwrap();
+
+        let mut builder = MetricBuilder::without_registry();
+        builder.set_registry(gatherer.registry()).namespace("foo");
+        let histogram = builder.histogram("bar").bucket(1.0).finish().unwrap();
+        histogram.observe(0.5);
+
+        let families = gatherer.gather().into_vec();
+        assert_eq!(families[0].name().to_string(), "app_foo_bar");
+        assert_eq!(
+            format!("\n{}", families[0]),
+            r#"
+# TYPE app_foo_bar histogram
+app_foo_bar_bucket{le="1"} 1
+app_foo_bar_bucket{le="+Inf"} 1
+app_foo_bar_sum 0.5
+app_foo_bar_count 1
+"#
+        );
+    }
+
+    #[test]
+    fn describe_works() {
+        use metric::{Metric, MetricKind, MetricName};
+
+        struct Described(Option<metrics::Counter>);
+        impl Collect for Described {
+            type Metrics = std::option::IntoIter<Metric>;
+            fn collect(&mut self) -> Option<Self::Metrics> {
+                Some(self.0.clone().map(Metric::from).into_iter())
+            }
+            fn describe(&self) -> Vec<(MetricName, MetricKind, Option<String>)> {
+                let name = MetricName::new(None, None, "foo").unwrap();
+                vec![(name, MetricKind::Counter, Some("Foo".to_owned()))]
+            }
+        }
+
+        let gatherer = Gatherer::new();
+        gatherer.registry().register_boxed(Described(None).boxed());
+        let counter = CounterBuilder::new("bar").finish().unwrap();
+        gatherer.registry().register(counter.collector());
+        assert_eq!(
+            gatherer.gather().to_text(),
+            "# TYPE bar counter\nbar 0\n# HELP foo Foo\n# TYPE foo counter\n"
+        );
+
+        let gatherer = Gatherer::new();
+        let counter = CounterBuilder::new("foo").finish().unwrap();
+        gatherer.registry().register(Described(Some(counter)));
+        assert_eq!(
+            gatherer.gather().to_text(),
+            "# HELP foo Foo\n# TYPE foo counter\nfoo 0\n"
+        );
+    }
+
+    #[test]
+    fn subscribe_works() {
+        let primary = Gatherer::new();
+        let mut secondary = Gatherer::new();
+        let counter = CounterBuilder::new("foo")
+            .registry(primary.registry())
+            .finish()
+            .unwrap();
+        secondary.subscribe(&primary.registry());
+        secondary.subscribe(&primary.registry());
+        assert_eq!(secondary.gather().to_text(), "# TYPE foo counter\nfoo 0\n");
+
+        std::mem::drop(counter);
+        assert_eq!(primary.gather().to_text(), "");
+        assert_eq!(secondary.gather().to_text(), "");
+
+        std::mem::drop(secondary);
+        let _counter = CounterBuilder::new("bar")
+            .registry(primary.registry())
+            .finish()
+            .unwrap();
+        assert_eq!(primary.gather().to_text(), "# TYPE bar counter\nbar 0\n");
+    }
+
+    #[test]
+    fn scoped_registry_works() {
+        let gatherer = Gatherer::new();
+        let outer = ScopedRegistry::new(gatherer.registry());
+        let inner = ScopedRegistry::new(outer.registry());
+        let _foo = CounterBuilder::new("foo")
+            .registry(outer.registry())
+            .finish()
+            .unwrap();
+        let _bar = GaugeBuilder::new("bar")
+            .registry(inner.registry())
+            .finish()
+            .unwrap();
+        assert_eq!(gatherer.metric_names().len(), 2);
+
+        let registry = inner.registry();
+        std::mem::drop(inner);
+        assert_eq!(gatherer.gather().to_text(), "# TYPE foo counter\nfoo 0\n");
+
+        // Collectors registered after the scope is dropped are ignored.
+        let _baz = CounterBuilder::new("baz")
+            .registry(registry)
+            .finish()
+            .unwrap();
+        assert_eq!(gatherer.gather().to_text(), "# TYPE foo counter\nfoo 0\n");
+
+        let _qux = GaugeBuilder::new("qux")
+            .registry(ScopedRegistry::new(outer.registry()).registry())
+            .finish()
+            .unwrap();
+        std::mem::drop(outer);
+        assert!(gatherer.metric_names().is_empty());
+        assert_eq!(gatherer.gather().to_text(), "");
+    }
+
+    #[test]
+    fn gather_grouped_by_works() {
+        use std::time::Duration;
+
+        let gatherer = Gatherer::new();
+        let builder = MetricBuilder::with_registry(gatherer.registry());
+        let mut metrics = Vec::new();
+        for &(zone, instance) in &[("x", "1"), ("x", "2"), ("y", "3")] {
+            let counter = builder
+                .counter("foo")
+                .label("zone", zone)
+                .label("instance_id", instance)
+                .finish()
+                .unwrap();
+            counter.add_u64(instance.parse().unwrap());
+            let histogram = builder
+                .histogram("bar")
+                .bucket(if zone == "x" { 1.0 } else { 2.0 })
+                .label("instance_id", instance)
+                .finish()
+                .unwrap();
+            histogram.observe(0.5);
+            let summary = builder
+                .summary("baz", Duration::from_secs(60))
+                .quantile(0.5)
+                .label("instance_id", instance)
+                .finish()
+                .unwrap();
+            summary.observe(instance.parse::<f64>().unwrap());
+            metrics.push((counter, histogram, summary));
+        }
+
+        assert_eq!(
+            gatherer.gather_grouped_by(&["zone"]).to_text(),
+            [
+                "# TYPE bar histogram",
+                "bar_bucket{le=\"1\"} 2",
+                "bar_bucket{le=\"2\"} 3",
+                "bar_bucket{le=\"+Inf\"} 3",
+                "bar_sum 1.5",
+                "bar_count 3",
+                "# TYPE baz summary",
+                "baz{quantile=\"0.5\"} 2",
+                "baz_sum 6",
+                "baz_count 3",
+                "# TYPE foo counter",
+                "foo{zone=\"x\"} 3",
+                "foo{zone=\"y\"} 3",
+                "",
+            ]
+            .join("\n")
+        );
+
+        // The original labels are kept by `gather`.
+        assert_eq!(gatherer.gather().into_vec()[2].metrics().len(), 3);
+    }
+
+    #[test]
+    fn lint_hook_works() {
+        use std::sync::{Arc, Mutex};
+
+        let lints = Arc::new(Mutex::new(Vec::new()));
+        let mut gatherer = Gatherer::new();
+        let cloned = Arc::clone(&lints);
+        gatherer.set_lint_hook(move |lint| cloned.lock().unwrap().push(lint));
+
+        let builder = MetricBuilder::with_registry(gatherer.registry());
+        let _foo = builder.counter("foo_total").finish().unwrap();
+        let _bar = builder.histogram("bar").help("Bar").finish().unwrap();
+        let _baz = builder
+            .histogram("baz")
+            .help("Baz")
+            .bucket(1.0)
+            .finish()
+            .unwrap();
+        gatherer.gather();
+        gatherer.gather();
+
+        let name = |name| MetricName::new(None, None, name).unwrap();
+        assert_eq!(
+            *lints.lock().unwrap(),
+            [
+                Lint::SingleBucket(name("bar")),
+                Lint::MissingHelp(name("foo_total")),
+            ]
+        );
+
+        lints.lock().unwrap().clear();
+        let _qux = builder.counter("qux").help("Qux").finish().unwrap();
+        gatherer.gather();
+        assert_eq!(
+            *lints.lock().unwrap(),
+            [Lint::MissingTotalSuffix(name("qux"))]
+        );
+    }
+
+    #[test]
+    fn aggregation_of_mismatched_buckets_works() {
+        let gatherer = Gatherer::new();
+        let mut builder = MetricBuilder::without_registry();
+        builder.set_registry(gatherer.registry());
+
+        let histogram0 = builder
+            .histogram("histogram")
+            .bucket(1e-20)
+            .finish()
+            .unwrap();
+        let histogram1 = builder
+            .histogram("histogram")
+            .bucket(1e-17)
+            .bucket(std::f64::NEG_INFINITY)
+            .finish()
+            .unwrap();
+        histogram0.observe(1e-21);
+        histogram1.observe(5e-18);
+
+        let families = gatherer.gather().into_vec();
+        assert_eq!(families.len(), 1);
+        if let metric::Metrics::Histogram(ref v) = *families[0].metrics() {
+            assert_eq!(v.len(), 1);
+            assert_eq!(v[0].components().len(), 2);
+            assert_eq!(v[0].components()[1].count(), 1);
+            assert_eq!(
+                v[0].cumulative_buckets()
+                    .map(|b| (b.upper_bound(), b.cumulative_count()))
+                    .collect::<Vec<_>>(),
+                [
+                    (std::f64::NEG_INFINITY, 0),
+                    (1e-20, 1),
+                    (1e-17, 2),
+                    (std::f64::INFINITY, 2)
+                ]
+            );
+            assert_eq!(
+                v[0].bucket_counts(),
+                [
+                    (f64::NEG_INFINITY, 0),
+                    (1e-20, 1),
+                    (1e-17, 1),
+                    (f64::INFINITY, 0)
+                ]
+            );
+        } else {
+            panic!();
+        }
+    }
+
+    #[test]
+    fn before_gather_hooks_run_in_order() {
+        use std::sync::{Arc, Mutex};
+
+        let mut gatherer = Gatherer::new();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        for i in 0..3 {
+            let calls = Arc::clone(&calls);
+            gatherer.on_before_gather(move || calls.lock().unwrap().push(i));
+        }
+        gatherer.gather();
+        gatherer.gather();
+        assert_eq!(*calls.lock().unwrap(), [0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn concurrent_gather_works() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let gatherer = Arc::new(Gatherer::new());
+        let counter = CounterBuilder::new("foo")
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        counter.add_u64(3);
+
+        let handles = (0..4)
+            .map(|_| {
+                let gatherer = Arc::clone(&gatherer);
+                thread::spawn(move || gatherer.gather().to_text())
+            })
+            .collect::<Vec<_>>();
+        for h in handles {
+            assert_eq!(h.join().unwrap(), "# TYPE foo counter\nfoo 3\n");
+        }
+
+        std::mem::drop(counter);
+        assert_eq!(gatherer.gather().to_text(), "");
+    }
 }
@@ -0,0 +1,139 @@
+/// Builds a counter and registers it to the default registry.
+///
+/// This expands to a `CounterBuilder` method chain and
+/// evaluates to the result of `CounterBuilder::finish`.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate prometrics;
+///
+/// # fn main() {
+/// let counter = counter!("requests_total", "Number of requests").unwrap();
+/// assert_eq!(counter.help(), Some("Number of requests"));
+///
+/// let counter = counter!("errors_total", "Number of errors", labels => {
+///     "method" => "GET",
+///     "status" => "500",
+/// }).unwrap();
+/// assert_eq!(counter.labels().to_string(), r#"{method="GET",status="500"}"#);
+/// # }
+/// ```
+#[cfg(not(feature = "no-default-registry"))]
+#[macro_export]
+macro_rules! counter {
+    ($name:expr) => {
+        $crate::metrics::CounterBuilder::new($name)
+            .default_registry()
+            .finish()
+    };
+    ($name:expr, $help:expr $(, labels => { $($label:expr => $value:expr),* $(,)* })*) => {
+        $crate::metrics::CounterBuilder::new($name)
+            .help($help)
+            $($(.label($label, $value))*)*
+            .default_registry()
+            .finish()
+    };
+}
+
+/// Builds a gauge and registers it to the default registry.
+///
+/// This expands to a `GaugeBuilder` method chain and
+/// evaluates to the result of `GaugeBuilder::finish`.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate prometrics;
+///
+/// # fn main() {
+/// let gauge = gauge!("in_flight_requests", "Number of in-flight requests", labels => {
+///     "method" => "GET",
+/// }).unwrap();
+/// gauge.set(3.0);
+/// assert_eq!(gauge.to_string(), r#"in_flight_requests{method="GET"} 3"#);
+/// # }
+/// ```
+#[cfg(not(feature = "no-default-registry"))]
+#[macro_export]
+macro_rules! gauge {
+    ($name:expr) => {
+        $crate::metrics::GaugeBuilder::new($name)
+            .default_registry()
+            .finish()
+    };
+    ($name:expr, $help:expr $(, labels => { $($label:expr => $value:expr),* $(,)* })*) => {
+        $crate::metrics::GaugeBuilder::new($name)
+            .help($help)
+            $($(.label($label, $value))*)*
+            .default_registry()
+            .finish()
+    };
+}
+
+/// Builds a histogram and registers it to the default registry.
+///
+/// This expands to a `HistogramBuilder` method chain and
+/// evaluates to the result of `HistogramBuilder::finish`.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate prometrics;
+///
+/// # fn main() {
+/// let histogram = histogram!("latency_seconds", "Request latency", buckets => [0.1, 1.0], labels => {
+///     "method" => "GET",
+/// }).unwrap();
+/// assert_eq!(histogram.buckets().len(), 3);
+/// # }
+/// ```
+#[cfg(not(feature = "no-default-registry"))]
+#[macro_export]
+macro_rules! histogram {
+    ($name:expr, $help:expr, buckets => [ $($bucket:expr),* $(,)* ]
+     $(, labels => { $($label:expr => $value:expr),* $(,)* })*) => {
+        $crate::metrics::HistogramBuilder::new($name)
+            .help($help)
+            $(.bucket($bucket))*
+            $($(.label($label, $value))*)*
+            .default_registry()
+            .finish()
+    };
+}
+
+/// Builds multiple metrics at once.
+///
+/// Each element is an expression which evaluates to a `Result` (e.g., an invocation of `counter!`).
+/// This macro evaluates to a `Result` holding the tuple of the resulting metrics,
+/// or the first error if any of them fails.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate prometrics;
+///
+/// # #[cfg(not(feature = "no-default-registry"))]
+/// # fn main() {
+/// let (requests, in_flight) = register_metrics! {
+///     counter!("requests_total", "Number of requests"),
+///     gauge!("in_flight_requests", "Number of in-flight requests"),
+/// }.unwrap();
+/// requests.increment();
+/// in_flight.increment();
+///
+/// assert!(register_metrics! { counter!("ok"), counter!("0_ng") }.is_err());
+/// # }
+/// # #[cfg(feature = "no-default-registry")]
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! register_metrics {
+    ($($metric:expr),* $(,)*) => {
+        (|| -> $crate::Result<_> { Ok(($($metric?,)*)) })()
+    };
+}
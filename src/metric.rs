@@ -1,14 +1,61 @@
 //! Metric.
 use std;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
-pub use aggregated_metrics::{
+pub use self::aggregated::{
     AggregatedCounter, AggregatedGauge, AggregatedHistogram, AggregatedSummary,
 };
 
-use label::Labels;
+pub use frozen_metrics::{FrozenMetric, FrozenMetricFamilies, FrozenMetricFamily, FrozenValue};
+
+use aggregated_metrics::Group;
+use label::{Label, Labels};
 use metrics::{Counter, Gauge, Histogram, Summary};
-use {ErrorKind, Result};
+use {Error, ErrorKind, Result};
+
+/// Metrics aggregated by `Gatherer`.
+///
+/// The metrics which have the same name and labels are merged into one of the types in this module
+/// (e.g., the values of counters are summed and the buckets of histograms are unified).
+/// Custom exporters can read them via `MetricFamily::metrics` to produce formats other than the text one.
+///
+/// # Examples
+///
+/// ```
+/// use prometrics::Gatherer;
+/// use prometrics::metric::Metrics;
+/// use prometrics::metrics::CounterBuilder;
+///
+/// let gatherer = Gatherer::new();
+/// let counters = (0..2)
+///     .map(|_| {
+///         CounterBuilder::new("foo_total")
+///             .registry(gatherer.registry())
+///             .finish()
+///             .unwrap()
+///     })
+///     .collect::<Vec<_>>();
+/// for c in &counters {
+///     c.add_u64(3);
+/// }
+///
+/// let families = gatherer.gather().into_vec();
+/// if let Metrics::Counter(ref counters) = *families[0].metrics() {
+///     assert_eq!(counters.len(), 1);
+///     assert_eq!(counters[0].metric_name().to_string(), "foo_total");
+///     assert_eq!(counters[0].value(), 6.0);
+/// } else {
+///     unreachable!();
+/// }
+/// ```
+pub mod aggregated {
+    pub use aggregated_metrics::{
+        AggregatedCounter, AggregatedGauge, AggregatedHistogram, AggregatedSummary,
+    };
+}
 
 /// Metric.
 ///
@@ -86,9 +133,9 @@ impl From<Summary> for Metric {
 /// - [Metric names](https://prometheus.io/docs/instrumenting/writing_clientlibs/#metric-names)
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct MetricName {
-    namespace: Option<String>,
-    subsystem: Option<String>,
-    name: String,
+    namespace: Option<Arc<str>>,
+    subsystem: Option<Arc<str>>,
+    name: Arc<str>,
 }
 impl MetricName {
     /// Returns the namespace part of this.
@@ -106,11 +153,24 @@ impl MetricName {
         &self.name
     }
 
-    pub(crate) fn new(
-        namespace: Option<&str>,
-        subsystem: Option<&str>,
-        name: &str,
-    ) -> Result<Self> {
+    /// Makes a new `MetricName` instance.
+    ///
+    /// # Errors
+    ///
+    /// If any of the parts contains invalid characters,
+    /// this function returns `ErrorKind::InvalidInput` error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metric::MetricName;
+    ///
+    /// let name = MetricName::new(Some("foo"), None, "bar").unwrap();
+    /// assert_eq!(name.to_string(), "foo_bar");
+    ///
+    /// assert!(MetricName::new(None, None, "1bar").is_err());
+    /// ```
+    pub fn new(namespace: Option<&str>, subsystem: Option<&str>, name: &str) -> Result<Self> {
         if let Some(s) = namespace {
             track!(Self::validate_name(s), "{:?}", s)?;
         }
@@ -120,12 +180,75 @@ impl MetricName {
         track!(Self::validate_name(name), "{:?}", name)?;
 
         Ok(MetricName {
-            namespace: namespace.map(|s| s.to_owned()),
-            subsystem: subsystem.map(|s| s.to_owned()),
-            name: name.to_string(),
+            namespace: namespace.map(intern),
+            subsystem: subsystem.map(intern),
+            name: intern(name),
         })
     }
-    fn validate_name(name: &str) -> Result<()> {
+    /// Makes a copy of this name whose namespace part is replaced with `namespace`.
+    ///
+    /// # Errors
+    ///
+    /// If `namespace` contains invalid characters,
+    /// this method returns `ErrorKind::InvalidInput` error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metric::MetricName;
+    ///
+    /// let name = MetricName::new(None, Some("http"), "requests").unwrap();
+    /// assert_eq!(name.with_namespace("foo").unwrap().to_string(), "foo_http_requests");
+    /// assert_eq!(name.with_subsystem("grpc").unwrap().to_string(), "grpc_requests");
+    /// assert_eq!(name.with_suffix("_total").unwrap().to_string(), "http_requests_total");
+    /// ```
+    pub fn with_namespace(&self, namespace: &str) -> Result<Self> {
+        track!(Self::new(Some(namespace), self.subsystem(), self.name()))
+    }
+
+    /// Makes a copy of this name whose subsystem part is replaced with `subsystem`.
+    ///
+    /// # Errors
+    ///
+    /// If `subsystem` contains invalid characters,
+    /// this method returns `ErrorKind::InvalidInput` error.
+    pub fn with_subsystem(&self, subsystem: &str) -> Result<Self> {
+        track!(Self::new(self.namespace(), Some(subsystem), self.name()))
+    }
+
+    /// Makes a copy of this name whose name part is suffixed with `suffix`.
+    ///
+    /// Note that no separator is inserted between the name part and `suffix`.
+    ///
+    /// # Errors
+    ///
+    /// If `suffix` contains invalid characters,
+    /// this method returns `ErrorKind::InvalidInput` error.
+    pub fn with_suffix(&self, suffix: &str) -> Result<Self> {
+        let name = format!("{}{}", self.name(), suffix);
+        track!(Self::new(self.namespace(), self.subsystem(), &name))
+    }
+
+    /// Makes a copy of this name whose namespace part is prefixed with `prefix`.
+    ///
+    /// If this name has no namespace, `prefix` becomes the namespace.
+    ///
+    /// `prefix` must have been validated by `validate_name`,
+    /// which ensures that the resulting namespace is also valid.
+    pub(crate) fn with_namespace_prefix(&self, prefix: &str) -> Self {
+        let namespace = if let Some(namespace) = self.namespace() {
+            format!("{}_{}", prefix, namespace)
+        } else {
+            prefix.to_owned()
+        };
+        MetricName {
+            namespace: Some(intern(&namespace)),
+            subsystem: self.subsystem.clone(),
+            name: Arc::clone(&self.name),
+        }
+    }
+
+    pub(crate) fn validate_name(name: &str) -> Result<()> {
         // REGEX: [a-zA-Z_:][a-zA-Z0-9_:]*
         track_assert!(!name.is_empty(), ErrorKind::InvalidInput);
         match name.as_bytes()[0] as char {
@@ -154,6 +277,33 @@ impl fmt::Display for MetricName {
     }
 }
 
+lazy_static! {
+    static ref INTERNED_NAMES: Mutex<HashSet<Arc<str>>> = Mutex::new(HashSet::new());
+}
+
+/// Returns the shared storage of `s`.
+///
+/// Strings which are no longer referred to by any `MetricName` are released
+/// when the interner is about to grow, so the interner never retains more than
+/// about twice as many strings as the ones in use.
+///
+/// This is only invoked when names are built (e.g., by metric builders), not when metrics are updated.
+fn intern(s: &str) -> Arc<str> {
+    if let Ok(mut names) = INTERNED_NAMES.lock() {
+        if let Some(name) = names.get(s) {
+            return Arc::clone(name);
+        }
+        if names.len() == names.capacity() {
+            names.retain(|name| Arc::strong_count(name) > 1);
+        }
+        let name: Arc<str> = Arc::from(s);
+        names.insert(Arc::clone(&name));
+        name
+    } else {
+        Arc::from(s)
+    }
+}
+
 /// Metric kind.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[allow(missing_docs)]
@@ -173,6 +323,118 @@ impl fmt::Display for MetricKind {
         }
     }
 }
+impl FromStr for MetricKind {
+    type Err = Error;
+
+    /// Parses a metric kind in the form used by the `# TYPE` lines of the text format.
+    ///
+    /// # Errors
+    ///
+    /// If `s` is not one of `counter`, `gauge`, `summary` and `histogram`,
+    /// this method returns `ErrorKind::InvalidInput` error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metric::MetricKind;
+    ///
+    /// assert_eq!("counter".parse::<MetricKind>().ok(), Some(MetricKind::Counter));
+    /// assert!("untyped".parse::<MetricKind>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "counter" => Ok(MetricKind::Counter),
+            "gauge" => Ok(MetricKind::Gauge),
+            "summary" => Ok(MetricKind::Summary),
+            "histogram" => Ok(MetricKind::Histogram),
+            _ => track_panic!(ErrorKind::InvalidInput, "Unknown metric kind: {:?}", s),
+        }
+    }
+}
+
+/// This trait allows for customizing how sample values are rendered in the text format.
+///
+/// Only the values of samples (e.g., the value of a counter or the sum of a histogram) are affected;
+/// integral ones such as the counts of histograms and the values of integer gauges
+/// (except in `FrozenMetricFamilies`, which hold the values of gauges as floats),
+/// as well as label values (e.g., the `le` label of histograms), are rendered as usual.
+///
+/// # Examples
+///
+/// ```
+/// use std::fmt;
+/// use prometrics::Gatherer;
+/// use prometrics::metric::ValueFormatter;
+/// use prometrics::metrics::GaugeBuilder;
+///
+/// struct FixedPrecision;
+/// impl ValueFormatter for FixedPrecision {
+///     fn write_value(&self, f: &mut dyn fmt::Write, value: f64) -> fmt::Result {
+///         write!(f, "{:.2}", value)
+///     }
+/// }
+///
+/// let gatherer = Gatherer::new();
+/// let gauge = GaugeBuilder::new("foo").registry(gatherer.registry()).finish().unwrap();
+/// gauge.set(12.300000001);
+///
+/// let families = gatherer.gather();
+/// assert_eq!(families.to_text(), "# TYPE foo gauge\nfoo 12.300000001\n");
+/// assert_eq!(families.to_text_with_formatter(&FixedPrecision), "# TYPE foo gauge\nfoo 12.30\n");
+/// ```
+pub trait ValueFormatter {
+    /// Writes the text representation of `value` to `f`.
+    fn write_value(&self, f: &mut dyn fmt::Write, value: f64) -> fmt::Result;
+}
+
+/// The default `ValueFormatter`.
+///
+/// Finite values are rendered in the shortest form that round-trips,
+/// and non-finite ones are rendered as `NaN`, `+Inf` and `-Inf`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefaultValueFormatter;
+impl ValueFormatter for DefaultValueFormatter {
+    fn write_value(&self, f: &mut dyn fmt::Write, value: f64) -> fmt::Result {
+        write!(f, "{}", MetricValue(value))
+    }
+}
+
+/// Options for the text format.
+#[derive(Clone)]
+pub(crate) struct TextOptions<'a> {
+    /// Whether timestamps are included in the output.
+    pub timestamps: bool,
+
+    /// The formatter of sample values.
+    pub formatter: &'a dyn ValueFormatter,
+}
+impl<'a> TextOptions<'a> {
+    pub fn value(&self, value: f64) -> FormattedValue<'a> {
+        FormattedValue {
+            value,
+            formatter: self.formatter,
+        }
+    }
+}
+impl Default for TextOptions<'static> {
+    fn default() -> Self {
+        TextOptions {
+            timestamps: true,
+            formatter: &DefaultValueFormatter,
+        }
+    }
+}
+
+/// A sample value which is rendered by a `ValueFormatter`.
+pub(crate) struct FormattedValue<'a> {
+    value: f64,
+    formatter: &'a dyn ValueFormatter,
+}
+impl<'a> fmt::Display for FormattedValue<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.formatter.write_value(f, self.value)
+    }
+}
 
 pub(crate) struct MetricValue(pub f64);
 impl fmt::Display for MetricValue {
@@ -180,7 +442,7 @@ impl fmt::Display for MetricValue {
         if self.0.is_finite() {
             write!(f, "{}", self.0)
         } else if self.0.is_nan() {
-            write!(f, "Nan")
+            write!(f, "NaN")
         } else if self.0.is_sign_positive() {
             write!(f, "+Inf")
         } else {
@@ -189,6 +451,105 @@ impl fmt::Display for MetricValue {
     }
 }
 
+/// A sample (i.e., a line of the text format) of a metric.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    name: String,
+    labels: Vec<Label>,
+    value: f64,
+    timestamp: Option<i64>,
+}
+impl Sample {
+    /// Returns the name of this sample (e.g., `foo_bucket`).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the labels of this sample.
+    ///
+    /// This includes the reserved labels (i.e., `le` and `quantile`) if they exist.
+    pub fn labels(&self) -> &[Label] {
+        &self.labels
+    }
+
+    /// Returns the value of this sample.
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Returns the timestamp of this sample.
+    pub fn timestamp(&self) -> Option<i64> {
+        self.timestamp
+    }
+
+    fn new(
+        name: &MetricName,
+        suffix: &str,
+        reserved: Option<Label>,
+        labels: &Labels,
+        value: f64,
+        timestamp: Option<i64>,
+    ) -> Self {
+        Sample {
+            name: format!("{}{}", name, suffix),
+            labels: reserved.into_iter().chain(labels.iter().cloned()).collect(),
+            value,
+            timestamp,
+        }
+    }
+}
+
+/// The value of an `le` label.
+///
+/// Since Prometheus identifies buckets by the string form of `le`,
+/// a bound is always rendered in the same way: as the shortest decimal string
+/// (never in scientific notation) which round-trips to the same `f64`.
+/// Negative zero is rendered as `0`.
+pub(crate) struct BucketBound(pub f64);
+impl fmt::Display for BucketBound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 == 0.0 {
+            write!(f, "0")
+        } else {
+            write!(f, "{}", MetricValue(self.0))
+        }
+    }
+}
+
+/// Writes `labels` enclosed in braces, together with a reserved label (e.g., `le` of histograms).
+///
+/// The reserved label is placed first, or among `labels` in name order if `sorted` is `true`.
+pub(crate) fn write_labels_with_reserved<'a, W, I, V>(
+    f: &mut W,
+    reserved: (&str, V),
+    labels: I,
+    sorted: bool,
+) -> fmt::Result
+where
+    W: fmt::Write,
+    I: IntoIterator<Item = &'a Label>,
+    V: fmt::Display,
+{
+    let mut reserved = Some(reserved);
+    let mut delimiter = "{";
+    for label in labels {
+        if let Some((name, value)) = reserved.take() {
+            if !sorted || name < label.name() {
+                write!(f, "{}{}=\"{}\"", delimiter, name, value)?;
+                delimiter = ",";
+            } else {
+                reserved = Some((name, value));
+            }
+        }
+        write!(f, "{}{}", delimiter, label)?;
+        delimiter = ",";
+    }
+    if let Some((name, value)) = reserved {
+        write!(f, "{}{}=\"{}\"", delimiter, name, value)?;
+    }
+    write!(f, "}}")
+}
+
 /// Metric families.
 #[derive(Debug, Clone)]
 pub struct MetricFamilies(pub(crate) Vec<MetricFamily>);
@@ -200,15 +561,183 @@ impl MetricFamilies {
 
     /// Converts to the text format.
     pub fn to_text(&self) -> String {
-        use std::fmt::Write;
+        self.to_text_with_options(&TextOptions::default())
+    }
 
+    /// Makes a point-in-time snapshot of the current values of these metric families.
+    ///
+    /// The result is detached from the live metrics,
+    /// so it is unaffected by subsequent updates of them and can be sent to other threads.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let gatherer = Gatherer::new();
+    /// let counter = CounterBuilder::new("foo").registry(gatherer.registry()).finish().unwrap();
+    ///
+    /// let families = gatherer.gather();
+    /// let text = families.to_text();
+    /// let frozen = families.freeze();
+    /// assert_eq!(frozen.to_text(), text);
+    ///
+    /// counter.increment();
+    /// assert_eq!(frozen.to_text(), "# TYPE foo counter\nfoo 0\n");
+    /// ```
+    pub fn freeze(self) -> FrozenMetricFamilies {
+        FrozenMetricFamilies(self.0.iter().map(FrozenMetricFamily::new).collect())
+    }
+
+    /// Encodes a snapshot of this into a compact binary format.
+    ///
+    /// The result is the same as `freeze().to_bytes()`, and can be decoded by
+    /// `FrozenMetricFamilies::from_bytes`.
+    /// See `FrozenMetricFamilies::to_bytes` for more details.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        FrozenMetricFamilies(self.0.iter().map(FrozenMetricFamily::new).collect()).to_bytes()
+    }
+
+    /// Checks that no two families with different names are rendered identically.
+    ///
+    /// For example, the name with the namespace `a` and the name `b_c`,
+    /// and the one with the namespace `a_b` and the name `c` are both rendered as `a_b_c`.
+    /// Such families would be merged unexpectedly by Prometheus.
+    ///
+    /// # Errors
+    ///
+    /// If there are colliding families, this method returns `ErrorKind::InvalidInput` error
+    /// whose message contains the definitions of the colliding names.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let gatherer = Gatherer::new();
+    /// let _a = CounterBuilder::new("b_c")
+    ///     .namespace("a")
+    ///     .registry(gatherer.registry())
+    ///     .finish()
+    ///     .unwrap();
+    /// assert!(gatherer.gather().check_name_collisions().is_ok());
+    ///
+    /// let _b = CounterBuilder::new("c")
+    ///     .namespace("a_b")
+    ///     .registry(gatherer.registry())
+    ///     .finish()
+    ///     .unwrap();
+    /// assert!(gatherer.gather().check_name_collisions().is_err());
+    /// ```
+    pub fn check_name_collisions(&self) -> Result<()> {
+        let mut names: HashMap<String, &MetricName> = HashMap::new();
+        for family in &self.0 {
+            let name = family.name();
+            if let Some(other) = names.insert(name.to_string(), name) {
+                track_assert!(
+                    other == name,
+                    ErrorKind::InvalidInput,
+                    "Metric names collide: {:?} and {:?} are both rendered as {:?}",
+                    other,
+                    name,
+                    name.to_string()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts to the text format without the timestamps of the metrics.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let gatherer = Gatherer::new();
+    /// let counter = CounterBuilder::new("foo").registry(gatherer.registry()).finish().unwrap();
+    /// counter.timestamp_mut().set(1234);
+    ///
+    /// let families = gatherer.gather();
+    /// assert_eq!(families.to_text(), "# TYPE foo counter\nfoo 0 1234\n");
+    /// assert_eq!(families.to_text_without_timestamps(), "# TYPE foo counter\nfoo 0\n");
+    /// ```
+    pub fn to_text_without_timestamps(&self) -> String {
+        self.to_text_with_options(&TextOptions {
+            timestamps: false,
+            ..TextOptions::default()
+        })
+    }
+
+    /// Converts to the text format, rendering the sample values by `formatter`.
+    ///
+    /// See `ValueFormatter` for more details.
+    pub fn to_text_with_formatter(&self, formatter: &dyn ValueFormatter) -> String {
+        self.to_text_with_options(&TextOptions {
+            formatter,
+            ..TextOptions::default()
+        })
+    }
+
+    fn to_text_with_options(&self, options: &TextOptions) -> String {
         let mut buf = String::new();
         for m in &self.0 {
-            write!(buf, "{}", m).expect("Never fails");
+            m.write_text(&mut buf, options).expect("Never fails");
         }
         buf
     }
 }
+impl fmt::Display for MetricFamilies {
+    /// Writes a human readable overview of these families.
+    ///
+    /// Each line consists of the name, the kind, the number of series and the help of a family.
+    /// Use `to_text` to obtain the text exposition format.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::{CounterBuilder, GaugeBuilder};
+    ///
+    /// let gatherer = Gatherer::new();
+    /// let _foo0 = CounterBuilder::new("foo")
+    ///     .help("Foo")
+    ///     .label("a", "0")
+    ///     .registry(gatherer.registry())
+    ///     .finish()
+    ///     .unwrap();
+    /// let _foo1 = CounterBuilder::new("foo")
+    ///     .label("a", "1")
+    ///     .registry(gatherer.registry())
+    ///     .finish()
+    ///     .unwrap();
+    /// let _bar = GaugeBuilder::new("bar").registry(gatherer.registry()).finish().unwrap();
+    ///
+    /// assert_eq!(
+    ///     gatherer.gather().to_string(),
+    ///     "bar gauge (1 series)\nfoo counter (2 series): Foo\n"
+    /// );
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for family in &self.0 {
+            write!(
+                f,
+                "{} {} ({} series)",
+                family.name(),
+                family.kind(),
+                family.metrics().len()
+            )?;
+            if let Some(help) = family.help() {
+                write!(f, ": {}", help)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
 impl AsRef<[MetricFamily]> for MetricFamilies {
     fn as_ref(&self) -> &[MetricFamily] {
         &self.0
@@ -259,6 +788,194 @@ impl MetricFamily {
         &self.metrics
     }
 
+    /// Returns the names of the labels used by the metrics in this family, in sorted order.
+    ///
+    /// The result is the union of the label names of all the metrics,
+    /// and does not contain the reserved labels (i.e., `le` of histograms and `quantile` of summaries).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let gatherer = Gatherer::new();
+    /// let _a = CounterBuilder::new("foo")
+    ///     .label("method", "GET")
+    ///     .registry(gatherer.registry())
+    ///     .finish()
+    ///     .unwrap();
+    /// let _b = CounterBuilder::new("foo")
+    ///     .label("code", "404")
+    ///     .registry(gatherer.registry())
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// let families = gatherer.gather().into_vec();
+    /// assert_eq!(families[0].label_names(), ["code", "method"]);
+    /// ```
+    pub fn label_names(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        match self.metrics {
+            Metrics::Counter(ref v) => {
+                for m in v {
+                    names.extend(m.labels().iter().map(|l| l.name()));
+                }
+            }
+            Metrics::Gauge(ref v) => {
+                for m in v {
+                    names.extend(m.labels().iter().map(|l| l.name()));
+                }
+            }
+            Metrics::Summary(ref v) => {
+                for m in v {
+                    names.extend(m.labels().iter().map(|l| l.name()));
+                }
+            }
+            Metrics::Histogram(ref v) => {
+                for m in v {
+                    names.extend(m.labels().iter().map(|l| l.name()));
+                }
+            }
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Returns an iterator over the samples of this metric family.
+    ///
+    /// Histograms and summaries are expanded to multiple samples
+    /// (i.e., `{name}_bucket` or `{name}`, `{name}_sum` and `{name}_count`)
+    /// in the same order as the text format.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::HistogramBuilder;
+    ///
+    /// let gatherer = Gatherer::new();
+    /// let histogram = HistogramBuilder::new("foo")
+    ///     .bucket(1.0)
+    ///     .label("bar", "baz")
+    ///     .registry(gatherer.registry())
+    ///     .finish()
+    ///     .unwrap();
+    /// histogram.observe(0.5);
+    ///
+    /// let family = gatherer.gather().into_vec().remove(0);
+    /// let samples = family
+    ///     .samples()
+    ///     .map(|s| (s.name().to_owned(), s.labels().len(), s.value()))
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(
+    ///     samples,
+    ///     [
+    ///         ("foo_bucket".to_owned(), 2, 1.0),
+    ///         ("foo_bucket".to_owned(), 2, 1.0),
+    ///         ("foo_sum".to_owned(), 1, 0.5),
+    ///         ("foo_count".to_owned(), 1, 1.0),
+    ///     ]
+    /// );
+    /// ```
+    pub fn samples(&self) -> impl Iterator<Item = Sample> {
+        let mut samples = Vec::new();
+        match self.metrics {
+            Metrics::Counter(ref v) => {
+                for m in v {
+                    samples.push(Sample::new(
+                        m.metric_name(),
+                        "",
+                        None,
+                        m.labels(),
+                        m.value(),
+                        m.timestamp().get(),
+                    ));
+                }
+            }
+            Metrics::Gauge(ref v) => {
+                for m in v {
+                    samples.push(Sample::new(
+                        m.metric_name(),
+                        "",
+                        None,
+                        m.labels(),
+                        m.value(),
+                        m.timestamp().get(),
+                    ));
+                }
+            }
+            Metrics::Summary(ref v) => {
+                for m in v {
+                    let timestamp = m.timestamp().get();
+                    for (quantile, value) in m.quantiles() {
+                        let label =
+                            Label::new("quantile", &quantile.to_string()).expect("Never fails");
+                        samples.push(Sample::new(
+                            m.metric_name(),
+                            "",
+                            Some(label),
+                            m.labels(),
+                            value,
+                            timestamp,
+                        ));
+                    }
+                    samples.push(Sample::new(
+                        m.metric_name(),
+                        "_sum",
+                        None,
+                        m.labels(),
+                        m.sum(),
+                        timestamp,
+                    ));
+                    samples.push(Sample::new(
+                        m.metric_name(),
+                        "_count",
+                        None,
+                        m.labels(),
+                        m.count() as f64,
+                        timestamp,
+                    ));
+                }
+            }
+            Metrics::Histogram(ref v) => {
+                for m in v {
+                    let timestamp = m.timestamp().get();
+                    for bucket in m.cumulative_buckets() {
+                        let le = BucketBound(bucket.upper_bound()).to_string();
+                        let label = Label::new("le", &le).expect("Never fails");
+                        samples.push(Sample::new(
+                            m.metric_name(),
+                            "_bucket",
+                            Some(label),
+                            m.labels(),
+                            bucket.cumulative_count() as f64,
+                            timestamp,
+                        ));
+                    }
+                    samples.push(Sample::new(
+                        m.metric_name(),
+                        "_sum",
+                        None,
+                        m.labels(),
+                        m.sum(),
+                        timestamp,
+                    ));
+                    samples.push(Sample::new(
+                        m.metric_name(),
+                        "_count",
+                        None,
+                        m.labels(),
+                        m.count() as f64,
+                        timestamp,
+                    ));
+                }
+            }
+        }
+        samples.into_iter()
+    }
+
     pub(crate) fn new(metric: Metric) -> Self {
         match metric {
             Metric::Counter(m) => MetricFamily {
@@ -283,6 +1000,58 @@ impl MetricFamily {
             },
         }
     }
+    pub(crate) fn prepend_namespace(&mut self, prefix: &str) {
+        let name = self.name.with_namespace_prefix(prefix);
+        match self.metrics {
+            Metrics::Counter(ref mut v) => {
+                for m in v.iter_mut() {
+                    m.set_metric_name(name.clone());
+                }
+            }
+            Metrics::Gauge(ref mut v) => {
+                for m in v.iter_mut() {
+                    m.set_metric_name(name.clone());
+                }
+            }
+            Metrics::Summary(ref mut v) => {
+                for m in v.iter_mut() {
+                    m.set_metric_name(name.clone());
+                }
+            }
+            Metrics::Histogram(ref mut v) => {
+                for m in v.iter_mut() {
+                    m.set_metric_name(name.clone());
+                }
+            }
+        }
+        self.name = name;
+    }
+    pub(crate) fn empty(name: MetricName, kind: MetricKind, help: Option<String>) -> Self {
+        let metrics = match kind {
+            MetricKind::Counter => Metrics::Counter(Vec::new()),
+            MetricKind::Gauge => Metrics::Gauge(Vec::new()),
+            MetricKind::Summary => Metrics::Summary(Vec::new()),
+            MetricKind::Histogram => Metrics::Histogram(Vec::new()),
+        };
+        MetricFamily {
+            name,
+            help,
+            metrics,
+        }
+    }
+    pub(crate) fn group_by(&mut self, names: &[&str]) {
+        match self.metrics {
+            Metrics::Counter(ref mut v) => Group::group_by(v, names),
+            Metrics::Gauge(ref mut v) => Group::group_by(v, names),
+            Metrics::Summary(ref mut v) => Group::group_by(v, names),
+            Metrics::Histogram(ref mut v) => Group::group_by(v, names),
+        }
+    }
+    pub(crate) fn set_help_if_absent(&mut self, help: Option<String>) {
+        if self.help.is_none() {
+            self.help = help;
+        }
+    }
     pub(crate) fn same_family(&self, metric: &Metric) -> bool {
         (self.name(), self.kind()) == (metric.name(), metric.kind())
     }
@@ -323,8 +1092,12 @@ impl MetricFamily {
         }
     }
 }
-impl fmt::Display for MetricFamily {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl MetricFamily {
+    pub(crate) fn write_text<W: fmt::Write>(
+        &self,
+        f: &mut W,
+        options: &TextOptions,
+    ) -> fmt::Result {
         if let Some(help) = self.help() {
             // > HELP lines may contain any sequence of UTF-8 characters (after the metric name),
             // > but the backslash and the line-feed characters have to be escaped as \\ and \n, respectively
@@ -339,10 +1112,15 @@ impl fmt::Display for MetricFamily {
             writeln!(f, "")?;
         }
         writeln!(f, "# TYPE {} {}", self.name(), self.kind())?;
-        write!(f, "{}", self.metrics)?;
+        self.metrics.write_text(f, options)?;
         Ok(())
     }
 }
+impl fmt::Display for MetricFamily {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_text(f, &TextOptions::default())
+    }
+}
 
 /// Sequence of the same metric.
 #[derive(Debug, Clone)]
@@ -353,30 +1131,431 @@ pub enum Metrics {
     Summary(Vec<AggregatedSummary>),
     Histogram(Vec<AggregatedHistogram>),
 }
-impl fmt::Display for Metrics {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl Metrics {
+    /// Returns the number of the metrics (i.e., series) in this sequence.
+    pub fn len(&self) -> usize {
+        match *self {
+            Metrics::Counter(ref v) => v.len(),
+            Metrics::Gauge(ref v) => v.len(),
+            Metrics::Summary(ref v) => v.len(),
+            Metrics::Histogram(ref v) => v.len(),
+        }
+    }
+
+    /// Returns `true` if this sequence has no metrics, otherwise `false`.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn write_text<W: fmt::Write>(
+        &self,
+        f: &mut W,
+        options: &TextOptions,
+    ) -> fmt::Result {
         match *self {
             Metrics::Counter(ref v) => {
                 for m in v.iter() {
-                    writeln!(f, "{}", m)?;
+                    m.write_text(f, options)?;
+                    writeln!(f)?;
                 }
             }
             Metrics::Gauge(ref v) => {
                 for m in v.iter() {
-                    writeln!(f, "{}", m)?;
+                    m.write_text(f, options)?;
+                    writeln!(f)?;
                 }
             }
             Metrics::Summary(ref v) => {
                 for m in v.iter() {
-                    writeln!(f, "{}", m)?;
+                    m.write_text(f, options)?;
+                    writeln!(f)?;
                 }
             }
             Metrics::Histogram(ref v) => {
                 for m in v.iter() {
-                    writeln!(f, "{}", m)?;
+                    m.write_text(f, options)?;
+                    writeln!(f)?;
                 }
             }
         }
         Ok(())
     }
 }
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_text(f, &TextOptions::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn metric_name_storage_is_shared() {
+        let a = MetricName::new(Some("foo"), None, "bar").unwrap();
+        let b = MetricName::new(None, Some("foo"), "bar").unwrap();
+        assert!(Arc::ptr_eq(
+            a.namespace.as_ref().unwrap(),
+            b.subsystem.as_ref().unwrap()
+        ));
+        assert!(Arc::ptr_eq(&a.name, &b.name));
+        assert_eq!(a.namespace(), Some("foo"));
+        assert_eq!(b.name(), "bar");
+    }
+
+    #[test]
+    fn unused_metric_names_are_released() {
+        for i in 0..10_000 {
+            let name = MetricName::new(None, None, &format!("unused_{}", i)).unwrap();
+            assert_eq!(name.name(), format!("unused_{}", i));
+        }
+        let interned = INTERNED_NAMES.lock().unwrap();
+        assert!(interned.len() < 5_000, "len={}", interned.len());
+    }
+
+    #[test]
+    fn samples_works() {
+        use std::time::Duration;
+
+        use metrics::{CounterBuilder, SummaryBuilder};
+        use Gatherer;
+
+        let gatherer = Gatherer::new();
+        let counter = CounterBuilder::new("foo")
+            .label("a", "b")
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        counter.timestamp_mut().set(10);
+        let summary = SummaryBuilder::new("bar", Duration::from_secs(10))
+            .quantile(0.5)
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        summary.observe(3.0);
+
+        let families = gatherer.gather().into_vec();
+        let samples = families
+            .iter()
+            .flat_map(|f| f.samples())
+            .map(|s| {
+                let labels = s.labels().iter().map(|l| l.to_string()).collect::<Vec<_>>();
+                (s.name().to_owned(), labels, s.value(), s.timestamp())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            samples,
+            [
+                (
+                    "bar".to_owned(),
+                    vec![r#"quantile="0.5""#.to_owned()],
+                    3.0,
+                    None
+                ),
+                ("bar_sum".to_owned(), vec![], 3.0, None),
+                ("bar_count".to_owned(), vec![], 1.0, None),
+                ("foo".to_owned(), vec![r#"a="b""#.to_owned()], 0.0, Some(10)),
+            ]
+        );
+    }
+
+    #[test]
+    fn label_names_works() {
+        use metrics::MetricBuilder;
+        use Gatherer;
+
+        let gatherer = Gatherer::new();
+        let builder = MetricBuilder::with_registry(gatherer.registry());
+        let _h0 = track_try_unwrap!(builder
+            .histogram("foo")
+            .bucket(1.0)
+            .label("path", "/")
+            .label("method", "GET")
+            .finish());
+        let _h1 = track_try_unwrap!(builder
+            .histogram("foo")
+            .bucket(1.0)
+            .label("method", "PUT")
+            .label("code", "200")
+            .finish());
+        let _h2 = track_try_unwrap!(builder.histogram("foo").bucket(1.0).finish());
+        let _c = track_try_unwrap!(builder.counter("bar").finish());
+
+        let families = gatherer.gather().into_vec();
+        assert_eq!(families[0].name().to_string(), "bar");
+        assert!(families[0].label_names().is_empty());
+        assert_eq!(families[1].label_names(), ["code", "method", "path"]);
+    }
+
+    #[test]
+    fn metric_kind_from_str_works() {
+        for kind in &[
+            MetricKind::Counter,
+            MetricKind::Gauge,
+            MetricKind::Summary,
+            MetricKind::Histogram,
+        ] {
+            assert_eq!(
+                track_try_unwrap!(kind.to_string().parse::<MetricKind>()),
+                *kind
+            );
+        }
+        assert_eq!(
+            "Counter".parse::<MetricKind>().err().map(|e| *e.kind()),
+            Some(ErrorKind::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn sort_reserved_label_works() {
+        use std::time::Duration;
+
+        use metrics::MetricBuilder;
+        use Gatherer;
+
+        let gatherer = Gatherer::new();
+        let mut builder = MetricBuilder::with_registry(gatherer.registry());
+        builder.label("aaa", "0").label("zzz", "1");
+        let histogram = track_try_unwrap!(builder
+            .histogram("histogram")
+            .bucket(1.0)
+            .sort_reserved_label()
+            .finish());
+        let summary = track_try_unwrap!(builder
+            .summary("summary", Duration::from_secs(60))
+            .quantile(0.5)
+            .sort_reserved_label()
+            .finish());
+        let _default = track_try_unwrap!(builder.histogram("default").bucket(1.0).finish());
+        histogram.observe(0.5);
+        summary.observe(2.0);
+
+        let families = gatherer.gather();
+        let text = families.to_text();
+        assert!(text.contains("\ndefault_bucket{le=\"1\",aaa=\"0\",zzz=\"1\"} 0\n"));
+        assert!(text.contains("\nhistogram_bucket{aaa=\"0\",le=\"1\",zzz=\"1\"} 1\n"));
+        assert!(text.contains("\nhistogram_bucket{aaa=\"0\",le=\"+Inf\",zzz=\"1\"} 1\n"));
+        assert!(text.contains("\nsummary{aaa=\"0\",quantile=\"0.5\",zzz=\"1\"} 2\n"));
+        assert_eq!(families.freeze().to_text(), text);
+
+        let mut builder = MetricBuilder::without_registry();
+        builder.label("aaa", "0");
+        let histogram = track_try_unwrap!(builder
+            .histogram("histogram")
+            .bucket(1.0)
+            .sort_reserved_label()
+            .finish());
+        assert!(histogram
+            .to_string()
+            .starts_with("histogram_bucket{aaa=\"0\",le=\"1\"} 0\n"));
+    }
+
+    #[test]
+    fn binary_snapshot_round_trip_works() {
+        use std::time::Duration;
+
+        use metrics::MetricBuilder;
+        use Gatherer;
+
+        let gatherer = Gatherer::new();
+        let mut builder = MetricBuilder::with_registry(gatherer.registry());
+        builder.label("foo", "a\"b\nc");
+        let counter = track_try_unwrap!(builder.counter("counter").help("Help\n").finish());
+        let nan = track_try_unwrap!(builder.gauge("nan").finish());
+        let inf = track_try_unwrap!(builder.gauge("inf").label("bar", "").finish());
+        let summary = track_try_unwrap!(builder
+            .summary("summary", Duration::from_secs(60))
+            .quantile(0.5)
+            .quantile_with_error(0.9, 0.01)
+            .sort_reserved_label()
+            .finish());
+        let histogram = track_try_unwrap!(builder
+            .histogram("histogram")
+            .buckets(vec![-1.0, 0.1, 2.5])
+            .finish());
+        counter.add_u64(3);
+        nan.set(f64::NAN);
+        inf.set(f64::NEG_INFINITY);
+        summary.observe(2.0);
+        summary.observe(-0.5);
+        histogram.observe(0.1);
+        histogram.observe(-3.0);
+        inf.timestamp_mut().set(-1234);
+
+        let families = gatherer.gather();
+        let bytes = families.to_bytes();
+        assert_eq!(bytes[0], 1);
+        let frozen = families.freeze();
+        assert_eq!(frozen.to_bytes(), bytes);
+
+        let restored = track_try_unwrap!(FrozenMetricFamilies::from_bytes(&bytes));
+        assert_eq!(restored.to_text(), frozen.to_text());
+        assert_eq!(restored.to_bytes(), bytes);
+        let nan = restored
+            .as_ref()
+            .iter()
+            .find(|f| f.name() == "nan")
+            .unwrap();
+        assert_eq!(nan.kind(), MetricKind::Gauge);
+        match *nan.metrics()[0].value() {
+            FrozenValue::Gauge(v) => assert!(v.is_nan()),
+            _ => panic!(),
+        }
+
+        // Invalid inputs
+        let mut unknown_version = bytes.clone();
+        unknown_version[0] = 2;
+        for invalid in &[
+            &[][..],
+            &unknown_version[..],
+            &bytes[..bytes.len() - 1],
+            &[&bytes[..], &[0][..]].concat()[..],
+        ] {
+            assert_eq!(
+                FrozenMetricFamilies::from_bytes(invalid)
+                    .err()
+                    .map(|e| *e.kind()),
+                Some(ErrorKind::InvalidInput)
+            );
+        }
+    }
+
+    #[test]
+    fn value_formatter_works() {
+        use std::time::Duration;
+
+        use metrics::MetricBuilder;
+        use Gatherer;
+
+        struct Scientific;
+        impl ValueFormatter for Scientific {
+            fn write_value(&self, f: &mut dyn fmt::Write, value: f64) -> fmt::Result {
+                write!(f, "{:e}", value)
+            }
+        }
+
+        let gatherer = Gatherer::new();
+        let builder = MetricBuilder::with_registry(gatherer.registry());
+        let counter = track_try_unwrap!(builder.counter("counter").finish());
+        let integer = track_try_unwrap!(builder.gauge("integer").integer().finish());
+        let summary = track_try_unwrap!(builder
+            .summary("summary", Duration::from_secs(60))
+            .quantile(0.5)
+            .finish());
+        let histogram = track_try_unwrap!(builder.histogram("histogram").bucket(1.5).finish());
+        counter.add_u64(1200);
+        integer.set_i64(1200);
+        summary.observe(0.25);
+        histogram.observe(0.25);
+
+        let families = gatherer.gather();
+        let expected = [
+            "# TYPE counter counter",
+            "counter 1.2e3",
+            "# TYPE histogram histogram",
+            "histogram_bucket{le=\"1.5\"} 1",
+            "histogram_bucket{le=\"+Inf\"} 1",
+            "histogram_sum 2.5e-1",
+            "histogram_count 1",
+            "# TYPE integer gauge",
+            "integer 1200",
+            "# TYPE summary summary",
+            "summary{quantile=\"0.5\"} 2.5e-1",
+            "summary_sum 2.5e-1",
+            "summary_count 1",
+            "",
+        ]
+        .join("\n");
+        assert_eq!(families.to_text_with_formatter(&Scientific), expected);
+
+        // Frozen snapshots hold the values of gauges as floats.
+        assert_eq!(
+            families.freeze().to_text_with_formatter(&Scientific),
+            expected.replace("integer 1200", "integer 1.2e3")
+        );
+
+        let families = gatherer.gather();
+        assert_eq!(
+            families.to_text_with_formatter(&DefaultValueFormatter),
+            families.to_text()
+        );
+    }
+
+    #[test]
+    fn freeze_works() {
+        use std::thread;
+        use std::time::Duration;
+
+        use metrics::MetricBuilder;
+        use Gatherer;
+
+        let gatherer = Gatherer::new();
+        let mut builder = MetricBuilder::with_registry(gatherer.registry());
+        builder.label("foo", "a\\\"b\tc");
+        let counter = track_try_unwrap!(builder.counter("counter").help("Help").finish());
+        let gauge = track_try_unwrap!(builder.gauge("gauge").finish());
+        let summary = track_try_unwrap!(builder
+            .summary("summary", Duration::from_secs(60))
+            .quantile(0.5)
+            .finish());
+        let histogram = track_try_unwrap!(builder.histogram("histogram").bucket(1.0).finish());
+        counter.add_u64(3);
+        gauge.set(-1.5);
+        summary.observe(2.0);
+        histogram.observe(0.5);
+        gauge.timestamp_mut().set(1234);
+
+        let families = gatherer.gather();
+        let text = families.to_text();
+        let text_without_timestamps = families.to_text_without_timestamps();
+        let frozen = families.freeze();
+        counter.increment();
+        histogram.observe(2.0);
+
+        let frozen = thread::spawn(move || frozen).join().unwrap();
+        assert_eq!(frozen.to_text(), text);
+        assert_eq!(frozen.to_text_without_timestamps(), text_without_timestamps);
+        assert_eq!(frozen.as_ref().len(), 4);
+        assert_eq!(frozen.as_ref()[0].name(), "counter");
+        assert_eq!(frozen.as_ref()[0].help(), Some("Help"));
+        match *frozen.as_ref()[0].metrics()[0].value() {
+            FrozenValue::Counter(v) => assert_eq!(v, 3.0),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn check_name_collisions_works() {
+        use metrics::{CounterBuilder, GaugeBuilder};
+        use Gatherer;
+
+        let gatherer = Gatherer::new();
+        let _counter = CounterBuilder::new("foo")
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        let _gauge = GaugeBuilder::new("foo")
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        let _gauge = GaugeBuilder::new("baz_qux")
+            .subsystem("bar")
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        assert!(gatherer.gather().check_name_collisions().is_ok());
+
+        let _gauge = GaugeBuilder::new("qux")
+            .namespace("bar")
+            .subsystem("baz")
+            .registry(gatherer.registry())
+            .finish()
+            .unwrap();
+        let e = gatherer.gather().check_name_collisions().err().unwrap();
+        assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+        let message = e.to_string();
+        assert!(message.contains(r#"subsystem: Some("bar"), name: "baz_qux""#));
+        assert!(message.contains(r#"namespace: Some("bar"), subsystem: Some("baz"), name: "qux""#));
+    }
+}
@@ -1,7 +1,9 @@
 use std::time::Duration;
 
+#[cfg(not(feature = "no-default-registry"))]
+use default_registry;
 use metrics::{CounterBuilder, GaugeBuilder, HistogramBuilder, SummaryBuilder};
-use {default_registry, Registry};
+use Registry;
 
 /// Common builder for various metrics.
 #[derive(Debug, Clone)]
@@ -10,9 +12,12 @@ pub struct MetricBuilder {
     subsystem: Option<String>,
     labels: Vec<(String, String)>,
     registries: Vec<Registry>,
+    auto_help: bool,
+    preserve_label_order: bool,
 }
 impl MetricBuilder {
     /// Makes a builder with the default registry.
+    #[cfg(not(feature = "no-default-registry"))]
     pub fn new() -> Self {
         Self::with_registry(default_registry())
     }
@@ -24,6 +29,8 @@ impl MetricBuilder {
             subsystem: None,
             labels: Vec::new(),
             registries: vec![registry],
+            auto_help: false,
+            preserve_label_order: false,
         }
     }
 
@@ -34,6 +41,8 @@ impl MetricBuilder {
             subsystem: None,
             labels: Vec::new(),
             registries: Vec::new(),
+            auto_help: false,
+            preserve_label_order: false,
         }
     }
 
@@ -51,9 +60,36 @@ impl MetricBuilder {
 
     /// Adds a label.
     ///
+    /// If a label with the same name has already been added, its value is replaced with `value`.
+    ///
     /// Note that `name` will be validated when creating the metrics.
     pub fn label(&mut self, name: &str, value: &str) -> &mut Self {
-        self.labels.push((name.to_owned(), value.to_owned()));
+        if let Some(l) = self.labels.iter_mut().find(|l| l.0 == name) {
+            l.1 = value.to_owned();
+        } else {
+            self.labels.push((name.to_owned(), value.to_owned()));
+        }
+        self
+    }
+
+    /// Adds labels.
+    ///
+    /// This is equivalent to calling `label` for each of the given name-value pairs.
+    pub fn labels<I, K, V>(&mut self, labels: I) -> &mut Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        for (name, value) in labels {
+            self.label(name.as_ref(), value.as_ref());
+        }
+        self
+    }
+
+    /// Removes the label named `name` if it has been added.
+    pub fn remove_label(&mut self, name: &str) -> &mut Self {
+        self.labels.retain(|l| l.0 != name);
         self
     }
 
@@ -69,9 +105,43 @@ impl MetricBuilder {
         self
     }
 
+    /// Makes the builders created by this give a placeholder help to their metrics.
+    ///
+    /// The placeholder is `"Auto-generated help for <name>."`, where `<name>` is the full name of the metric.
+    /// It can be overwritten by calling `help` method of the resulting builders.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metrics::MetricBuilder;
+    ///
+    /// let counter = MetricBuilder::without_registry()
+    ///     .namespace("foo")
+    ///     .auto_help()
+    ///     .counter("bar_total")
+    ///     .finish()
+    ///     .unwrap();
+    /// assert_eq!(counter.help(), Some("Auto-generated help for foo_bar_total."));
+    /// ```
+    pub fn auto_help(&mut self) -> &mut Self {
+        self.auto_help = true;
+        self
+    }
+
+    /// Makes the builders created by this keep the labels of their metrics in insertion order.
+    ///
+    /// See `CounterBuilder::preserve_label_order` for more details.
+    pub fn preserve_label_order(&mut self) -> &mut Self {
+        self.preserve_label_order = true;
+        self
+    }
+
     /// Makes a `CounterBuilder` that inherited the setting of this builder.
     pub fn counter(&self, name: &str) -> CounterBuilder {
         let mut builder = CounterBuilder::new(name);
+        if let Some(help) = self.auto_help_text(name) {
+            builder.help(&help);
+        }
         if let Some(ref namespace) = self.namespace {
             builder.namespace(namespace);
         }
@@ -81,6 +151,9 @@ impl MetricBuilder {
         for &(ref k, ref v) in &self.labels {
             builder.label(k, v);
         }
+        if self.preserve_label_order {
+            builder.preserve_label_order();
+        }
         for r in &self.registries {
             builder.registry(r.clone());
         }
@@ -90,6 +163,9 @@ impl MetricBuilder {
     /// Makes a `GaugeBuilder` that inherited the setting of this builder.
     pub fn gauge(&self, name: &str) -> GaugeBuilder {
         let mut builder = GaugeBuilder::new(name);
+        if let Some(help) = self.auto_help_text(name) {
+            builder.help(&help);
+        }
         if let Some(ref namespace) = self.namespace {
             builder.namespace(namespace);
         }
@@ -99,6 +175,9 @@ impl MetricBuilder {
         for &(ref k, ref v) in &self.labels {
             builder.label(k, v);
         }
+        if self.preserve_label_order {
+            builder.preserve_label_order();
+        }
         for r in &self.registries {
             builder.registry(r.clone());
         }
@@ -108,6 +187,9 @@ impl MetricBuilder {
     /// Makes a `HistogramBuilder` that inherited the setting of this builder.
     pub fn histogram(&self, name: &str) -> HistogramBuilder {
         let mut builder = HistogramBuilder::new(name);
+        if let Some(help) = self.auto_help_text(name) {
+            builder.help(&help);
+        }
         if let Some(ref namespace) = self.namespace {
             builder.namespace(namespace);
         }
@@ -117,6 +199,9 @@ impl MetricBuilder {
         for &(ref k, ref v) in &self.labels {
             builder.label(k, v);
         }
+        if self.preserve_label_order {
+            builder.preserve_label_order();
+        }
         for r in &self.registries {
             builder.registry(r.clone());
         }
@@ -126,6 +211,9 @@ impl MetricBuilder {
     /// Makes a `SummaryBuilder` that inherited the setting of this builder.
     pub fn summary(&self, name: &str, window: Duration) -> SummaryBuilder {
         let mut builder = SummaryBuilder::new(name, window);
+        if let Some(help) = self.auto_help_text(name) {
+            builder.help(&help);
+        }
         if let Some(ref namespace) = self.namespace {
             builder.namespace(namespace);
         }
@@ -135,14 +223,74 @@ impl MetricBuilder {
         for &(ref k, ref v) in &self.labels {
             builder.label(k, v);
         }
+        if self.preserve_label_order {
+            builder.preserve_label_order();
+        }
         for r in &self.registries {
             builder.registry(r.clone());
         }
         builder
     }
+
+    fn auto_help_text(&self, name: &str) -> Option<String> {
+        if !self.auto_help {
+            return None;
+        }
+        let mut full_name = String::new();
+        for part in self.namespace.iter().chain(self.subsystem.iter()) {
+            full_name.push_str(part);
+            full_name.push('_');
+        }
+        full_name.push_str(name);
+        Some(format!("Auto-generated help for {}.", full_name))
+    }
 }
+#[cfg(not(feature = "no-default-registry"))]
 impl Default for MetricBuilder {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn duplicate_label_is_overwritten() {
+        let counter = track_try_unwrap!(MetricBuilder::without_registry()
+            .label("foo", "bar")
+            .label("foo", "baz")
+            .counter("qux")
+            .finish());
+        assert_eq!(counter.to_string(), r#"qux{foo="baz"} 0"#);
+    }
+
+    #[test]
+    fn remove_label_works() {
+        let mut builder = MetricBuilder::without_registry();
+        builder.label("foo", "bar").label("baz", "qux");
+        let counter = track_try_unwrap!(builder.remove_label("foo").counter("quux").finish());
+        assert_eq!(counter.to_string(), r#"quux{baz="qux"} 0"#);
+    }
+
+    #[test]
+    fn preserve_label_order_works() {
+        let mut builder = MetricBuilder::without_registry();
+        builder.label("foo", "0").label("bar", "1");
+
+        let counter = track_try_unwrap!(builder.counter("sorted").finish());
+        assert_eq!(counter.to_string(), r#"sorted{bar="1",foo="0"} 0"#);
+
+        builder.preserve_label_order();
+        let mut counter = track_try_unwrap!(builder.counter("preserved").finish());
+        assert_eq!(counter.to_string(), r#"preserved{foo="0",bar="1"} 0"#);
+
+        track_try_unwrap!(counter.labels_mut().insert("baz", "2"));
+        track_try_unwrap!(counter.labels_mut().insert("foo", "3"));
+        assert_eq!(
+            counter.to_string(),
+            r#"preserved{foo="3",bar="1",baz="2"} 0"#
+        );
+    }
+}
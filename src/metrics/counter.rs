@@ -1,12 +1,19 @@
 use std::fmt;
-use std::iter;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::io;
+use std::option;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Weak};
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime};
 
-use atomic::{AtomicF64, AtomicU64};
+use atomic::{AtomicCompensatedF64, AtomicU64};
+#[cfg(not(feature = "no-default-registry"))]
 use default_registry;
-use label::{Label, Labels, LabelsMut};
+use label::{Label, LabelVec, Labels, LabelsMut};
 use metric::{Metric, MetricName, MetricValue};
+#[cfg(feature = "async")]
+use metrics::TimedFuture;
 use timestamp::{self, Timestamp, TimestampMut};
 use {Collect, ErrorKind, Registry, Result};
 
@@ -14,6 +21,15 @@ use {Collect, ErrorKind, Registry, Result};
 ///
 /// Cloned counters share the same value.
 ///
+/// # Precision
+///
+/// The value is held in two parts: an exact integer part (`u64`) and a fractional part (`f64`).
+/// Whole numbers are always added to the integer part, and for fractional counts,
+/// only the remainder below one is added to the fractional part using compensated summation.
+/// Thus, repeatedly adding small fractional counts (e.g., durations in seconds) does not drift
+/// even over long uptimes.
+/// Counts which exceed the range of `u64` are added to the fractional part as they are.
+///
 /// # Examples
 ///
 /// ```
@@ -75,14 +91,49 @@ impl Counter {
     /// Increments this counter.
     #[inline]
     pub fn increment(&self) {
-        self.0.value.increment()
+        self.0.value.increment();
+        self.touch();
     }
 
     /// Adds `count` to this counter.
+    ///
+    /// # Errors
+    ///
+    /// If `count` is negative or `NaN`, this method returns `ErrorKind::InvalidInput` error.
     #[inline]
     pub fn add(&self, count: f64) -> Result<()> {
+        track_assert!(
+            !count.is_nan(),
+            ErrorKind::InvalidInput,
+            "NaN is not allowed"
+        );
         track_assert!(count >= 0.0, ErrorKind::InvalidInput, "count={}", count);
         self.0.value.add(count);
+        self.touch();
+        Ok(())
+    }
+
+    /// Adds `count` to this counter and sets its timestamp to `timestamp`.
+    ///
+    /// # Errors
+    ///
+    /// If `count` is negative or `NaN`, this method returns `ErrorKind::InvalidInput` error
+    /// and neither the value nor the timestamp is updated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let counter = CounterBuilder::new("foo").finish().unwrap();
+    /// counter.add_with_timestamp(2.0, UNIX_EPOCH + Duration::from_secs(10)).unwrap();
+    /// assert_eq!(counter.to_string(), "foo 2 10000");
+    /// ```
+    #[inline]
+    pub fn add_with_timestamp(&self, count: f64, timestamp: SystemTime) -> Result<()> {
+        track!(self.add(count))?;
+        TimestampMut::new(&self.0.timestamp).set_time(timestamp);
         Ok(())
     }
 
@@ -90,6 +141,7 @@ impl Counter {
     #[inline]
     pub fn add_u64(&self, count: u64) {
         self.0.value.add_u64(count);
+        self.touch();
     }
 
     /// Measures the exeuction time of `f` and adds its duration to the counter in seconds.
@@ -105,10 +157,110 @@ impl Counter {
         result
     }
 
+    /// Returns a future which adds the elapsed time until `future` completes to this counter in seconds.
+    ///
+    /// The elapsed time is measured from the invocation of this method,
+    /// so it includes the time the future spends suspended.
+    #[cfg(feature = "async")]
+    pub fn time_async<F: Future>(&self, future: F) -> TimedFuture<F> {
+        TimedFuture::counter(future, self.clone())
+    }
+
+    /// Returns `true` if this counter is exposed by `Gatherer`, otherwise `false`.
+    ///
+    /// A counter is enabled when it is built.
+    pub fn is_enabled(&self) -> bool {
+        self.0.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Enables or disables the exposition of this counter.
+    ///
+    /// The collector of a disabled counter produces no metrics,
+    /// but the value of the counter is kept and can still be updated.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.0.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if this counter has been updated since it was built, otherwise `false`.
+    ///
+    /// This is useful for detecting code paths that are expected to update the counter but never do.
+    pub fn observed(&self) -> bool {
+        self.0.observed.load(Ordering::Relaxed)
+    }
+
+    // Makes a detached copy of this counter and resets this to zero,
+    // if this has been built with `CounterBuilder::reset_on_collect`.
+    pub(crate) fn take_if_reset_on_collect(&self) -> Option<Counter> {
+        if self.0.reset_on_collect {
+            Some(Counter(Arc::new(self.0.take())))
+        } else {
+            None
+        }
+    }
+
     /// Returns a collector for this counter.
     pub fn collector(&self) -> CounterCollector {
         CounterCollector(Arc::downgrade(&self.0))
     }
+
+    /// Returns the number of live handles sharing the state of this counter.
+    ///
+    /// This is a debugging aid (e.g., for chasing leaked per-request counters) and
+    /// equals to `Arc::strong_count` of the shared state.
+    /// Note that the count also includes transient handles held by collectors while gathering,
+    /// as well as the ones held by gathered `MetricFamilies` until they are dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let counter = CounterBuilder::new("foo").finish().unwrap();
+    /// assert_eq!(counter.handle_count(), 1);
+    ///
+    /// let cloned = counter.clone();
+    /// assert_eq!(counter.handle_count(), 2);
+    ///
+    /// drop(cloned);
+    /// assert_eq!(counter.handle_count(), 1);
+    /// ```
+    pub fn handle_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+
+    /// Writes the exposition line of this counter (with a trailing newline) to `writer`.
+    ///
+    /// The line is the same as the `Display` representation of this counter,
+    /// but is written directly to `writer` without building any intermediate `String`.
+    /// This is useful for exporting a large number of counters in a hot loop.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let counter = CounterBuilder::new("foo").label("bar", "baz").finish().unwrap();
+    /// counter.add_u64(3);
+    ///
+    /// let mut buf = Vec::new();
+    /// counter.write_sample(&mut buf).unwrap();
+    /// assert_eq!(buf, b"foo{bar=\"baz\"} 3\n");
+    /// ```
+    pub fn write_sample<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        // `io::Write::write_fmt` forwards the pieces of the `Display` output to `writer` as they are.
+        writeln!(writer, "{}", self)
+    }
+
+    // Records that this counter has been updated.
+    #[inline]
+    fn touch(&self) {
+        if !self.0.observed.load(Ordering::Relaxed) {
+            self.0.observed.store(true, Ordering::Relaxed);
+        }
+        if let Some(ref expiry) = self.0.expiry {
+            expiry.touch();
+        }
+    }
 }
 impl fmt::Display for Counter {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -133,6 +285,10 @@ pub struct CounterBuilder {
     help: Option<String>,
     labels: Vec<(String, String)>,
     registries: Vec<Registry>,
+    preserve_label_order: bool,
+    reset_on_collect: bool,
+    expire_after: Option<Duration>,
+    shards: usize,
 }
 impl CounterBuilder {
     /// Makes a builder for counters named `name`.
@@ -144,6 +300,10 @@ impl CounterBuilder {
             help: None,
             labels: Vec::new(),
             registries: Vec::new(),
+            preserve_label_order: false,
+            reset_on_collect: false,
+            expire_after: None,
+            shards: 1,
         }
     }
 
@@ -169,9 +329,40 @@ impl CounterBuilder {
     ///
     /// Note that `name` will be validated in the invocation of the `finish` method.
     pub fn label(&mut self, name: &str, value: &str) -> &mut Self {
+        if let Some(l) = self.labels.iter_mut().find(|l| l.0 == name) {
+            l.1 = value.to_string();
+        } else {
+            self.labels.push((name.to_string(), value.to_string()));
+        }
+        self
+    }
+
+    /// Adds labels.
+    ///
+    /// This is equivalent to calling `label` for each of the given name-value pairs.
+    pub fn labels<I, K, V>(&mut self, labels: I) -> &mut Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        for (name, value) in labels {
+            self.label(name.as_ref(), value.as_ref());
+        }
+        self
+    }
+
+    /// Removes the label named `name` if it has been added.
+    pub fn remove_label(&mut self, name: &str) -> &mut Self {
         self.labels.retain(|l| l.0 != name);
-        self.labels.push((name.to_string(), value.to_string()));
-        self.labels.sort();
+        self
+    }
+
+    /// Keeps the labels of the resulting counters in insertion order instead of sorting them by name.
+    ///
+    /// This also applies to the labels inserted via `labels_mut` after the counter is built.
+    pub fn preserve_label_order(&mut self) -> &mut Self {
+        self.preserve_label_order = true;
         self
     }
 
@@ -181,51 +372,226 @@ impl CounterBuilder {
         self
     }
 
+    /// Clears the current registries, then sets `registry` as the new one.
+    pub fn set_registry(&mut self, registry: Registry) -> &mut Self {
+        self.registries = vec![registry];
+        self
+    }
+
     /// Adds the default registry.
+    #[cfg(not(feature = "no-default-registry"))]
     pub fn default_registry(&mut self) -> &mut Self {
         self.registry(default_registry())
     }
 
+    /// Makes the resulting counters export deltas instead of cumulative values.
+    ///
+    /// Each time a counter is gathered by `Gatherer::gather_and_reset`,
+    /// its value is atomically read and reset to zero,
+    /// so the gathered value is the amount added since the previous reset.
+    /// Increments which race with the gathering are never lost; they are included in the next one.
+    /// Other ways of collecting the counter (e.g., `Gatherer::gather`) do not reset it.
+    ///
+    /// Note that Prometheus expects counters to be cumulative.
+    /// Functions like `rate()` and `increase()` regard every decrease of a counter as a reset,
+    /// so they produce meaningless results for delta counters.
+    /// This mode is intended for downstream systems which consume per-scrape deltas.
+    /// Also, if the counter is registered to multiple registries, each gatherer receives
+    /// only the part of the deltas which it happened to collect.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let gatherer = Gatherer::new();
+    /// let counter = CounterBuilder::new("foo")
+    ///     .reset_on_collect()
+    ///     .registry(gatherer.registry())
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// counter.add_u64(3);
+    /// assert_eq!(gatherer.gather().to_text(), "# TYPE foo counter\nfoo 3\n");
+    /// assert_eq!(gatherer.gather_and_reset().to_text(), "# TYPE foo counter\nfoo 3\n");
+    /// assert_eq!(gatherer.gather_and_reset().to_text(), "# TYPE foo counter\nfoo 0\n");
+    /// assert_eq!(counter.value(), 0.0);
+    /// ```
+    pub fn reset_on_collect(&mut self) -> &mut Self {
+        self.reset_on_collect = true;
+        self
+    }
+
+    /// Makes the resulting counters expire if they have not been updated for `ttl`.
+    ///
+    /// The collector of an expired counter reports it as dead,
+    /// so the counter is removed from the registries when it is gathered next time
+    /// and is never exposed afterwards, even if it is updated again.
+    ///
+    /// By default, counters never expire.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let gatherer = Gatherer::new();
+    /// let counter = CounterBuilder::new("foo")
+    ///     .expire_after(Duration::from_millis(10))
+    ///     .registry(gatherer.registry())
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// counter.increment();
+    /// assert_eq!(gatherer.gather().to_text(), "# TYPE foo counter\nfoo 1\n");
+    ///
+    /// thread::sleep(Duration::from_millis(20));
+    /// assert_eq!(gatherer.gather().to_text(), "");
+    /// ```
+    pub fn expire_after(&mut self, ttl: Duration) -> &mut Self {
+        self.expire_after = Some(ttl);
+        self
+    }
+
+    /// Makes the resulting counters spread their values over `shards` slots.
+    ///
+    /// Each thread updates one of the slots (each of which occupies its own cache line),
+    /// and the slots are summed when the value is read.
+    /// This reduces the contention between threads that update the same counter heavily,
+    /// at the cost of memory and slower reads.
+    /// Note that fractional parts of the values given to `Counter::add` are not sharded.
+    ///
+    /// The default value is `1` (i.e., no sharding).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::thread;
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let counter = CounterBuilder::new("foo").sharded(8).finish().unwrap();
+    /// let handles = (0..4)
+    ///     .map(|_| {
+    ///         let counter = counter.clone();
+    ///         thread::spawn(move || {
+    ///             for _ in 0..100 {
+    ///                 counter.increment();
+    ///             }
+    ///         })
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// for h in handles {
+    ///     h.join().unwrap();
+    /// }
+    /// assert_eq!(counter.value(), 400.0);
+    /// ```
+    pub fn sharded(&mut self, shards: usize) -> &mut Self {
+        self.shards = shards;
+        self
+    }
+
+    /// Validates the settings of this builder without building a counter.
+    ///
+    /// This performs the same checks as `finish`, but does not register anything.
+    ///
+    /// # Errors
+    ///
+    /// This method will return `Err(_)` if and only if `finish` would fail
+    /// for reasons other than the registration to the registries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// assert!(CounterBuilder::new("foo").label("bar", "baz").validate().is_ok());
+    /// assert!(CounterBuilder::new("foo").label("b-r", "baz").validate().is_err());
+    /// assert!(CounterBuilder::new("0foo").validate().is_err());
+    /// ```
+    pub fn validate(&self) -> Result<()> {
+        track!(self.check_shards())?;
+        track!(self.build_name())?;
+        track!(self.build_labels())?;
+        Ok(())
+    }
+
     /// Builds a counter.
     ///
     /// # Errors
     ///
-    /// This method will return `Err(_)` if any of the name of the metric or labels is malformed.
+    /// This method will return `Err(_)` if one of the following conditions is satisfied:
+    ///
+    /// - Any of the name of the metric or labels is malformed
+    /// - `sharded` has been specified with `0`
+    /// - Any of the registries has lost its gatherer (`ErrorKind::RegistrationFailed`)
+    ///
+    /// In the latter case, the counter is dropped, so it never appears in the other registries either.
     pub fn finish(&self) -> Result<Counter> {
-        let name = track!(MetricName::new(
-            self.namespace.as_ref().map(AsRef::as_ref),
-            self.subsystem.as_ref().map(AsRef::as_ref),
-            &self.name,
-        ))?;
-        let labels = track!(self
-            .labels
-            .iter()
-            .map(|&(ref name, ref value)| track!(Label::new(name, value)))
-            .collect::<Result<_>>())?;
+        track!(self.check_shards())?;
+        let name = track!(self.build_name())?;
+        let labels = track!(self.build_labels())?;
         let inner = Inner {
             name,
-            labels: Labels::new(labels),
+            labels: Labels::new(labels, self.preserve_label_order),
             help: self.help.clone(),
             timestamp: Timestamp::new(),
-            value: Value::new(),
+            value: Value::new(self.shards),
+            reset_on_collect: self.reset_on_collect,
+            expiry: self.expire_after.map(Expiry::new),
+            enabled: AtomicBool::new(true),
+            observed: AtomicBool::new(false),
         };
         let counter = Counter(Arc::new(inner));
-        for r in &self.registries {
-            r.register(counter.collector());
-        }
+        track!(Registry::register_to_all(&self.registries, || counter.collector()))?;
         Ok(counter)
     }
+
+    fn check_shards(&self) -> Result<()> {
+        track_assert_ne!(self.shards, 0, ErrorKind::InvalidInput);
+        Ok(())
+    }
+
+    fn build_name(&self) -> Result<MetricName> {
+        track!(MetricName::new(
+            self.namespace.as_ref().map(AsRef::as_ref),
+            self.subsystem.as_ref().map(AsRef::as_ref),
+            &self.name,
+        ))
+    }
+
+    fn build_labels(&self) -> Result<LabelVec> {
+        let mut labels: LabelVec = track!(self
+            .labels
+            .iter()
+            .map(|(name, value)| track!(Label::new(name, value)))
+            .collect::<Result<_>>())?;
+        if !self.preserve_label_order {
+            labels.sort();
+        }
+        Ok(labels)
+    }
 }
 
 /// `Collect` trait implmentation for `Counter`.
 #[derive(Debug)]
 pub struct CounterCollector(Weak<Inner>);
 impl Collect for CounterCollector {
-    type Metrics = iter::Once<Metric>;
+    type Metrics = option::IntoIter<Metric>;
     fn collect(&mut self) -> Option<Self::Metrics> {
-        self.0
-            .upgrade()
-            .map(|inner| iter::once(Metric::Counter(Counter(inner))))
+        let inner = self.0.upgrade()?;
+        if let Some(ref expiry) = inner.expiry {
+            if expiry.is_expired() {
+                return None;
+            }
+        }
+        if !inner.enabled.load(Ordering::Relaxed) {
+            return Some(None.into_iter());
+        }
+        Some(Some(Metric::Counter(Counter(inner))).into_iter())
     }
 }
 
@@ -236,37 +602,106 @@ struct Inner {
     help: Option<String>,
     timestamp: Timestamp,
     value: Value,
+    reset_on_collect: bool,
+    expiry: Option<Expiry>,
+    enabled: AtomicBool,
+    observed: AtomicBool,
+}
+impl Inner {
+    /// Makes a detached copy of this and resets the value of this to zero.
+    fn take(&self) -> Self {
+        Inner {
+            name: self.name.clone(),
+            labels: self.labels.detached(),
+            help: self.help.clone(),
+            timestamp: Timestamp::from_value(self.timestamp.get()),
+            value: self.value.take(),
+            reset_on_collect: false,
+            expiry: None,
+            enabled: AtomicBool::new(true),
+            observed: AtomicBool::new(self.observed.load(Ordering::Relaxed)),
+        }
+    }
 }
 
+#[derive(Debug)]
+struct Expiry {
+    ttl: Duration,
+    origin: Instant,
+
+    // Milliseconds elapsed from `origin` until the last update
+    last_updated: AtomicU64,
+}
+impl Expiry {
+    fn new(ttl: Duration) -> Self {
+        Expiry {
+            ttl,
+            origin: Instant::now(),
+            last_updated: AtomicU64::new(0),
+        }
+    }
+
+    fn touch(&self) {
+        self.last_updated
+            .set(self.origin.elapsed().as_millis() as u64);
+    }
+
+    fn is_expired(&self) -> bool {
+        let last_updated = Duration::from_millis(self.last_updated.get());
+        self.origin.elapsed().saturating_sub(last_updated) > self.ttl
+    }
+}
+
+// 2^64
+const U64_LIMIT: f64 = 18_446_744_073_709_551_616.0;
+
 #[derive(Debug)]
 struct Value {
-    f64: AtomicF64,
-    u64: AtomicU64,
+    f64: AtomicCompensatedF64,
+    u64: Box<[Shard]>,
 }
 impl Value {
-    fn new() -> Self {
+    fn new(shards: usize) -> Self {
         Value {
-            f64: AtomicF64::new(0.0),
-            u64: AtomicU64::new(0),
+            f64: AtomicCompensatedF64::new(0.0),
+            u64: (0..shards).map(|_| Shard(AtomicU64::new(0))).collect(),
         }
     }
 
     #[inline]
     fn get(&self) -> f64 {
-        self.f64.get() + self.u64.get() as f64
+        let u64 = self
+            .u64
+            .iter()
+            .fold(0u64, |acc, s| acc.wrapping_add(s.0.get()));
+        self.f64.get() + u64 as f64
+    }
+
+    #[inline]
+    fn shard(&self) -> &AtomicU64 {
+        if self.u64.len() == 1 {
+            &self.u64[0].0
+        } else {
+            &self.u64[THREAD_INDEX.with(|i| *i) % self.u64.len()].0
+        }
     }
 
     #[inline]
     fn increment(&self) {
-        self.u64.inc();
+        self.shard().inc();
     }
 
     #[inline]
     fn add(&self, count: f64) {
-        let floor = count.floor() as u64;
-        let ceil = count.ceil() as u64;
-        if floor == ceil {
-            self.u64.add(floor);
+        if count < U64_LIMIT {
+            let whole = count.trunc();
+            if whole > 0.0 {
+                self.shard().add(whole as u64);
+            }
+            let fraction = count - whole;
+            if fraction > 0.0 {
+                self.f64.add(fraction);
+            }
         } else {
             self.f64.add(count);
         }
@@ -274,12 +709,36 @@ impl Value {
 
     #[inline]
     fn add_u64(&self, count: u64) {
-        self.u64.add(count);
+        self.shard().add(count);
+    }
+
+    fn take(&self) -> Self {
+        Value {
+            f64: AtomicCompensatedF64::new(self.f64.swap(0.0)),
+            u64: self
+                .u64
+                .iter()
+                .map(|s| Shard(AtomicU64::new(s.0.swap(0))))
+                .collect(),
+        }
     }
 }
 
+// A slot of `Value`, aligned to a cache line so that updating it does not disturb the other slots.
+#[derive(Debug)]
+#[repr(align(64))]
+struct Shard(AtomicU64);
+
+static NEXT_THREAD_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_INDEX: usize = NEXT_THREAD_INDEX.fetch_add(1, Ordering::Relaxed);
+}
+
 #[cfg(test)]
 mod test {
+    use std::collections::HashMap;
+
     use super::*;
 
     #[test]
@@ -310,4 +769,186 @@ mod test {
             r#"test_counter_foo_total{bar="baz"} 8.45"#
         );
     }
+
+    #[test]
+    fn rendered_labels_follow_modifications() {
+        let mut counter =
+            track_try_unwrap!(CounterBuilder::new("foo").label("bar", "baz").finish());
+        assert_eq!(counter.to_string(), r#"foo{bar="baz"} 0"#);
+
+        counter.labels_mut().insert("a", "b").unwrap();
+        counter.labels_mut().remove("bar");
+        assert_eq!(counter.to_string(), r#"foo{a="b"} 0"#);
+        counter.labels_mut().clear();
+        assert_eq!(counter.to_string(), "foo 0");
+    }
+
+    #[test]
+    fn nan_is_rejected() {
+        let counter = track_try_unwrap!(CounterBuilder::new("foo").finish());
+        counter.add(1.0).unwrap();
+        assert_eq!(
+            counter.add(std::f64::NAN).err().map(|e| *e.kind()),
+            Some(ErrorKind::InvalidInput)
+        );
+        assert_eq!(
+            counter.add(-1.0).err().map(|e| *e.kind()),
+            Some(ErrorKind::InvalidInput)
+        );
+        assert_eq!(counter.value(), 1.0);
+
+        counter.add(std::f64::INFINITY).unwrap();
+        assert_eq!(counter.value(), std::f64::INFINITY);
+    }
+
+    #[test]
+    fn labels_works() {
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), "3".to_string());
+        let counter = track_try_unwrap!(CounterBuilder::new("foo")
+            .label("a", "0")
+            .labels(vec![("c", "1"), ("b", "2")])
+            .labels(&map)
+            .finish());
+        assert_eq!(counter.to_string(), r#"foo{a="3",b="2",c="1"} 0"#);
+
+        assert!(CounterBuilder::new("foo")
+            .labels(vec![("__reserved", "0")])
+            .finish()
+            .is_err());
+    }
+
+    #[test]
+    fn reset_on_collect_works() {
+        use std::thread;
+
+        let gatherer = ::Gatherer::new();
+        let counter = track_try_unwrap!(CounterBuilder::new("foo")
+            .reset_on_collect()
+            .registry(gatherer.registry())
+            .finish());
+        let handle = {
+            let counter = counter.clone();
+            thread::spawn(move || {
+                for _ in 0..10_000 {
+                    counter.increment();
+                }
+            })
+        };
+
+        let mut total = 0.0;
+        while !handle.is_finished() {
+            for f in gatherer.gather_and_reset() {
+                if let ::metric::Metrics::Counter(ref v) = *f.metrics() {
+                    total += v[0].value();
+                }
+            }
+        }
+        handle.join().unwrap();
+        total += counter.value();
+        assert_eq!(total, 10_000.0);
+    }
+
+    #[test]
+    fn reset_on_collect_keeps_label_order() {
+        let gatherer = ::Gatherer::new();
+        let _counter = track_try_unwrap!(CounterBuilder::new("foo")
+            .label("b", "1")
+            .label("a", "2")
+            .preserve_label_order()
+            .reset_on_collect()
+            .registry(gatherer.registry())
+            .finish());
+        assert_eq!(
+            gatherer.gather_and_reset().to_text(),
+            "# TYPE foo counter\nfoo{b=\"1\",a=\"2\"} 0\n"
+        );
+    }
+
+    #[test]
+    fn set_enabled_works() {
+        let counter = track_try_unwrap!(CounterBuilder::new("foo").finish());
+        let mut collector = counter.collector();
+        assert!(counter.is_enabled());
+
+        counter.set_enabled(false);
+        counter.increment();
+        assert_eq!(collector.collect().map(|m| m.count()), Some(0));
+
+        counter.set_enabled(true);
+        assert_eq!(collector.collect().map(|m| m.count()), Some(1));
+        assert_eq!(counter.value(), 1.0);
+    }
+
+    #[test]
+    fn fractional_adds_do_not_drift() {
+        let counter = track_try_unwrap!(CounterBuilder::new("foo").finish());
+        for _ in 0..1_000_000 {
+            counter.add(0.1).unwrap();
+        }
+        assert!((counter.value() - 100_000.0).abs() < 1e-9);
+
+        counter.add(1e20).unwrap();
+        assert_eq!(counter.value(), 1e20 + 100_000.0);
+    }
+
+    #[test]
+    fn expire_after_works() {
+        use std::thread;
+
+        let counter = track_try_unwrap!(CounterBuilder::new("foo")
+            .expire_after(Duration::from_millis(50))
+            .finish());
+        let mut collector = counter.collector();
+        for _ in 0..3 {
+            thread::sleep(Duration::from_millis(30));
+            counter.increment();
+            assert!(collector.collect().is_some());
+        }
+
+        thread::sleep(Duration::from_millis(80));
+        assert!(collector.collect().is_none());
+    }
+
+    #[test]
+    fn observed_works() {
+        let counter = track_try_unwrap!(CounterBuilder::new("foo").finish());
+        assert!(!counter.observed());
+        assert!(counter.add(-1.0).is_err());
+        assert!(!counter.observed());
+        counter.add_u64(0);
+        assert!(counter.observed());
+    }
+
+    #[test]
+    fn sharded_works() {
+        let gatherer = ::Gatherer::new();
+        let counter = track_try_unwrap!(CounterBuilder::new("foo")
+            .sharded(4)
+            .reset_on_collect()
+            .registry(gatherer.registry())
+            .finish());
+        let handles = (0..8)
+            .map(|_| {
+                let counter = counter.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        counter.increment();
+                    }
+                    counter.add(1.5).unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(counter.value(), 8012.0);
+        assert_eq!(
+            gatherer.gather_and_reset().to_text(),
+            "# TYPE foo counter\nfoo 8012\n"
+        );
+        assert_eq!(counter.value(), 0.0);
+
+        assert!(CounterBuilder::new("foo").sharded(0).finish().is_err());
+    }
 }
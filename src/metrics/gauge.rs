@@ -1,14 +1,21 @@
 use std::fmt;
-use std::iter;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::io;
+use std::option;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Weak};
-use std::time::Instant;
+use std::time::{Instant, SystemTime};
 
-use atomic::AtomicF64;
+use atomic::{AtomicF64, AtomicI64};
+#[cfg(not(feature = "no-default-registry"))]
 use default_registry;
-use label::{Label, Labels, LabelsMut};
+use label::{Label, LabelVec, Labels, LabelsMut};
 use metric::{Metric, MetricName, MetricValue};
+#[cfg(feature = "async")]
+use metrics::TimedFuture;
 use timestamp::{self, Timestamp, TimestampMut};
-use {Collect, Registry, Result};
+use {Collect, ErrorKind, Registry, Result};
 
 /// `Gauge` is a metric that represents a single numerical value that can arbitrarily go up and down.
 ///
@@ -59,42 +66,190 @@ impl Gauge {
         self.0.value.get()
     }
 
-    /// Increments this gauge.
+    /// Returns the value of this gauge as an integer.
+    ///
+    /// If this gauge has not been built with `GaugeBuilder::integer`,
+    /// the value is converted from `f64` (the fractional part is truncated).
+    #[inline]
+    pub fn value_i64(&self) -> i64 {
+        self.0.value.get_i64()
+    }
+
+    /// Increments this gauge and returns the updated value.
+    #[inline]
+    pub fn increment(&self) -> f64 {
+        self.add(1.0)
+    }
+
+    /// Adds `count` to this gauge and returns the updated value.
+    ///
+    /// If this gauge has been built with `GaugeBuilder::reject_nan` and `count` is `NaN`,
+    /// this method does nothing and returns the current value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metrics::GaugeBuilder;
+    ///
+    /// let gauge = GaugeBuilder::new("foo").finish().unwrap();
+    /// assert_eq!(gauge.add(2.0), 2.0);
+    /// assert_eq!(gauge.increment(), 3.0);
+    /// assert_eq!(gauge.subtract(0.5), 2.5);
+    /// assert_eq!(gauge.decrement(), 1.5);
+    /// ```
     #[inline]
-    pub fn increment(&self) {
-        self.add(1.0);
+    pub fn add(&self, count: f64) -> f64 {
+        if self.0.reject_nan && count.is_nan() {
+            self.0.value.get()
+        } else {
+            self.mark_observed();
+            self.0.value.add(count)
+        }
     }
 
     /// Adds `count` to this gauge.
+    ///
+    /// # Errors
+    ///
+    /// If this gauge has been built with `GaugeBuilder::reject_nan` and `count` is `NaN`,
+    /// this method returns `ErrorKind::InvalidInput` error.
     #[inline]
-    pub fn add(&self, count: f64) {
+    pub fn try_add(&self, count: f64) -> Result<()> {
+        track!(self.check_nan(count))?;
         self.0.value.add(count);
+        self.mark_observed();
+        Ok(())
+    }
+
+    /// Adds `count` to this gauge and returns the updated value.
+    ///
+    /// If this gauge has been built with `GaugeBuilder::integer`,
+    /// the addition is performed without converting the value to `f64`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metrics::GaugeBuilder;
+    ///
+    /// let gauge = GaugeBuilder::new("foo").integer().finish().unwrap();
+    /// gauge.set_i64(1 << 53);
+    /// assert_eq!(gauge.add_i64(1), (1 << 53) + 1);
+    /// assert_eq!(gauge.to_string(), "foo 9007199254740993");
+    /// ```
+    #[inline]
+    pub fn add_i64(&self, count: i64) -> i64 {
+        self.mark_observed();
+        self.0.value.add_i64(count)
     }
 
-    /// Decrements this gauge.
+    /// Decrements this gauge and returns the updated value.
     #[inline]
-    pub fn decrement(&self) {
-        self.add(-1.0);
+    pub fn decrement(&self) -> f64 {
+        self.add(-1.0)
     }
 
-    /// Subtracts `count` from this gauge.
+    /// Subtracts `count` from this gauge and returns the updated value.
     #[inline]
-    pub fn subtract(&self, count: f64) {
-        self.add(-count);
+    pub fn subtract(&self, count: f64) -> f64 {
+        self.add(-count)
     }
 
     /// Sets this gauge to `value`.
+    ///
+    /// If this gauge has been built with `GaugeBuilder::reject_nan` and `value` is `NaN`,
+    /// this method does nothing.
     #[inline]
     pub fn set(&self, value: f64) {
+        if !(self.0.reject_nan && value.is_nan()) {
+            self.0.value.set(value);
+            self.mark_observed();
+        }
+    }
+
+    /// Sets this gauge to `value`.
+    ///
+    /// If this gauge has been built with `GaugeBuilder::integer`,
+    /// the value is stored without being converted to `f64`.
+    #[inline]
+    pub fn set_i64(&self, value: i64) {
+        self.0.value.set_i64(value);
+        self.mark_observed();
+    }
+
+    /// Sets this gauge to `value`.
+    ///
+    /// # Errors
+    ///
+    /// If this gauge has been built with `GaugeBuilder::reject_nan` and `value` is `NaN`,
+    /// this method returns `ErrorKind::InvalidInput` error.
+    #[inline]
+    pub fn try_set(&self, value: f64) -> Result<()> {
+        track!(self.check_nan(value))?;
         self.0.value.set(value);
+        self.mark_observed();
+        Ok(())
+    }
+
+    /// Sets this gauge to `value` and its timestamp to `timestamp`.
+    ///
+    /// If this gauge has been built with `GaugeBuilder::reject_nan` and `value` is `NaN`,
+    /// neither the value nor the timestamp is updated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// use prometrics::metrics::GaugeBuilder;
+    ///
+    /// let gauge = GaugeBuilder::new("foo").finish().unwrap();
+    /// gauge.set_with_timestamp(1.5, UNIX_EPOCH + Duration::from_secs(10));
+    /// assert_eq!(gauge.to_string(), "foo 1.5 10000");
+    /// ```
+    #[inline]
+    pub fn set_with_timestamp(&self, value: f64, timestamp: SystemTime) {
+        if !(self.0.reject_nan && value.is_nan()) {
+            self.0.value.set(value);
+            self.mark_observed();
+            TimestampMut::new(&self.0.timestamp).set_time(timestamp);
+        }
+    }
+
+    /// Sets this gauge to `value` and its timestamp to the current time.
+    ///
+    /// This is equivalent to `self.set_with_timestamp(value, SystemTime::now())`.
+    #[inline]
+    pub fn set_now(&self, value: f64) {
+        self.set_with_timestamp(value, SystemTime::now());
     }
 
     /// Sets this gauge to the current unixtime in seconds.
+    ///
+    /// Note that the unit differs from that of `Timestamp` (milliseconds).
+    /// Use `set_to_current_time_millis` to obtain a value comparable with timestamps.
     #[inline]
     pub fn set_to_current_time(&self) {
         self.set(timestamp::now_unixtime_seconds());
     }
 
+    /// Sets this gauge to the current unixtime in milliseconds.
+    ///
+    /// The unit of the value is the same as that of `Timestamp`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metrics::GaugeBuilder;
+    ///
+    /// let gauge = GaugeBuilder::new("foo").finish().unwrap();
+    /// gauge.set_to_current_time_millis();
+    /// gauge.timestamp_mut().set_now();
+    /// assert!(gauge.timestamp().get().unwrap() as f64 - gauge.value() < 1000.0);
+    /// ```
+    #[inline]
+    pub fn set_to_current_time_millis(&self) {
+        self.set(timestamp::now_unixtime_millis() as f64);
+    }
+
     /// Tracks in-progress processings in some piece of code/function.
     ///
     /// # Examples
@@ -135,10 +290,80 @@ impl Gauge {
         result
     }
 
+    /// Returns a future which sets this gauge to the elapsed time until `future` completes in seconds.
+    ///
+    /// The elapsed time is measured from the invocation of this method,
+    /// so it includes the time the future spends suspended.
+    #[cfg(feature = "async")]
+    pub fn time_async<F: Future>(&self, future: F) -> TimedFuture<F> {
+        TimedFuture::gauge(future, self.clone())
+    }
+
+    /// Returns `true` if this gauge is exposed by `Gatherer`, otherwise `false`.
+    ///
+    /// A gauge is enabled when it is built.
+    pub fn is_enabled(&self) -> bool {
+        self.0.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Enables or disables the exposition of this gauge.
+    ///
+    /// The collector of a disabled gauge produces no metrics,
+    /// but the value of the gauge is kept and can still be updated.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.0.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if this gauge has been updated since it was built, otherwise `false`.
+    ///
+    /// The initial value given by `GaugeBuilder::initial_value` is not regarded as an update.
+    pub fn observed(&self) -> bool {
+        self.0.observed.load(Ordering::Relaxed)
+    }
+
     /// Returns a collector for this gauge.
     pub fn collector(&self) -> GaugeCollector {
         GaugeCollector(Arc::downgrade(&self.0))
     }
+
+    /// Returns the number of live handles sharing the state of this gauge.
+    ///
+    /// See `Counter::handle_count` for the caveats.
+    pub fn handle_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+
+    /// Writes the exposition line of this gauge (with a trailing newline) to `writer`.
+    ///
+    /// See `Counter::write_sample` for more details.
+    pub fn write_sample<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        // `io::Write::write_fmt` forwards the pieces of the `Display` output to `writer` as they are.
+        writeln!(writer, "{}", self)
+    }
+
+    /// Returns the value of this gauge if it has been built with `GaugeBuilder::integer`.
+    pub(crate) fn integer_value(&self) -> Option<i64> {
+        match self.0.value {
+            Value::Integer(ref v) => Some(v.get()),
+            Value::Float(_) => None,
+        }
+    }
+
+    #[inline]
+    fn mark_observed(&self) {
+        if !self.0.observed.load(Ordering::Relaxed) {
+            self.0.observed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn check_nan(&self, value: f64) -> Result<()> {
+        track_assert!(
+            !(self.0.reject_nan && value.is_nan()),
+            ErrorKind::InvalidInput,
+            "NaN is not allowed"
+        );
+        Ok(())
+    }
 }
 impl fmt::Display for Gauge {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -146,7 +371,11 @@ impl fmt::Display for Gauge {
         if !self.labels().is_empty() {
             write!(f, "{}", self.labels())?;
         }
-        write!(f, " {}", MetricValue(self.value()))?;
+        if let Some(value) = self.integer_value() {
+            write!(f, " {}", value)?;
+        } else {
+            write!(f, " {}", MetricValue(self.value()))?;
+        }
         if let Some(timestamp) = self.timestamp().get() {
             write!(f, " {}", timestamp)?;
         }
@@ -163,7 +392,10 @@ pub struct GaugeBuilder {
     help: Option<String>,
     labels: Vec<(String, String)>,
     initial_value: f64,
+    reject_nan: bool,
+    integer: bool,
     registries: Vec<Registry>,
+    preserve_label_order: bool,
 }
 impl GaugeBuilder {
     /// Makes a builder for gauges named `name`.
@@ -175,7 +407,10 @@ impl GaugeBuilder {
             help: None,
             labels: Vec::new(),
             initial_value: 0.0,
+            reject_nan: false,
+            integer: false,
             registries: Vec::new(),
+            preserve_label_order: false,
         }
     }
 
@@ -201,9 +436,40 @@ impl GaugeBuilder {
     ///
     /// Note that `name` will be validated in the invocation of the `finish` method.
     pub fn label(&mut self, name: &str, value: &str) -> &mut Self {
+        if let Some(l) = self.labels.iter_mut().find(|l| l.0 == name) {
+            l.1 = value.to_string();
+        } else {
+            self.labels.push((name.to_string(), value.to_string()));
+        }
+        self
+    }
+
+    /// Adds labels.
+    ///
+    /// This is equivalent to calling `label` for each of the given name-value pairs.
+    pub fn labels<I, K, V>(&mut self, labels: I) -> &mut Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        for (name, value) in labels {
+            self.label(name.as_ref(), value.as_ref());
+        }
+        self
+    }
+
+    /// Removes the label named `name` if it has been added.
+    pub fn remove_label(&mut self, name: &str) -> &mut Self {
         self.labels.retain(|l| l.0 != name);
-        self.labels.push((name.to_string(), value.to_string()));
-        self.labels.sort();
+        self
+    }
+
+    /// Keeps the labels of the resulting gauges in insertion order instead of sorting them by name.
+    ///
+    /// This also applies to the labels inserted via `labels_mut` after the gauge is built.
+    pub fn preserve_label_order(&mut self) -> &mut Self {
+        self.preserve_label_order = true;
         self
     }
 
@@ -213,7 +479,14 @@ impl GaugeBuilder {
         self
     }
 
+    /// Clears the current registries, then sets `registry` as the new one.
+    pub fn set_registry(&mut self, registry: Registry) -> &mut Self {
+        self.registries = vec![registry];
+        self
+    }
+
     /// Adds the default registry.
+    #[cfg(not(feature = "no-default-registry"))]
     pub fn default_registry(&mut self) -> &mut Self {
         self.registry(default_registry())
     }
@@ -224,46 +497,118 @@ impl GaugeBuilder {
         self
     }
 
+    /// Makes resulting gauges reject `NaN` values.
+    ///
+    /// `Gauge::set` and `Gauge::add` ignore `NaN` values,
+    /// and `Gauge::try_set` and `Gauge::try_add` return errors for them.
+    ///
+    /// By default, `NaN` values are accepted as any other values.
+    pub fn reject_nan(&mut self) -> &mut Self {
+        self.reject_nan = true;
+        self
+    }
+
+    /// Makes resulting gauges hold their values as `i64` instead of `f64`.
+    ///
+    /// Integer gauges keep exact values beyond `2^53`, and are exposed without fractional parts.
+    /// The `f64` values given to `Gauge::set` and `Gauge::add` (and the initial value)
+    /// are truncated toward zero, and `NaN` is treated as zero.
+    pub fn integer(&mut self) -> &mut Self {
+        self.integer = true;
+        self
+    }
+
+    /// Validates the settings of this builder without building a gauge.
+    ///
+    /// This performs the same checks as `finish`, but does not register anything.
+    ///
+    /// # Errors
+    ///
+    /// This method will return `Err(_)` if and only if `finish` would fail
+    /// for reasons other than the registration to the registries.
+    pub fn validate(&self) -> Result<()> {
+        track!(self.check_initial_value())?;
+        track!(self.build_name())?;
+        track!(self.build_labels())?;
+        Ok(())
+    }
+
     /// Builds a gauge.
     ///
     /// # Errors
     ///
-    /// This method will return `Err(_)` if any of the name of the metric or labels is malformed.
+    /// This method will return `Err(_)` if one of the following conditions is satisfied:
+    ///
+    /// - Any of the name of the metric or labels is malformed
+    /// - `reject_nan` has been specified and the initial value is `NaN`
+    /// - Any of the registries has lost its gatherer (`ErrorKind::RegistrationFailed`)
+    ///
+    /// In the latter case, the gauge is dropped, so it never appears in the other registries either.
     pub fn finish(&self) -> Result<Gauge> {
-        let name = track!(MetricName::new(
-            self.namespace.as_ref().map(AsRef::as_ref),
-            self.subsystem.as_ref().map(AsRef::as_ref),
-            &self.name,
-        ))?;
-        let labels = track!(self
-            .labels
-            .iter()
-            .map(|&(ref name, ref value)| track!(Label::new(name, value)))
-            .collect::<Result<_>>())?;
+        track!(self.check_initial_value())?;
+        let name = track!(self.build_name())?;
+        let labels = track!(self.build_labels())?;
         let inner = Inner {
             name,
-            labels: Labels::new(labels),
+            labels: Labels::new(labels, self.preserve_label_order),
             help: self.help.clone(),
             timestamp: Timestamp::new(),
-            value: AtomicF64::new(self.initial_value),
+            value: if self.integer {
+                Value::Integer(AtomicI64::new(self.initial_value as i64))
+            } else {
+                Value::Float(AtomicF64::new(self.initial_value))
+            },
+            reject_nan: self.reject_nan,
+            enabled: AtomicBool::new(true),
+            observed: AtomicBool::new(false),
         };
         let gauge = Gauge(Arc::new(inner));
-        for r in &self.registries {
-            r.register(gauge.collector());
-        }
+        track!(Registry::register_to_all(&self.registries, || gauge.collector()))?;
         Ok(gauge)
     }
+
+    fn check_initial_value(&self) -> Result<()> {
+        track_assert!(
+            !(self.reject_nan && self.initial_value.is_nan()),
+            ErrorKind::InvalidInput,
+            "NaN is not allowed"
+        );
+        Ok(())
+    }
+
+    fn build_name(&self) -> Result<MetricName> {
+        track!(MetricName::new(
+            self.namespace.as_ref().map(AsRef::as_ref),
+            self.subsystem.as_ref().map(AsRef::as_ref),
+            &self.name,
+        ))
+    }
+
+    fn build_labels(&self) -> Result<LabelVec> {
+        let mut labels: LabelVec = track!(self
+            .labels
+            .iter()
+            .map(|(name, value)| track!(Label::new(name, value)))
+            .collect::<Result<_>>())?;
+        if !self.preserve_label_order {
+            labels.sort();
+        }
+        Ok(labels)
+    }
 }
 
 /// `Collect` trait implmentation for `Gauge`.
 #[derive(Debug, Clone)]
 pub struct GaugeCollector(Weak<Inner>);
 impl Collect for GaugeCollector {
-    type Metrics = iter::Once<Metric>;
+    type Metrics = option::IntoIter<Metric>;
     fn collect(&mut self) -> Option<Self::Metrics> {
-        self.0
-            .upgrade()
-            .map(|inner| iter::once(Metric::Gauge(Gauge(inner))))
+        self.0.upgrade().map(|inner| {
+            let enabled = inner.enabled.load(Ordering::Relaxed);
+            Some(Metric::Gauge(Gauge(inner)))
+                .filter(|_| enabled)
+                .into_iter()
+        })
     }
 }
 
@@ -273,7 +618,65 @@ struct Inner {
     labels: Labels,
     help: Option<String>,
     timestamp: Timestamp,
-    value: AtomicF64,
+    value: Value,
+    reject_nan: bool,
+    enabled: AtomicBool,
+    observed: AtomicBool,
+}
+
+#[derive(Debug)]
+enum Value {
+    Float(AtomicF64),
+    Integer(AtomicI64),
+}
+impl Value {
+    #[inline]
+    fn get(&self) -> f64 {
+        match *self {
+            Value::Float(ref v) => v.get(),
+            Value::Integer(ref v) => v.get() as f64,
+        }
+    }
+
+    #[inline]
+    fn get_i64(&self) -> i64 {
+        match *self {
+            Value::Float(ref v) => v.get() as i64,
+            Value::Integer(ref v) => v.get(),
+        }
+    }
+
+    #[inline]
+    fn add(&self, count: f64) -> f64 {
+        match *self {
+            Value::Float(ref v) => v.add(count),
+            Value::Integer(ref v) => v.add(count as i64) as f64,
+        }
+    }
+
+    #[inline]
+    fn add_i64(&self, count: i64) -> i64 {
+        match *self {
+            Value::Float(ref v) => v.add(count as f64) as i64,
+            Value::Integer(ref v) => v.add(count),
+        }
+    }
+
+    #[inline]
+    fn set(&self, value: f64) {
+        match *self {
+            Value::Float(ref v) => v.set(value),
+            Value::Integer(ref v) => v.set(value as i64),
+        }
+    }
+
+    #[inline]
+    fn set_i64(&self, value: i64) {
+        match *self {
+            Value::Float(ref v) => v.set(value as f64),
+            Value::Integer(ref v) => v.set(value),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -293,4 +696,108 @@ mod test {
         gauge.labels_mut().insert("bar", "baz").unwrap();
         assert_eq!(gauge.to_string(), r#"test_foo{bar="baz"} 2.34"#);
     }
+
+    #[test]
+    fn nan_works() {
+        let gauge = track_try_unwrap!(GaugeBuilder::new("foo").finish());
+        gauge.set(std::f64::NAN);
+        assert!(gauge.value().is_nan());
+        assert_eq!(gauge.to_string(), "foo NaN");
+        gauge.try_set(1.0).unwrap();
+        gauge.try_add(std::f64::NAN).unwrap();
+        assert!(gauge.value().is_nan());
+
+        let gauge = track_try_unwrap!(GaugeBuilder::new("foo").reject_nan().finish());
+        gauge.set(1.0);
+        gauge.set(std::f64::NAN);
+        gauge.add(std::f64::NAN);
+        assert_eq!(gauge.value(), 1.0);
+        assert_eq!(
+            gauge.try_set(std::f64::NAN).err().map(|e| *e.kind()),
+            Some(ErrorKind::InvalidInput)
+        );
+        assert_eq!(
+            gauge.try_add(std::f64::NAN).err().map(|e| *e.kind()),
+            Some(ErrorKind::InvalidInput)
+        );
+        assert_eq!(gauge.value(), 1.0);
+
+        assert!(GaugeBuilder::new("foo")
+            .reject_nan()
+            .initial_value(std::f64::NAN)
+            .finish()
+            .is_err());
+    }
+
+    #[test]
+    fn current_time_units_works() {
+        let gauge = track_try_unwrap!(GaugeBuilder::new("foo").finish());
+        let mut timestamp = gauge.timestamp_mut();
+
+        gauge.set_to_current_time();
+        timestamp.set_now();
+        let seconds = gauge.value();
+        let millis = timestamp.get().unwrap() as f64;
+        assert!((millis / 1000.0 - seconds).abs() < 1.0);
+
+        gauge.set_to_current_time_millis();
+        timestamp.set_now();
+        let millis = timestamp.get().unwrap() as f64;
+        assert!((millis - gauge.value()).abs() < 1000.0);
+    }
+
+    #[test]
+    fn set_with_timestamp_works() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let gauge = track_try_unwrap!(GaugeBuilder::new("foo").reject_nan().finish());
+        gauge.set_with_timestamp(3.0, UNIX_EPOCH + Duration::from_millis(1234));
+        assert_eq!(gauge.to_string(), "foo 3 1234");
+
+        gauge.set_with_timestamp(f64::NAN, UNIX_EPOCH);
+        assert_eq!(gauge.to_string(), "foo 3 1234");
+
+        gauge.set_now(4.0);
+        assert_eq!(gauge.value(), 4.0);
+        assert!(gauge.timestamp().get().unwrap() > 1234);
+    }
+
+    #[test]
+    fn integer_works() {
+        let gauge = track_try_unwrap!(GaugeBuilder::new("foo")
+            .integer()
+            .initial_value(2.7)
+            .finish());
+        assert_eq!(gauge.value_i64(), 2);
+
+        gauge.set_i64(i64::MAX - 1);
+        assert_eq!(gauge.add_i64(1), i64::MAX);
+        assert_eq!(gauge.to_string(), format!("foo {}", i64::MAX));
+
+        gauge.set(-3.9);
+        assert_eq!(gauge.value_i64(), -3);
+        assert_eq!(gauge.increment(), -2.0);
+        gauge.add(f64::NAN);
+        assert_eq!(gauge.value(), -2.0);
+
+        let gauge = track_try_unwrap!(GaugeBuilder::new("foo").finish());
+        gauge.set_i64(3);
+        assert_eq!(gauge.add_i64(2), 5);
+        gauge.set(1.5);
+        assert_eq!(gauge.value_i64(), 1);
+        assert_eq!(gauge.to_string(), "foo 1.5");
+    }
+
+    #[test]
+    fn observed_works() {
+        let gauge = track_try_unwrap!(GaugeBuilder::new("foo")
+            .initial_value(1.0)
+            .reject_nan()
+            .finish());
+        assert!(!gauge.observed());
+        gauge.set(f64::NAN);
+        assert!(!gauge.observed());
+        gauge.set(1.0);
+        assert!(gauge.observed());
+    }
 }
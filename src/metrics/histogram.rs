@@ -1,14 +1,22 @@
 use std;
+use std::borrow::Borrow;
 use std::fmt;
-use std::iter;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::option;
+use std::sync::atomic::{self, AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Weak};
-use std::time::Instant;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use atomic::{AtomicF64, AtomicU64};
-use bucket::{Bucket, CumulativeBuckets};
+use atomic::{AtomicCompensatedF64, AtomicU64};
+use bucket::{Bucket, CumulativeBucket, CumulativeBuckets};
+#[cfg(not(feature = "no-default-registry"))]
 use default_registry;
-use label::{Label, Labels, LabelsMut};
-use metric::{Metric, MetricName, MetricValue};
+use label::{Label, LabelVec, Labels, LabelsMut};
+use metric::{write_labels_with_reserved, BucketBound, Metric, MetricName, MetricValue};
+#[cfg(feature = "async")]
+use metrics::TimedFuture;
 use timestamp::{self, Timestamp, TimestampMut};
 use {Collect, ErrorKind, Registry, Result};
 
@@ -62,11 +70,25 @@ impl Histogram {
         &self.0.buckets
     }
 
+    /// Returns the upper bounds of the buckets of this histogram.
+    ///
+    /// The bounds are in ascending order and the last one is always `+Inf`.
+    pub fn bucket_bounds(&self) -> &[f64] {
+        &self.0.upper_bounds
+    }
+
     /// Returns the cumulative buckets of this histogram.
     pub fn cumulative_buckets(&self) -> CumulativeBuckets {
         CumulativeBuckets::new(&self.0.buckets)
     }
 
+    /// Returns the cumulative buckets of this histogram as an owned vector.
+    ///
+    /// Unlike `cumulative_buckets`, the result does not borrow this histogram.
+    pub fn cumulative_buckets_vec(&self) -> Vec<CumulativeBucket> {
+        self.cumulative_buckets().collect()
+    }
+
     /// Returns the total observation count.
     #[inline]
     pub fn count(&self) -> u64 {
@@ -79,17 +101,331 @@ impl Histogram {
         self.0.sum.get()
     }
 
+    /// Estimates the `q`-quantile (`0 <= q <= 1`) of the observed values.
+    ///
+    /// This uses the same algorithm as the `histogram_quantile()` function of Prometheus:
+    /// the bucket containing the quantile is located from the cumulative counts,
+    /// and the value is linearly interpolated between the bounds of the bucket
+    /// (the lower bound of the first bucket is assumed to be zero).
+    /// If the quantile falls into the `+Inf` bucket, the largest finite upper bound is returned.
+    ///
+    /// Note that the result is only an estimate whose accuracy is bounded by the bucket resolution.
+    ///
+    /// This method returns `None` if there are no observations,
+    /// if `q` is out of the range `[0, 1]`, or if this histogram only has the `+Inf` bucket.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metrics::HistogramBuilder;
+    ///
+    /// let histogram = HistogramBuilder::new("foo").buckets(vec![1.0, 2.0]).finish().unwrap();
+    /// assert_eq!(histogram.estimate_quantile(0.5), None);
+    ///
+    /// histogram.observe(0.5);
+    /// histogram.observe(1.5);
+    /// assert_eq!(histogram.estimate_quantile(0.5), Some(1.0));
+    /// assert_eq!(histogram.estimate_quantile(0.75), Some(1.5));
+    /// ```
+    pub fn estimate_quantile(&self, q: f64) -> Option<f64> {
+        if !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+        let buckets = self
+            .cumulative_buckets()
+            .map(|b| (b.upper_bound(), b.cumulative_count()))
+            .collect::<Vec<_>>();
+        let total = buckets.last().map_or(0, |b| b.1);
+        if total == 0 || buckets.len() < 2 {
+            return None;
+        }
+
+        let rank = q * total as f64;
+        let i = buckets
+            .iter()
+            .position(|b| b.1 > 0 && b.1 as f64 >= rank)
+            .expect("Never fails");
+        if i == buckets.len() - 1 {
+            return Some(buckets[i - 1].0);
+        }
+        if i == 0 && buckets[0].0 <= 0.0 {
+            return Some(buckets[0].0);
+        }
+
+        let (start, start_count) = if i == 0 { (0.0, 0) } else { buckets[i - 1] };
+        let (end, end_count) = buckets[i];
+        let count = (end_count - start_count) as f64;
+        Some(start + (end - start) * ((rank - start_count as f64) / count))
+    }
+
     /// Observes a value.
+    ///
+    /// How negative values are handled is determined by the builder of this histogram
+    /// (see `HistogramBuilder::reject_negative` and `HistogramBuilder::clamp_negative_to_zero`).
+    /// If negative values are rejected, they are ignored by this method.
+    ///
+    /// Any value convertible to `f64` (e.g., `u32`) is accepted.
+    ///
+    /// # Panics
+    ///
+    /// If `value` is `NaN`, this method will panic.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metrics::HistogramBuilder;
+    ///
+    /// let histogram = HistogramBuilder::new("foo").bucket(10.0).finish().unwrap();
+    /// histogram.observe(1.5);
+    /// histogram.observe(42u32);
+    /// assert_eq!(histogram.sum(), 43.5);
+    /// ```
+    #[inline]
+    pub fn observe<V: Into<f64>>(&self, value: V) {
+        self.observe_f64(value.into());
+    }
+
     #[inline]
-    pub fn observe(&self, value: f64) {
+    fn observe_f64(&self, value: f64) {
+        assert!(!value.is_nan());
+        if let Ok(value) = self.apply_limits(value) {
+            self.observe_unchecked(value);
+        }
+    }
+
+    /// Observes a value.
+    ///
+    /// # Errors
+    ///
+    /// If `value` is `NaN`, or if it is negative and the histogram has been built with
+    /// `HistogramBuilder::reject_negative`, this method returns `ErrorKind::InvalidInput` error.
+    #[inline]
+    pub fn try_observe(&self, value: f64) -> Result<()> {
+        track_assert!(
+            !value.is_nan(),
+            ErrorKind::InvalidInput,
+            "NaN is not allowed"
+        );
+        let value = track!(self.apply_limits(value))?;
+        self.observe_unchecked(value);
+        Ok(())
+    }
+
+    /// Observes a value and returns the index of the bucket (in `buckets()`) that was incremented.
+    ///
+    /// If the value is negative and rejected (see `HistogramBuilder::reject_negative`),
+    /// it is ignored and `None` is returned.
+    ///
+    /// # Panics
+    ///
+    /// If `value` is `NaN`, this method will panic.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metrics::HistogramBuilder;
+    ///
+    /// let histogram = HistogramBuilder::new("foo").buckets(vec![1.0, 2.0]).finish().unwrap();
+    /// assert_eq!(histogram.observe_indexed(0.5), Some(0));
+    /// assert_eq!(histogram.observe_indexed(2.0), Some(1));
+    /// assert_eq!(histogram.observe_indexed(3.0), Some(2));
+    /// assert_eq!(histogram.buckets()[2].upper_bound(), std::f64::INFINITY);
+    /// ```
+    pub fn observe_indexed(&self, value: f64) -> Option<usize> {
+        assert!(!value.is_nan());
+        self.apply_limits(value)
+            .ok()
+            .map(|value| self.observe_unchecked(value))
+    }
+
+    /// Observes a sequence of values.
+    ///
+    /// The result is the same as calling `observe` for each value,
+    /// but the counts of the buckets and the sum are updated only once per invocation.
+    ///
+    /// # Panics
+    ///
+    /// If any of `values` is `NaN`, this method will panic.
+    pub fn observe_many(&self, values: &[f64]) {
+        let mut counts = vec![0; self.0.buckets.len()];
+        let mut sum = 0.0;
+        let mut compensation = 0.0;
+        for &value in values {
+            assert!(!value.is_nan());
+            if let Ok(value) = self.apply_limits(value) {
+                if let Some(c) = counts.get_mut(self.bucket_index(value)) {
+                    *c += 1;
+                }
+                let new_sum = sum + value;
+                compensation += if f64::abs(sum) >= f64::abs(value) {
+                    (sum - new_sum) + value
+                } else {
+                    (value - new_sum) + sum
+                };
+                sum = new_sum;
+            }
+        }
+        if counts.iter().any(|&c| c != 0) {
+            self.mark_observed();
+        }
+        self.update(|| {
+            for (bucket, &count) in self.0.buckets.iter().zip(counts.iter()) {
+                if count != 0 {
+                    bucket.add(count);
+                }
+            }
+            self.0.sum.add(sum + compensation);
+        });
+    }
+
+    /// Observes `value` as if it were observed `weight` times.
+    ///
+    /// The count of the bucket to which `value` belongs and the total count are increased by `weight`,
+    /// and the sum is increased by `value * weight`.
+    /// Negative values are handled in the same way as `observe`,
+    /// and if `weight` is zero, this method does nothing.
+    ///
+    /// # Panics
+    ///
+    /// If `value` is `NaN`, this method will panic.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metrics::HistogramBuilder;
+    ///
+    /// let histogram = HistogramBuilder::new("foo").bucket(1.0).finish().unwrap();
+    /// histogram.observe_weighted(0.5, 3);
+    /// assert_eq!(histogram.buckets()[0].count(), 3);
+    /// assert_eq!(histogram.count(), 3);
+    /// assert_eq!(histogram.sum(), 1.5);
+    /// ```
+    pub fn observe_weighted(&self, value: f64, weight: u64) {
         assert!(!value.is_nan());
-        let i = self
-            .0
-            .buckets
-            .binary_search_by(|b| b.upper_bound().partial_cmp(&value).expect("Never fails"))
-            .unwrap_or_else(|i| i);
-        self.0.buckets.get(i).map(|b| b.increment());
-        self.0.sum.add(value);
+        if weight == 0 {
+            return;
+        }
+        if let Ok(value) = self.apply_limits(value) {
+            self.mark_observed();
+            let i = self.bucket_index(value);
+            self.update(|| {
+                if let Some(b) = self.0.buckets.get(i) {
+                    b.add(weight);
+                }
+                self.0.sum.add(value * weight as f64);
+            });
+        }
+    }
+
+    /// Adds the per-bucket counts and the sum of `other` to this histogram.
+    ///
+    /// This is useful for folding histograms which are local to workers into a global one.
+    /// Unless this histogram has been built with `HistogramBuilder::consistent_snapshot`,
+    /// each bucket and the sum are updated separately, so a concurrent reader may see a partially merged state.
+    /// The limits configured by `HistogramBuilder` (e.g., `clamp_max`) are not re-applied.
+    ///
+    /// # Errors
+    ///
+    /// If the bucket bounds of `other` differ from those of this histogram,
+    /// this method returns `ErrorKind::InvalidInput` error and this histogram is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metrics::HistogramBuilder;
+    ///
+    /// let global = HistogramBuilder::new("foo").bucket(1.0).finish().unwrap();
+    /// let local = HistogramBuilder::new("foo").bucket(1.0).finish().unwrap();
+    /// global.observe(0.5);
+    /// local.observe(0.5);
+    /// local.observe(1.5);
+    ///
+    /// global.merge_from(&local).unwrap();
+    /// assert_eq!(global.buckets()[0].count(), 2);
+    /// assert_eq!(global.count(), 3);
+    /// assert_eq!(global.sum(), 2.5);
+    /// ```
+    pub fn merge_from(&self, other: &Histogram) -> Result<()> {
+        track_assert!(
+            self.bucket_bounds() == other.bucket_bounds(),
+            ErrorKind::InvalidInput,
+            "Bucket mismatch: {:?} != {:?}",
+            self.bucket_bounds(),
+            other.bucket_bounds()
+        );
+        let other = other.freeze();
+        if other.count() != 0 {
+            self.mark_observed();
+        }
+        self.update(|| {
+            let mut prev = 0;
+            for (b, o) in self.0.buckets.iter().zip(other.buckets()) {
+                let count = o.cumulative_count() - prev;
+                prev = o.cumulative_count();
+                if count != 0 {
+                    b.add(count);
+                }
+            }
+            self.0.sum.add(other.sum());
+        });
+        Ok(())
+    }
+
+    // Applies `f`, which updates the buckets or the sum, to this histogram.
+    #[inline]
+    fn update<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        if let Some(ref generation) = self.0.generation {
+            generation.write(f)
+        } else {
+            f()
+        }
+    }
+
+    #[inline]
+    fn mark_observed(&self) {
+        if !self.0.observed.load(Ordering::Relaxed) {
+            self.0.observed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // Applies the handling of negative values and `HistogramBuilder::clamp_max` to `value`.
+    #[inline]
+    fn apply_limits(&self, value: f64) -> Result<f64> {
+        let value = track!(self.0.negative_values.apply(value))?;
+        match self.0.max_value {
+            Some(max) if value > max => Ok(max),
+            _ => Ok(value),
+        }
+    }
+
+    #[inline]
+    fn observe_unchecked(&self, value: f64) -> usize {
+        self.mark_observed();
+        let i = self.bucket_index(value);
+        self.update(|| {
+            if let Some(b) = self.0.buckets.get(i) {
+                b.increment();
+            }
+            self.0.sum.add(value);
+        });
+        i
+    }
+
+    #[inline]
+    fn bucket_index(&self, value: f64) -> usize {
+        self.0
+            .upper_bounds
+            .binary_search_by(|b| b.partial_cmp(&value).expect("Never fails"))
+            .unwrap_or_else(|i| i)
+    }
+
+    /// Observes `duration` in seconds.
+    #[inline]
+    pub fn observe_duration(&self, duration: Duration) {
+        self.observe(timestamp::duration_to_seconds(duration));
     }
 
     /// Measures the exeuction time of `f` and observes its duration in seconds.
@@ -105,10 +441,133 @@ impl Histogram {
         result
     }
 
+    /// Returns a future which observes the elapsed time until `future` completes in seconds.
+    ///
+    /// The elapsed time is measured from the invocation of this method,
+    /// so it includes the time the future spends suspended.
+    #[cfg(feature = "async")]
+    pub fn time_async<F: Future>(&self, future: F) -> TimedFuture<F> {
+        TimedFuture::histogram(future, self.clone())
+    }
+
+    /// Returns `true` if this histogram is exposed by `Gatherer`, otherwise `false`.
+    ///
+    /// A histogram is enabled when it is built.
+    pub fn is_enabled(&self) -> bool {
+        self.0.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Enables or disables the exposition of this histogram.
+    ///
+    /// The collector of a disabled histogram produces no metrics,
+    /// but the value of the histogram is kept and can still be updated.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.0.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if this histogram has observed any value since it was built, otherwise `false`.
+    pub fn observed(&self) -> bool {
+        self.0.observed.load(Ordering::Relaxed)
+    }
+
+    /// Takes a snapshot of the current state of this histogram.
+    ///
+    /// The count of the snapshot always equals to the cumulative count of the last bucket.
+    /// Unless the histogram has been built with `HistogramBuilder::consistent_snapshot`,
+    /// the buckets and the sum are read separately,
+    /// so the sum may not reflect exactly the same observations as the buckets
+    /// if there are concurrent observations.
+    pub fn freeze(&self) -> FrozenHistogram {
+        let read = || FrozenHistogram::new(self.cumulative_buckets_vec(), self.sum());
+        if let Some(ref generation) = self.0.generation {
+            generation.read(read)
+        } else {
+            read()
+        }
+    }
+
+    /// Computes the changes of this histogram since the `earlier` snapshot was taken.
+    ///
+    /// The resulting buckets are cumulative, as with `cumulative_buckets`.
+    ///
+    /// # Errors
+    ///
+    /// If the bucket bounds of `earlier` differ from those of this histogram,
+    /// or if `earlier` has a larger count than this histogram in any bucket
+    /// (i.e., it was not taken from this histogram at an earlier point in time),
+    /// this method returns `ErrorKind::InvalidInput` error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use prometrics::metrics::HistogramBuilder;
+    ///
+    /// let histogram = HistogramBuilder::new("foo").buckets(vec![1.0, 2.0]).finish().unwrap();
+    /// histogram.observe(0.5);
+    /// let earlier = histogram.freeze();
+    ///
+    /// histogram.observe(1.5);
+    /// histogram.observe(3.0);
+    /// let delta = histogram.diff(&earlier).unwrap();
+    /// assert_eq!(
+    ///     delta.buckets().iter().map(|b| b.cumulative_count()).collect::<Vec<_>>(),
+    ///     [0, 1, 2]
+    /// );
+    /// assert_eq!(delta.sum(), 4.5);
+    /// assert_eq!(delta.count(), 2);
+    /// assert_eq!(delta.rate(Duration::from_secs(4)), 0.5);
+    /// ```
+    pub fn diff(&self, earlier: &FrozenHistogram) -> Result<HistogramDelta> {
+        let current = self.freeze();
+        track_assert_eq!(
+            current.buckets.len(),
+            earlier.buckets.len(),
+            ErrorKind::InvalidInput,
+            "Bucket mismatch"
+        );
+        let mut buckets = Vec::with_capacity(current.buckets.len());
+        for (now, before) in current.buckets.iter().zip(earlier.buckets.iter()) {
+            track_assert!(
+                now.upper_bound() == before.upper_bound(),
+                ErrorKind::InvalidInput,
+                "Bucket mismatch: {} != {}",
+                now.upper_bound(),
+                before.upper_bound()
+            );
+            let count = now
+                .cumulative_count()
+                .checked_sub(before.cumulative_count());
+            let count = track_assert_some!(
+                count,
+                ErrorKind::InvalidInput,
+                "Not an earlier snapshot: upper_bound={}",
+                now.upper_bound()
+            );
+            buckets.push(CumulativeBucket::new(now.upper_bound(), count));
+        }
+        Ok(HistogramDelta {
+            buckets,
+            sum: current.sum - earlier.sum,
+            count: current.count - earlier.count,
+        })
+    }
+
+    pub(crate) fn sorts_reserved_label(&self) -> bool {
+        self.0.sort_reserved_label
+    }
+
     /// Returns a collector for this histogram.
     pub fn collector(&self) -> HistogramCollector {
         HistogramCollector(Arc::downgrade(&self.0))
     }
+
+    /// Returns the number of live handles sharing the state of this histogram.
+    ///
+    /// See `Counter::handle_count` for the caveats.
+    pub fn handle_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
 }
 impl fmt::Display for Histogram {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -123,24 +582,25 @@ impl fmt::Display for Histogram {
             "".to_string()
         };
 
-        for bucket in self.cumulative_buckets() {
-            write!(
-                f,
-                "{}_bucket{{le=\"{}\"",
-                self.metric_name(),
-                MetricValue(bucket.upper_bound())
-            )?;
-            for label in self.labels().iter() {
-                write!(f, ",{}={:?}", label.name(), label.value())?;
-            }
-            writeln!(f, "}} {}{}", bucket.cumulative_count(), timestamp)?;
+        let snapshot = self.freeze();
+        for bucket in snapshot.buckets() {
+            write!(f, "{}_bucket", self.metric_name())?;
+            self.labels().with_labels(|labels| {
+                write_labels_with_reserved(
+                    f,
+                    ("le", BucketBound(bucket.upper_bound())),
+                    labels,
+                    self.0.sort_reserved_label,
+                )
+            })?;
+            writeln!(f, " {}{}", bucket.cumulative_count(), timestamp)?;
         }
         writeln!(
             f,
             "{}_sum{} {}{}",
             self.metric_name(),
             labels,
-            MetricValue(self.sum()),
+            MetricValue(snapshot.sum()),
             timestamp
         )?;
         write!(
@@ -148,7 +608,7 @@ impl fmt::Display for Histogram {
             "{}_count{} {}{}",
             self.metric_name(),
             labels,
-            self.count(),
+            snapshot.count(),
             timestamp
         )?;
         Ok(())
@@ -164,7 +624,17 @@ pub struct HistogramBuilder {
     help: Option<String>,
     labels: Vec<(String, String)>,
     bucket_upper_bounds: Vec<f64>,
+
+    // The `start` and `factor` of each `exponential_buckets` invocation, which are validated by `finish`.
+    exponential_buckets: Vec<(f64, f64)>,
+
+    negative_values: NegativeValues,
+    max_value: Option<f64>,
     registries: Vec<Registry>,
+    preserve_label_order: bool,
+    sort_reserved_label: bool,
+    consistent_snapshot: bool,
+    seed: Option<Seed>,
 }
 impl HistogramBuilder {
     /// Makes a builder for histograms named `name`.
@@ -176,25 +646,30 @@ impl HistogramBuilder {
             help: None,
             labels: Vec::new(),
             bucket_upper_bounds: vec![std::f64::INFINITY],
+            exponential_buckets: Vec::new(),
+            negative_values: NegativeValues::Accept,
+            max_value: None,
             registries: Vec::new(),
+            preserve_label_order: false,
+            sort_reserved_label: false,
+            consistent_snapshot: false,
+            seed: None,
         }
     }
 
     /// Makes a builder with the specified linear buckets.
     pub fn with_linear_buckets(name: &str, start: f64, width: f64, count: usize) -> Self {
         let mut this = Self::new(name);
-        for x in (0..count).map(|i| start + i as f64 * width) {
-            this.bucket(x);
-        }
+        this.linear_buckets(start, width, count);
         this
     }
 
     /// Makes a builder with the specified exponential buckets.
+    ///
+    /// See `HistogramBuilder::exponential_buckets` for the resulting buckets.
     pub fn with_exponential_buckets(name: &str, start: f64, factor: f64, count: usize) -> Self {
         let mut this = Self::new(name);
-        for x in (0..count).map(|i| start + factor.powi(i as i32)) {
-            this.bucket(x);
-        }
+        this.exponential_buckets(start, factor, count);
         this
     }
 
@@ -222,9 +697,77 @@ impl HistogramBuilder {
     ///
     /// The name `"le"` is reserved for designating buckets.
     pub fn label(&mut self, name: &str, value: &str) -> &mut Self {
+        if let Some(l) = self.labels.iter_mut().find(|l| l.0 == name) {
+            l.1 = value.to_string();
+        } else {
+            self.labels.push((name.to_string(), value.to_string()));
+        }
+        self
+    }
+
+    /// Adds labels.
+    ///
+    /// This is equivalent to calling `label` for each of the given name-value pairs.
+    pub fn labels<I, K, V>(&mut self, labels: I) -> &mut Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        for (name, value) in labels {
+            self.label(name.as_ref(), value.as_ref());
+        }
+        self
+    }
+
+    /// Removes the label named `name` if it has been added.
+    pub fn remove_label(&mut self, name: &str) -> &mut Self {
         self.labels.retain(|l| l.0 != name);
-        self.labels.push((name.to_string(), value.to_string()));
-        self.labels.sort();
+        self
+    }
+
+    /// Keeps the labels of the resulting histograms in insertion order instead of sorting them by name.
+    ///
+    /// This also applies to the labels inserted via `labels_mut` after the histogram is built.
+    pub fn preserve_label_order(&mut self) -> &mut Self {
+        self.preserve_label_order = true;
+        self
+    }
+
+    /// Places the `le` label among the user defined labels in name order when exposing the resulting histograms.
+    ///
+    /// By default, the `le` label precedes the user defined labels.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metrics::HistogramBuilder;
+    ///
+    /// let histogram = HistogramBuilder::new("foo")
+    ///     .bucket(1.0)
+    ///     .labels(vec![("aaa", "0"), ("zzz", "1")])
+    ///     .sort_reserved_label()
+    ///     .finish()
+    ///     .unwrap();
+    /// assert!(histogram.to_string().starts_with(r#"foo_bucket{aaa="0",le="1",zzz="1"} 0"#));
+    /// ```
+    pub fn sort_reserved_label(&mut self) -> &mut Self {
+        self.sort_reserved_label = true;
+        self
+    }
+
+    /// Makes the resulting histograms always expose a coherent set of bucket counts and sum.
+    ///
+    /// By default, a histogram which is being observed concurrently may be exposed
+    /// with a sum that does not reflect exactly the same observations as its buckets.
+    /// In this mode, every update is tracked by a generation counter (like a seqlock),
+    /// and readers (e.g., `freeze` and the text exposition) retry until they read the values
+    /// without any update in between.
+    ///
+    /// This slightly increases the cost of observations, and readers may spin for a while
+    /// if the histogram is updated continuously.
+    pub fn consistent_snapshot(&mut self) -> &mut Self {
+        self.consistent_snapshot = true;
         self
     }
 
@@ -234,7 +777,14 @@ impl HistogramBuilder {
         self
     }
 
+    /// Clears the current registries, then sets `registry` as the new one.
+    pub fn set_registry(&mut self, registry: Registry) -> &mut Self {
+        self.registries = vec![registry];
+        self
+    }
+
     /// Adds the default registry.
+    #[cfg(not(feature = "no-default-registry"))]
     pub fn default_registry(&mut self) -> &mut Self {
         self.registry(default_registry())
     }
@@ -246,12 +796,162 @@ impl HistogramBuilder {
     }
 
     /// Adds a sequence of buckets.
-    pub fn buckets<I: IntoIterator<Item = f64>>(&mut self, upper_bounds: I) -> &mut Self {
+    ///
+    /// Both of owned values (e.g., `Vec<f64>`) and borrowed ones (e.g., `&[f64]`) are accepted.
+    pub fn buckets<I>(&mut self, upper_bounds: I) -> &mut Self
+    where
+        I: IntoIterator,
+        I::Item: Borrow<f64>,
+    {
         self.bucket_upper_bounds
-            .append(&mut upper_bounds.into_iter().collect::<Vec<_>>());
+            .extend(upper_bounds.into_iter().map(|b| *b.borrow()));
         self
     }
 
+    /// Adds linear buckets.
+    ///
+    /// The buckets are the same as the ones of `HistogramBuilder::with_linear_buckets`.
+    /// This can be combined with the other bucket settings (e.g., `exponential_buckets`)
+    /// because the upper bounds are sorted and deduplicated by `finish`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metrics::HistogramBuilder;
+    ///
+    /// let histogram = HistogramBuilder::new("foo")
+    ///     .linear_buckets(0.0, 1.0, 4)
+    ///     .exponential_buckets(1.0, 2.0, 4)
+    ///     .finish()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     histogram.bucket_bounds(),
+    ///     [0.0, 1.0, 2.0, 3.0, 4.0, 8.0, f64::INFINITY]
+    /// );
+    /// ```
+    pub fn linear_buckets(&mut self, start: f64, width: f64, count: usize) -> &mut Self {
+        self.buckets((0..count).map(|i| start + i as f64 * width))
+    }
+
+    /// Adds exponential buckets.
+    ///
+    /// The upper bounds are `start`, `start * factor`, `start * factor^2`, ..., `start * factor^(count - 1)`.
+    ///
+    /// `start` must be positive and `factor` must be greater than `1.0`,
+    /// otherwise `finish` will fail.
+    pub fn exponential_buckets(&mut self, start: f64, factor: f64, count: usize) -> &mut Self {
+        self.exponential_buckets.push((start, factor));
+        self.buckets((0..count).map(|i| start * factor.powi(i as i32)))
+    }
+
+    /// Makes resulting histograms reject negative observations.
+    ///
+    /// `Histogram::observe` ignores negative values,
+    /// and `Histogram::try_observe` returns errors for them.
+    ///
+    /// By default, negative values are observed as they are
+    /// (i.e., they are counted in the lowest bucket and added to the sum).
+    pub fn reject_negative(&mut self) -> &mut Self {
+        self.negative_values = NegativeValues::Reject;
+        self
+    }
+
+    /// Makes resulting histograms observe negative values as `0.0`.
+    ///
+    /// This is useful for guarding duration histograms against clocks going backwards.
+    pub fn clamp_negative_to_zero(&mut self) -> &mut Self {
+        self.negative_values = NegativeValues::ClampToZero;
+        self
+    }
+
+    /// Makes resulting histograms observe values greater than `max` as `max`.
+    ///
+    /// Both of the bucket and the sum are updated with the clamped value,
+    /// so that an absurd observation (e.g., caused by a clock glitch) does not ruin the sum.
+    /// For histograms of durations, `max` is in seconds.
+    ///
+    /// By default, no upper limit is set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metrics::HistogramBuilder;
+    ///
+    /// let histogram = HistogramBuilder::new("foo")
+    ///     .bucket(1.0)
+    ///     .clamp_max(60.0)
+    ///     .finish()
+    ///     .unwrap();
+    /// histogram.observe(1e18);
+    /// assert_eq!(histogram.sum(), 60.0);
+    /// ```
+    pub fn clamp_max(&mut self, max: f64) -> &mut Self {
+        self.max_value = Some(max);
+        self
+    }
+
+    /// Sets the initial state of the resulting histograms.
+    ///
+    /// `buckets` are pairs of an upper bound and a cumulative count (i.e., the same as the `le` buckets
+    /// in the text format), `sum` is the sum of the observed values and `count` is the observation count.
+    /// This is useful for restoring a histogram persisted before the process restarted.
+    ///
+    /// The upper bounds in `buckets` must be the same as the ones of the histogram,
+    /// except that the `+Inf` bucket may be omitted.
+    /// The cumulative counts must be non-decreasing and must not exceed `count`.
+    /// These conditions are checked by `finish`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::metrics::HistogramBuilder;
+    ///
+    /// let histogram = HistogramBuilder::new("foo")
+    ///     .buckets(vec![1.0, 2.0])
+    ///     .seed(&[(1.0, 3), (2.0, 5)], 10.5, 6)
+    ///     .finish()
+    ///     .unwrap();
+    /// assert_eq!(histogram.count(), 6);
+    /// assert_eq!(histogram.sum(), 10.5);
+    /// assert_eq!(
+    ///     histogram.buckets().iter().map(|b| b.count()).collect::<Vec<_>>(),
+    ///     [3, 2, 1]
+    /// );
+    ///
+    /// // Not cumulative
+    /// assert!(HistogramBuilder::new("foo")
+    ///     .buckets(vec![1.0, 2.0])
+    ///     .seed(&[(1.0, 3), (2.0, 2)], 10.5, 6)
+    ///     .finish()
+    ///     .is_err());
+    /// ```
+    pub fn seed(&mut self, buckets: &[(f64, u64)], sum: f64, count: u64) -> &mut Self {
+        self.seed = Some(Seed {
+            buckets: buckets.to_vec(),
+            sum,
+            count,
+        });
+        self
+    }
+
+    /// Validates the settings of this builder without building a histogram.
+    ///
+    /// This performs the same checks as `finish`, but does not register anything.
+    ///
+    /// # Errors
+    ///
+    /// This method will return `Err(_)` if and only if `finish` would fail
+    /// for reasons other than the registration to the registries.
+    pub fn validate(&self) -> Result<()> {
+        track!(self.check_max_value())?;
+        track!(self.check_exponential_buckets())?;
+        track!(self.build_name())?;
+        track!(self.build_labels())?;
+        let buckets = track!(self.build_buckets())?;
+        track!(self.apply_seed(&buckets))?;
+        Ok(())
+    }
+
     /// Builds a histogram.
     ///
     /// # Errors
@@ -260,18 +960,85 @@ impl HistogramBuilder {
     ///
     /// - Any of the name of the metric or labels is malformed
     /// - There is a bucket whose upper bound is `NaN`
+    /// - `exponential_buckets` has been given a non-positive `start` or a `factor` not greater than `1.0`
+    /// - The data given by `seed` is inconsistent
+    /// - The value given by `clamp_max` is `NaN`
+    /// - Any of the registries has lost its gatherer (`ErrorKind::RegistrationFailed`)
+    ///
+    /// In the latter case, the histogram is dropped, so it never appears in the other registries either.
+    ///
+    /// Duplicate upper bounds are collapsed into a single bucket,
+    /// and the resulting histogram always has exactly one `+Inf` bucket.
     pub fn finish(&self) -> Result<Histogram> {
+        track!(self.check_max_value())?;
+        track!(self.check_exponential_buckets())?;
+        let bucket_name = track!(self.build_name())?;
+        let labels = track!(self.build_labels())?;
+        let buckets = track!(self.build_buckets())?;
+        let sum = track!(self.apply_seed(&buckets))?;
+        let upper_bounds = buckets.iter().map(|b| b.upper_bound()).collect();
+        let inner = Inner {
+            bucket_name,
+            labels: Labels::new(labels, self.preserve_label_order),
+            help: self.help.clone(),
+            timestamp: Timestamp::new(),
+            buckets: buckets.into_boxed_slice(),
+            upper_bounds,
+            negative_values: self.negative_values,
+            max_value: self.max_value,
+            sort_reserved_label: self.sort_reserved_label,
+            generation: if self.consistent_snapshot {
+                Some(Generation::new())
+            } else {
+                None
+            },
+            count: AtomicU64::new(0),
+            sum: AtomicCompensatedF64::new(sum),
+            enabled: AtomicBool::new(true),
+            observed: AtomicBool::new(false),
+        };
+        let histogram = Histogram(Arc::new(inner));
+        track!(Registry::register_to_all(&self.registries, || histogram.collector()))?;
+        Ok(histogram)
+    }
+
+    fn check_max_value(&self) -> Result<()> {
+        if let Some(max) = self.max_value {
+            track_assert!(!max.is_nan(), ErrorKind::InvalidInput, "NaN is not allowed");
+        }
+        Ok(())
+    }
+
+    fn check_exponential_buckets(&self) -> Result<()> {
+        for &(start, factor) in &self.exponential_buckets {
+            track_assert!(start > 0.0, ErrorKind::InvalidInput, "start:{}", start);
+            track_assert!(factor > 1.0, ErrorKind::InvalidInput, "factor:{}", factor);
+        }
+        Ok(())
+    }
+
+    fn build_name(&self) -> Result<MetricName> {
         let namespace = self.namespace.as_ref().map(AsRef::as_ref);
         let subsystem = self.subsystem.as_ref().map(AsRef::as_ref);
-        let bucket_name = track!(MetricName::new(namespace, subsystem, &self.name))?;
-        let labels = track!(self
+        track!(MetricName::new(namespace, subsystem, &self.name))
+    }
+
+    fn build_labels(&self) -> Result<LabelVec> {
+        let mut labels: LabelVec = track!(self
             .labels
             .iter()
-            .map(|&(ref name, ref value)| {
+            .map(|(name, value)| {
                 track_assert_ne!(name, "le", ErrorKind::InvalidInput);
                 track!(Label::new(name, value))
             })
             .collect::<Result<_>>())?;
+        if !self.preserve_label_order {
+            labels.sort();
+        }
+        Ok(labels)
+    }
+
+    fn build_buckets(&self) -> Result<Vec<Bucket>> {
         let mut buckets = track!(self
             .bucket_upper_bounds
             .iter()
@@ -282,20 +1049,121 @@ impl HistogramBuilder {
                 .partial_cmp(&b.upper_bound())
                 .expect("Never fails")
         });
-        let inner = Inner {
-            bucket_name,
-            labels: Labels::new(labels),
-            help: self.help.clone(),
-            timestamp: Timestamp::new(),
-            buckets,
-            count: AtomicU64::new(0),
-            sum: AtomicF64::new(0.0),
+        buckets.dedup_by(|a, b| a.upper_bound() == b.upper_bound());
+        if buckets.last().map(|b| b.upper_bound()) != Some(f64::INFINITY) {
+            buckets.push(track!(Bucket::new(f64::INFINITY))?);
+        }
+        Ok(buckets)
+    }
+
+    // Adds the seeded counts to `buckets`, and returns the seeded sum.
+    fn apply_seed(&self, buckets: &[Bucket]) -> Result<f64> {
+        let seed = match self.seed {
+            None => return Ok(0.0),
+            Some(ref seed) => seed,
         };
-        let histogram = Histogram(Arc::new(inner));
-        for r in &self.registries {
-            r.register(histogram.collector());
+        let finite_buckets = buckets.len() - 1;
+        track_assert!(
+            seed.buckets.len() == finite_buckets || seed.buckets.len() == buckets.len(),
+            ErrorKind::InvalidInput,
+            "The number of the seeded buckets is mismatched"
+        );
+
+        let mut counts = Vec::with_capacity(buckets.len());
+        let mut prev = 0;
+        for (bucket, &(upper_bound, cumulative_count)) in buckets.iter().zip(&seed.buckets) {
+            track_assert_eq!(bucket.upper_bound(), upper_bound, ErrorKind::InvalidInput);
+            track_assert!(
+                prev <= cumulative_count,
+                ErrorKind::InvalidInput,
+                "Seeded counts must be cumulative"
+            );
+            counts.push(cumulative_count - prev);
+            prev = cumulative_count;
+        }
+        track_assert!(prev <= seed.count, ErrorKind::InvalidInput);
+        if counts.len() == finite_buckets {
+            counts.push(seed.count - prev);
+        } else {
+            track_assert_eq!(prev, seed.count, ErrorKind::InvalidInput);
+        }
+        for (bucket, count) in buckets.iter().zip(counts) {
+            bucket.add(count);
+        }
+        Ok(seed.sum)
+    }
+}
+
+/// A point-in-time snapshot of a `Histogram`.
+///
+/// This is made by `Histogram::freeze`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrozenHistogram {
+    buckets: Vec<CumulativeBucket>,
+    sum: f64,
+    count: u64,
+}
+impl FrozenHistogram {
+    pub(crate) fn new(buckets: Vec<CumulativeBucket>, sum: f64) -> Self {
+        let count = buckets.last().map_or(0, |b| b.cumulative_count());
+        FrozenHistogram {
+            buckets,
+            sum,
+            count,
+        }
+    }
+
+    /// Returns the cumulative buckets of the histogram.
+    pub fn buckets(&self) -> &[CumulativeBucket] {
+        &self.buckets
+    }
+
+    /// Returns the sum of the observed values.
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// Returns the total observation count.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+/// The changes of a `Histogram` between two points in time.
+///
+/// This is made by `Histogram::diff`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramDelta {
+    buckets: Vec<CumulativeBucket>,
+    sum: f64,
+    count: u64,
+}
+impl HistogramDelta {
+    /// Returns the cumulative buckets which hold the increases of the counts.
+    pub fn buckets(&self) -> &[CumulativeBucket] {
+        &self.buckets
+    }
+
+    /// Returns the increase of the sum of the observed values.
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// Returns the increase of the observation count.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the number of observations per second over `elapsed`.
+    ///
+    /// If `elapsed` is zero, this method returns `0.0`.
+    pub fn rate(&self, elapsed: Duration) -> f64 {
+        let secs = timestamp::duration_to_seconds(elapsed);
+        if secs == 0.0 {
+            0.0
+        } else {
+            self.count as f64 / secs
         }
-        Ok(histogram)
     }
 }
 
@@ -303,11 +1171,62 @@ impl HistogramBuilder {
 #[derive(Debug, Clone)]
 pub struct HistogramCollector(Weak<Inner>);
 impl Collect for HistogramCollector {
-    type Metrics = iter::Once<Metric>;
+    type Metrics = option::IntoIter<Metric>;
     fn collect(&mut self) -> Option<Self::Metrics> {
-        self.0
-            .upgrade()
-            .map(|inner| iter::once(Metric::Histogram(Histogram(inner))))
+        self.0.upgrade().map(|inner| {
+            let enabled = inner.enabled.load(Ordering::Relaxed);
+            Some(Metric::Histogram(Histogram(inner)))
+                .filter(|_| enabled)
+                .into_iter()
+        })
+    }
+}
+
+// The state for reading the values of a histogram consistently (see `HistogramBuilder::consistent_snapshot`).
+//
+// Unlike a plain seqlock, this allows multiple writers to update the histogram concurrently;
+// `writers` is the number of the ongoing updates, and `generation` counts the completed ones.
+#[derive(Debug)]
+struct Generation {
+    writers: AtomicUsize,
+    generation: AtomicUsize,
+}
+impl Generation {
+    fn new() -> Self {
+        Generation {
+            writers: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+        }
+    }
+
+    fn write<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        self.writers.fetch_add(1, Ordering::SeqCst);
+        let result = f();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.writers.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+
+    fn read<F, T>(&self, f: F) -> T
+    where
+        F: Fn() -> T,
+    {
+        loop {
+            let generation = self.generation.load(Ordering::SeqCst);
+            if self.writers.load(Ordering::SeqCst) == 0 {
+                let result = f();
+                atomic::fence(Ordering::SeqCst);
+                if self.writers.load(Ordering::SeqCst) == 0
+                    && self.generation.load(Ordering::SeqCst) == generation
+                {
+                    return result;
+                }
+            }
+            thread::yield_now();
+        }
     }
 }
 
@@ -317,9 +1236,47 @@ struct Inner {
     labels: Labels,
     help: Option<String>,
     timestamp: Timestamp,
-    buckets: Vec<Bucket>,
+    buckets: Box<[Bucket]>,
+
+    // The upper bounds of `buckets`, kept separately so that lookups scan a compact array.
+    upper_bounds: Box<[f64]>,
+    negative_values: NegativeValues,
+    max_value: Option<f64>,
+    sort_reserved_label: bool,
+    generation: Option<Generation>,
     count: AtomicU64,
-    sum: AtomicF64,
+    sum: AtomicCompensatedF64,
+    enabled: AtomicBool,
+    observed: AtomicBool,
+}
+
+#[derive(Debug)]
+struct Seed {
+    buckets: Vec<(f64, u64)>,
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum NegativeValues {
+    Accept,
+    Reject,
+    ClampToZero,
+}
+impl NegativeValues {
+    #[inline]
+    fn apply(self, value: f64) -> Result<f64> {
+        if value >= 0.0 {
+            return Ok(value);
+        }
+        match self {
+            NegativeValues::Accept => Ok(value),
+            NegativeValues::Reject => {
+                track_panic!(ErrorKind::InvalidInput, "Negative value: {}", value)
+            }
+            NegativeValues::ClampToZero => Ok(0.0),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -327,6 +1284,138 @@ mod test {
     use super::*;
     use std::f64::INFINITY;
 
+    #[test]
+    fn consistent_snapshot_works() {
+        use std::thread;
+
+        let histogram = track_try_unwrap!(HistogramBuilder::new("foo")
+            .buckets(vec![0.5, 2.0])
+            .consistent_snapshot()
+            .finish());
+        let observers = (0..4)
+            .map(|_| {
+                let histogram = histogram.clone();
+                thread::spawn(move || {
+                    for _ in 0..2000 {
+                        histogram.observe(1.0);
+                        histogram.observe_weighted(1.0, 2);
+                        histogram.observe_many(&[1.0, 1.0]);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Every observed value is `1.0`, so the sum always equals to the count in coherent snapshots.
+        for _ in 0..1000 {
+            let snapshot = histogram.freeze();
+            assert_eq!(snapshot.sum(), snapshot.count() as f64);
+
+            let text = histogram.to_string();
+            let count = text.lines().last().and_then(|l| l.split(' ').nth(1));
+            let sum = text.lines().rev().nth(1).and_then(|l| l.split(' ').nth(1));
+            assert_eq!(sum, count, "{}", text);
+        }
+        for observer in observers {
+            observer.join().unwrap();
+        }
+        assert_eq!(histogram.count(), 4 * 2000 * 5);
+        assert_eq!(histogram.sum(), f64::from(4 * 2000 * 5));
+    }
+
+    #[test]
+    fn merge_from_works() {
+        let global = track_try_unwrap!(HistogramBuilder::new("foo")
+            .buckets(vec![1.0, 2.0])
+            .finish());
+        let local0 = track_try_unwrap!(HistogramBuilder::new("foo")
+            .buckets(vec![1.0, 2.0])
+            .finish());
+        let local1 = track_try_unwrap!(HistogramBuilder::new("bar")
+            .buckets(vec![2.0, 1.0])
+            .finish());
+        local0.observe(0.5);
+        local0.observe(3.0);
+        local1.observe(1.5);
+        local1.observe(1.5);
+
+        track_try_unwrap!(global.merge_from(&local0));
+        track_try_unwrap!(global.merge_from(&local1));
+        assert!(global.observed());
+        assert_eq!(
+            global
+                .cumulative_buckets()
+                .map(|b| (b.upper_bound(), b.cumulative_count()))
+                .collect::<Vec<_>>(),
+            [(1.0, 1), (2.0, 3), (f64::INFINITY, 4)]
+        );
+        assert_eq!(global.count(), 4);
+        assert_eq!(global.sum(), 6.5);
+        assert_eq!(local0.count(), 2);
+
+        let other = track_try_unwrap!(HistogramBuilder::new("foo").bucket(1.0).finish());
+        other.observe(0.5);
+        assert_eq!(
+            global.merge_from(&other).err().map(|e| *e.kind()),
+            Some(ErrorKind::InvalidInput)
+        );
+        assert_eq!(global.count(), 4);
+    }
+
+    #[test]
+    fn diff_works() {
+        let histogram = track_try_unwrap!(HistogramBuilder::new("foo")
+            .buckets(vec![1.0, 2.0])
+            .finish());
+        let first = histogram.freeze();
+        assert_eq!(first.count(), 0);
+
+        histogram.observe(0.5);
+        histogram.observe(2.5);
+        let second = histogram.freeze();
+        let delta = track_try_unwrap!(histogram.diff(&first));
+        assert_eq!(
+            delta
+                .buckets()
+                .iter()
+                .map(|b| (b.upper_bound(), b.cumulative_count()))
+                .collect::<Vec<_>>(),
+            [(1.0, 1), (2.0, 1), (f64::INFINITY, 2)]
+        );
+        assert_eq!(delta.sum(), 3.0);
+        assert_eq!(delta.count(), 2);
+
+        histogram.observe(1.5);
+        let delta = track_try_unwrap!(histogram.diff(&second));
+        assert_eq!(
+            delta
+                .buckets()
+                .iter()
+                .map(|b| b.cumulative_count())
+                .collect::<Vec<_>>(),
+            [0, 1, 1]
+        );
+        assert_eq!(delta.sum(), 1.5);
+        assert_eq!(delta.count(), 1);
+        assert_eq!(
+            track_try_unwrap!(histogram.diff(&histogram.freeze())).count(),
+            0
+        );
+
+        // Mismatched buckets
+        let other = track_try_unwrap!(HistogramBuilder::new("foo").bucket(1.0).finish());
+        assert!(histogram.diff(&other.freeze()).is_err());
+        let other = track_try_unwrap!(HistogramBuilder::new("foo")
+            .buckets(vec![1.0, 3.0])
+            .finish());
+        assert!(histogram.diff(&other.freeze()).is_err());
+
+        // Not an earlier snapshot
+        let other = track_try_unwrap!(HistogramBuilder::new("foo")
+            .buckets(vec![1.0, 2.0])
+            .finish());
+        assert!(other.diff(&histogram.freeze()).is_err());
+    }
+
     #[test]
     fn it_works() {
         let histogram =
@@ -385,4 +1474,323 @@ foo_count 4"#
             [(1.0, 0), (2.0, 1), (3.0, 1), (INFINITY, 2),]
         );
     }
+
+    #[test]
+    fn cumulative_buckets_vec_works() {
+        let histogram = track_try_unwrap!(HistogramBuilder::new("bar")
+            .buckets(vec![1.0, 2.0, 3.0])
+            .finish());
+        histogram.observe(2.0);
+        histogram.observe(5.0);
+
+        let buckets = histogram.cumulative_buckets_vec();
+        assert_eq!(buckets, histogram.cumulative_buckets().collect::<Vec<_>>());
+        assert_eq!(buckets[3].cumulative_count(), 2);
+    }
+
+    #[test]
+    fn bucket_bounds_works() {
+        let histogram = track_try_unwrap!(HistogramBuilder::new("bar")
+            .buckets(vec![2.0, 1.0, 3.0])
+            .finish());
+        assert_eq!(histogram.bucket_bounds(), [1.0, 2.0, 3.0, f64::INFINITY]);
+    }
+
+    #[test]
+    fn buckets_accept_slices() {
+        let histogram = track_try_unwrap!(HistogramBuilder::new("bar")
+            .buckets(&[3.0, 1.0][..])
+            .buckets(&vec![2.0])
+            .finish());
+        assert_eq!(
+            histogram
+                .buckets()
+                .iter()
+                .map(|b| b.upper_bound())
+                .collect::<Vec<_>>(),
+            [1.0, 2.0, 3.0, INFINITY]
+        );
+    }
+
+    #[test]
+    fn linear_and_exponential_buckets_works() {
+        let histogram = track_try_unwrap!(HistogramBuilder::new("foo")
+            .linear_buckets(0.0, 0.5, 10)
+            .exponential_buckets(1.0, 2.0, 8)
+            .finish());
+        let bounds = histogram.bucket_bounds();
+        assert!(bounds.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(bounds.len(), 10 + 8 - 3 + 1);
+        assert_eq!(bounds[0], 0.0);
+        assert_eq!(bounds[bounds.len() - 2], 128.0);
+        assert_eq!(bounds[bounds.len() - 1], f64::INFINITY);
+
+        let histogram = track_try_unwrap!(HistogramBuilder::with_exponential_buckets(
+            "foo", 0.5, 3.0, 3
+        )
+        .finish());
+        assert_eq!(histogram.bucket_bounds(), [0.5, 1.5, 4.5, f64::INFINITY]);
+
+        assert!(
+            HistogramBuilder::with_exponential_buckets("foo", 0.0, 2.0, 3)
+                .finish()
+                .is_err()
+        );
+        assert!(HistogramBuilder::new("foo")
+            .exponential_buckets(1.0, 1.0, 3)
+            .validate()
+            .is_err());
+        assert!(HistogramBuilder::new("foo")
+            .exponential_buckets(1.0, f64::NAN, 3)
+            .finish()
+            .is_err());
+    }
+
+    #[test]
+    fn negative_values_works() {
+        let histogram = track_try_unwrap!(HistogramBuilder::new("foo").bucket(0.0).finish());
+        histogram.observe(-1.0);
+        track_try_unwrap!(histogram.try_observe(-2.0));
+        assert_eq!(histogram.count(), 2);
+        assert_eq!(histogram.sum(), -3.0);
+
+        let histogram = track_try_unwrap!(HistogramBuilder::new("foo")
+            .bucket(0.0)
+            .reject_negative()
+            .finish());
+        histogram.observe(-1.0);
+        assert_eq!(
+            histogram.try_observe(-2.0).err().map(|e| *e.kind()),
+            Some(ErrorKind::InvalidInput)
+        );
+        assert_eq!(histogram.observe_indexed(-3.0), None);
+        track_try_unwrap!(histogram.try_observe(1.0));
+        assert_eq!(histogram.count(), 1);
+        assert_eq!(histogram.sum(), 1.0);
+
+        let histogram = track_try_unwrap!(HistogramBuilder::new("foo")
+            .bucket(0.0)
+            .clamp_negative_to_zero()
+            .finish());
+        histogram.observe(-1.0);
+        track_try_unwrap!(histogram.try_observe(-2.0));
+        assert_eq!(
+            histogram
+                .cumulative_buckets()
+                .map(|b| (b.upper_bound(), b.cumulative_count()))
+                .collect::<Vec<_>>(),
+            [(0.0, 2), (INFINITY, 2)]
+        );
+        assert_eq!(histogram.sum(), 0.0);
+
+        assert!(histogram.try_observe(std::f64::NAN).is_err());
+    }
+
+    #[test]
+    fn observe_many_works() {
+        let values = [7.0, 12.0, 50.5, 10.0, -1.0, 0.25];
+        let histogram0 =
+            track_try_unwrap!(HistogramBuilder::with_linear_buckets("foo", 0.0, 10.0, 5).finish());
+        let histogram1 =
+            track_try_unwrap!(HistogramBuilder::with_linear_buckets("foo", 0.0, 10.0, 5).finish());
+        for &v in &values {
+            histogram0.observe(v);
+        }
+        histogram1.observe_many(&values);
+        histogram1.observe_many(&[]);
+        assert_eq!(histogram0.to_string(), histogram1.to_string());
+        assert_eq!(histogram1.count(), 6);
+        assert_eq!(histogram1.sum(), 78.75);
+    }
+
+    #[test]
+    fn sum_is_compensated() {
+        let histogram = track_try_unwrap!(HistogramBuilder::new("foo").finish());
+        histogram.observe(1e16);
+        for _ in 0..10_000 {
+            histogram.observe(1.0);
+        }
+        assert_eq!(histogram.sum(), 1e16 + 10_000.0);
+    }
+
+    #[test]
+    fn duplicate_buckets_are_collapsed() {
+        let histogram = track_try_unwrap!(HistogramBuilder::new("foo")
+            .bucket(f64::INFINITY)
+            .bucket(1.0)
+            .bucket(1.0)
+            .finish());
+        assert_eq!(
+            histogram
+                .buckets()
+                .iter()
+                .map(|b| b.upper_bound())
+                .collect::<Vec<_>>(),
+            [1.0, f64::INFINITY]
+        );
+        assert_eq!(histogram.to_string().matches(r#"le="+Inf""#).count(), 1);
+    }
+
+    #[test]
+    fn le_values_are_stable() {
+        let histogram = track_try_unwrap!(HistogramBuilder::new("foo")
+            .buckets(vec![0.0001, 1e-20, 1e21, 2.5e15, -0.0, 1.0 / 3.0])
+            .finish());
+        let le = histogram
+            .to_string()
+            .lines()
+            .filter_map(|l| l.split('"').nth(1).map(|s| s.to_owned()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            le,
+            [
+                "0",
+                "0.00000000000000000001",
+                "0.0001",
+                "0.3333333333333333",
+                "2500000000000000",
+                "1000000000000000000000",
+                "+Inf"
+            ]
+        );
+    }
+
+    #[test]
+    fn observe_duration_works() {
+        let histogram = track_try_unwrap!(HistogramBuilder::new("foo").finish());
+        histogram.observe_duration(Duration::from_millis(1500));
+        assert_eq!(histogram.count(), 1);
+        assert_eq!(histogram.sum(), 1.5);
+    }
+
+    #[test]
+    fn estimate_quantile_works() {
+        let histogram = track_try_unwrap!(HistogramBuilder::new("foo")
+            .buckets(vec![1.0, 2.0, 4.0])
+            .finish());
+        for _ in 0..10 {
+            histogram.observe(0.5);
+            histogram.observe(1.5);
+        }
+        assert_eq!(histogram.estimate_quantile(0.0), Some(0.0));
+        assert_eq!(histogram.estimate_quantile(0.25), Some(0.5));
+        assert_eq!(histogram.estimate_quantile(0.5), Some(1.0));
+        assert_eq!(histogram.estimate_quantile(0.75), Some(1.5));
+        assert_eq!(histogram.estimate_quantile(1.0), Some(2.0));
+        assert_eq!(histogram.estimate_quantile(1.5), None);
+        assert_eq!(histogram.estimate_quantile(f64::NAN), None);
+
+        // Falls into the `+Inf` bucket
+        for _ in 0..20 {
+            histogram.observe(100.0);
+        }
+        assert_eq!(histogram.estimate_quantile(0.9), Some(4.0));
+
+        let histogram = track_try_unwrap!(HistogramBuilder::new("bar").finish());
+        histogram.observe(1.0);
+        assert_eq!(histogram.estimate_quantile(0.5), None);
+
+        let histogram = track_try_unwrap!(HistogramBuilder::new("baz")
+            .buckets(vec![1.0, 2.0])
+            .finish());
+        histogram.observe(1.5);
+        assert_eq!(histogram.estimate_quantile(0.0), Some(1.0));
+    }
+
+    #[test]
+    fn seed_works() {
+        let mut builder = HistogramBuilder::new("foo");
+        builder.buckets(vec![1.0, 2.0]);
+
+        builder.seed(&[(1.0, 1), (2.0, 2), (f64::INFINITY, 4)], 3.0, 4);
+        let histogram = track_try_unwrap!(builder.finish());
+        assert_eq!(
+            histogram
+                .cumulative_buckets()
+                .map(|b| b.cumulative_count())
+                .collect::<Vec<_>>(),
+            [1, 2, 4]
+        );
+        histogram.observe(0.5);
+        assert_eq!(histogram.count(), 5);
+        assert_eq!(histogram.sum(), 3.5);
+
+        // Mismatched count of the `+Inf` bucket
+        builder.seed(&[(1.0, 1), (2.0, 2), (f64::INFINITY, 3)], 3.0, 4);
+        assert!(builder.validate().is_err());
+
+        // Mismatched bounds
+        builder.seed(&[(1.0, 1), (3.0, 2)], 3.0, 4);
+        assert!(builder.validate().is_err());
+
+        // Missing buckets
+        builder.seed(&[(1.0, 1)], 3.0, 4);
+        assert!(builder.validate().is_err());
+
+        // Too small count
+        builder.seed(&[(1.0, 1), (2.0, 2)], 3.0, 1);
+        assert!(builder.validate().is_err());
+    }
+
+    #[test]
+    fn observe_weighted_works() {
+        let histogram = track_try_unwrap!(HistogramBuilder::new("foo")
+            .buckets(vec![1.0, 10.0])
+            .reject_negative()
+            .finish());
+        histogram.observe_weighted(0.5, 2);
+        histogram.observe_weighted(5.0, 4);
+        histogram.observe_weighted(100.0, 1);
+        histogram.observe_weighted(f64::INFINITY, 0);
+        histogram.observe_weighted(-1.0, 10);
+        assert_eq!(
+            histogram
+                .buckets()
+                .iter()
+                .map(|b| b.count())
+                .collect::<Vec<_>>(),
+            [2, 4, 1]
+        );
+        assert_eq!(histogram.count(), 7);
+        assert_eq!(histogram.sum(), 121.0);
+    }
+
+    #[test]
+    fn clamp_max_works() {
+        let histogram = track_try_unwrap!(HistogramBuilder::new("foo")
+            .buckets(vec![1.0, 10.0])
+            .clamp_max(5.0)
+            .finish());
+        histogram.observe(1e18);
+        histogram.observe(f64::INFINITY);
+        histogram.try_observe(7.0).unwrap();
+        histogram.observe_many(&[2.0, 1e300]);
+        histogram.observe_weighted(1e9, 2);
+        assert_eq!(histogram.observe_indexed(1e6), Some(1));
+        assert_eq!(
+            histogram
+                .buckets()
+                .iter()
+                .map(|b| b.count())
+                .collect::<Vec<_>>(),
+            [0, 8, 0]
+        );
+        assert_eq!(histogram.sum(), 37.0);
+
+        assert!(HistogramBuilder::new("foo")
+            .clamp_max(f64::NAN)
+            .finish()
+            .is_err());
+    }
+
+    #[test]
+    fn observed_works() {
+        let histogram = track_try_unwrap!(HistogramBuilder::new("foo").reject_negative().finish());
+        histogram.observe_many(&[-1.0]);
+        histogram.observe(-1.0);
+        histogram.observe_weighted(1.0, 0);
+        assert!(!histogram.observed());
+        histogram.observe(0.0);
+        assert!(histogram.observed());
+    }
 }
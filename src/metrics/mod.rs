@@ -6,13 +6,21 @@
 pub use self::builder::MetricBuilder;
 pub use self::counter::{Counter, CounterBuilder, CounterCollector};
 pub use self::gauge::{Gauge, GaugeBuilder, GaugeCollector};
-pub use self::histogram::{Histogram, HistogramBuilder, HistogramCollector};
+pub use self::histogram::{
+    FrozenHistogram, Histogram, HistogramBuilder, HistogramCollector, HistogramDelta,
+};
 pub use self::process::ProcessMetricsCollector;
+pub use self::scalar::ScalarMetric;
 pub use self::summary::{Summary, SummaryBuilder, SummaryCollector};
+#[cfg(feature = "async")]
+pub use self::timed_future::TimedFuture;
 
 mod builder;
 mod counter;
 mod gauge;
 mod histogram;
 mod process;
+mod scalar;
 mod summary;
+#[cfg(feature = "async")]
+mod timed_future;
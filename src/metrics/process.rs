@@ -31,6 +31,8 @@ lazy_static! {
 /// # Examples
 ///
 /// ```
+/// # #[cfg(not(feature = "no-default-registry"))]
+/// # fn main() {
 /// use prometrics::{default_gatherer, default_registry};
 /// use prometrics::metrics::ProcessMetricsCollector;
 ///
@@ -38,7 +40,10 @@ lazy_static! {
 /// default_registry().register(ProcessMetricsCollector::new());
 ///
 /// // Gather
-/// let _metrics = default_gatherer().lock().unwrap().gather();
+/// let _metrics = default_gatherer().read().unwrap().gather();
+/// # }
+/// # #[cfg(feature = "no-default-registry")]
+/// # fn main() {}
 /// ```
 #[derive(Debug)]
 pub struct ProcessMetricsCollector {
@@ -0,0 +1,68 @@
+use label::Labels;
+use metric::MetricName;
+use metrics::{Counter, Gauge};
+use timestamp::Timestamp;
+
+/// A metric which holds a single numeric value.
+///
+/// This is implemented by `Counter` and `Gauge`, and allows for writing code
+/// which handles any of them uniformly (e.g., `&dyn ScalarMetric`).
+///
+/// # Examples
+///
+/// ```
+/// use prometrics::metrics::{CounterBuilder, GaugeBuilder, ScalarMetric};
+///
+/// fn describe(metric: &dyn ScalarMetric) -> String {
+///     format!("{}{} = {}", metric.metric_name(), metric.labels(), metric.value())
+/// }
+///
+/// let counter = CounterBuilder::new("foo").finish().unwrap();
+/// let gauge = GaugeBuilder::new("bar").label("baz", "qux").finish().unwrap();
+/// counter.increment();
+/// gauge.set(2.5);
+///
+/// assert_eq!(describe(&counter), "foo{} = 1");
+/// assert_eq!(describe(&gauge), r#"bar{baz="qux"} = 2.5"#);
+/// ```
+pub trait ScalarMetric {
+    /// Returns the name of this metric.
+    fn metric_name(&self) -> &MetricName;
+
+    /// Returns the user defined labels of this metric.
+    fn labels(&self) -> &Labels;
+
+    /// Returns the timestamp of this metric.
+    fn timestamp(&self) -> &Timestamp;
+
+    /// Returns the current value of this metric.
+    fn value(&self) -> f64;
+}
+impl ScalarMetric for Counter {
+    fn metric_name(&self) -> &MetricName {
+        Counter::metric_name(self)
+    }
+    fn labels(&self) -> &Labels {
+        Counter::labels(self)
+    }
+    fn timestamp(&self) -> &Timestamp {
+        Counter::timestamp(self)
+    }
+    fn value(&self) -> f64 {
+        Counter::value(self)
+    }
+}
+impl ScalarMetric for Gauge {
+    fn metric_name(&self) -> &MetricName {
+        Gauge::metric_name(self)
+    }
+    fn labels(&self) -> &Labels {
+        Gauge::labels(self)
+    }
+    fn timestamp(&self) -> &Timestamp {
+        Gauge::timestamp(self)
+    }
+    fn value(&self) -> f64 {
+        Gauge::value(self)
+    }
+}
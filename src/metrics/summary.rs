@@ -1,14 +1,16 @@
-use std::cmp;
+use std::borrow::Borrow;
 use std::collections::VecDeque;
 use std::fmt;
-use std::iter;
+use std::option;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, Weak};
 use std::time::{Duration, Instant, SystemTime};
 
-use atomic::{AtomicF64, AtomicU64};
+use atomic::{AtomicCompensatedF64, AtomicU64};
+#[cfg(not(feature = "no-default-registry"))]
 use default_registry;
-use label::{Label, Labels, LabelsMut};
-use metric::{Metric, MetricName, MetricValue};
+use label::{Label, LabelVec, Labels, LabelsMut};
+use metric::{write_labels_with_reserved, Metric, MetricName, MetricValue};
 use quantile::Quantile;
 use timestamp::{self, Timestamp, TimestampMut};
 use {Collect, ErrorKind, Registry, Result};
@@ -44,6 +46,11 @@ impl Summary {
         &self.0.labels
     }
 
+    /// Returns the length of the sliding time window over which the quantiles are calculated.
+    pub fn window(&self) -> Duration {
+        self.0.window
+    }
+
     /// Returns the mutable user defined labels of this summary.
     pub fn labels_mut(&mut self) -> LabelsMut {
         LabelsMut::new(&self.0.labels, Some("quantile"))
@@ -72,40 +79,98 @@ impl Summary {
     }
 
     /// Calculates and returns the quantile-value pairs of this summary.
+    ///
+    /// The result is cached, and reused by subsequent invocations
+    /// until a value is observed or a sample leaves the window.
     pub fn quantiles(&self) -> Vec<(Quantile, f64)> {
-        let mut samples = self.with_current_samples(|_, samples| {
-            samples
-                .iter()
-                .map(|&(_, v)| v)
-                .filter(|v| !v.is_nan())
-                .collect::<Vec<_>>()
-        });
+        self.evict_all_expired_samples();
+        let version = (self.0.count.get(), self.0.evicted.get());
+        if let Ok(cache) = self.0.cache.lock() {
+            if let Some((cached_version, ref quantiles)) = *cache {
+                if cached_version == version {
+                    return quantiles.clone();
+                }
+            }
+        }
+
+        let quantiles = self.calculate_quantiles();
+        if let Ok(mut cache) = self.0.cache.lock() {
+            *cache = Some((version, quantiles.clone()));
+        }
+        quantiles
+    }
+
+    fn calculate_quantiles(&self) -> Vec<(Quantile, f64)> {
+        let mut samples = self.current_samples();
+        samples.retain(|v| !v.is_nan());
         samples.sort_by(|a, b| a.partial_cmp(b).expect("Never fails"));
 
         if samples.is_empty() {
             return Vec::new();
         }
-        let count = samples.len();
         self.0
             .quantiles
             .iter()
             .map(|&quantile| {
-                let index = cmp::min(count, (quantile.as_f64() * count as f64).floor() as usize);
-                (quantile, samples[index])
+                let value = quantile.value_of(&samples, self.0.interpolated);
+                (quantile, value)
             })
             .collect()
     }
 
     /// Observes a value.
+    ///
+    /// Any value convertible to `f64` (e.g., `u32`) is accepted.
+    ///
+    /// # Panics
+    ///
+    /// If `value` is `NaN`, this method will panic.
+    #[inline]
+    pub fn observe<V: Into<f64>>(&self, value: V) {
+        self.observe_f64(value.into());
+    }
+
+    #[inline]
+    fn observe_f64(&self, value: f64) {
+        assert!(!value.is_nan());
+        self.observe_unchecked(value);
+    }
+
+    /// Observes a value.
+    ///
+    /// # Errors
+    ///
+    /// If `value` is `NaN`, this method returns `ErrorKind::InvalidInput` error.
+    #[inline]
+    pub fn try_observe(&self, value: f64) -> Result<()> {
+        track_assert!(
+            !value.is_nan(),
+            ErrorKind::InvalidInput,
+            "NaN is not allowed"
+        );
+        self.observe_unchecked(value);
+        Ok(())
+    }
+
     #[inline]
-    pub fn observe(&self, value: f64) {
-        self.with_current_samples(|now, samples| {
+    fn observe_unchecked(&self, value: f64) {
+        self.mark_observed();
+        let now = SystemTime::now();
+        let shard = &self.0.samples[SHARD_INDEX.with(|i| *i)];
+        if let Ok(mut samples) = shard.lock() {
+            self.evict_expired_samples(now, &mut samples);
             samples.push_back((now, value));
-        });
+        }
         self.0.count.inc();
         self.0.sum.add(value);
     }
 
+    /// Observes `duration` in seconds.
+    #[inline]
+    pub fn observe_duration(&self, duration: Duration) {
+        self.observe(timestamp::duration_to_seconds(duration));
+    }
+
     /// Measures the exeuction time of `f` and observes its duration in seconds.
     #[inline]
     pub fn time<F, T>(&self, f: F) -> T
@@ -119,32 +184,88 @@ impl Summary {
         result
     }
 
+    /// Returns `true` if this summary is exposed by `Gatherer`, otherwise `false`.
+    ///
+    /// A summary is enabled when it is built.
+    pub fn is_enabled(&self) -> bool {
+        self.0.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Enables or disables the exposition of this summary.
+    ///
+    /// The collector of a disabled summary produces no metrics,
+    /// but the value of the summary is kept and can still be updated.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.0.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if this summary has observed any value since it was built, otherwise `false`.
+    pub fn observed(&self) -> bool {
+        self.0.observed.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn sorts_reserved_label(&self) -> bool {
+        self.0.sort_reserved_label
+    }
+
+    pub(crate) fn interpolates(&self) -> bool {
+        self.0.interpolated
+    }
+
     /// Returns a collector for this histogram.
     pub fn collector(&self) -> SummaryCollector {
         SummaryCollector(Arc::downgrade(&self.0))
     }
 
+    /// Returns the number of live handles sharing the state of this summary.
+    ///
+    /// See `Counter::handle_count` for the caveats.
+    pub fn handle_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+
     pub(crate) fn quantiles_without_values(&self) -> &[Quantile] {
         &self.0.quantiles
     }
 
-    pub(crate) fn with_current_samples<F, T>(&self, f: F) -> T
-    where
-        F: FnOnce(SystemTime, &mut VecDeque<(SystemTime, f64)>) -> T,
-    {
+    /// Returns the values observed within the current window (in no particular order).
+    pub(crate) fn current_samples(&self) -> Vec<f64> {
         let now = SystemTime::now();
-        if let Ok(mut samples) = self.0.samples.lock() {
-            while samples
-                .front()
-                .and_then(|s| now.duration_since(s.0).ok())
-                .and_then(|d| if d > self.0.window { Some(()) } else { None })
-                .is_some()
-            {
-                samples.pop_front();
+        let mut values = Vec::new();
+        for shard in self.0.samples.iter() {
+            if let Ok(mut samples) = shard.lock() {
+                self.evict_expired_samples(now, &mut samples);
+                values.extend(samples.iter().map(|&(_, v)| v));
             }
-            f(now, &mut samples)
-        } else {
-            f(now, &mut VecDeque::new())
+        }
+        values
+    }
+
+    #[inline]
+    fn mark_observed(&self) {
+        if !self.0.observed.load(Ordering::Relaxed) {
+            self.0.observed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn evict_all_expired_samples(&self) {
+        let now = SystemTime::now();
+        for shard in self.0.samples.iter() {
+            if let Ok(mut samples) = shard.lock() {
+                self.evict_expired_samples(now, &mut samples);
+            }
+        }
+    }
+
+    fn evict_expired_samples(&self, now: SystemTime, samples: &mut Samples) {
+        while samples
+            .front()
+            .and_then(|s| now.duration_since(s.0).ok())
+            .and_then(|d| if d > self.0.window { Some(()) } else { None })
+            .is_some()
+        {
+            samples.pop_front();
+            self.0.evicted.inc();
         }
     }
 }
@@ -162,16 +283,16 @@ impl fmt::Display for Summary {
         };
 
         for (quantile, value) in self.quantiles() {
-            write!(
-                f,
-                "{}{{quantile=\"{}\"",
-                self.metric_name(),
-                quantile.as_f64()
-            )?;
-            for label in self.labels().iter() {
-                write!(f, ",{}={:?}", label.name(), label.value())?;
-            }
-            writeln!(f, "}} {}{}", MetricValue(value), timestamp)?;
+            write!(f, "{}", self.metric_name())?;
+            self.labels().with_labels(|labels| {
+                write_labels_with_reserved(
+                    f,
+                    ("quantile", quantile),
+                    labels,
+                    self.0.sort_reserved_label,
+                )
+            })?;
+            writeln!(f, " {}{}", MetricValue(value), timestamp)?;
         }
         writeln!(
             f,
@@ -202,8 +323,12 @@ pub struct SummaryBuilder {
     help: Option<String>,
     labels: Vec<(String, String)>,
     window: Duration,
-    quantiles: Vec<f64>,
+    quantiles: Vec<(f64, f64)>,
     registries: Vec<Registry>,
+    preserve_label_order: bool,
+    sort_reserved_label: bool,
+    window_label: bool,
+    interpolated: bool,
 }
 impl SummaryBuilder {
     /// Makes a builder for summary named `name`.
@@ -217,6 +342,10 @@ impl SummaryBuilder {
             window,
             quantiles: Vec::new(),
             registries: Vec::new(),
+            preserve_label_order: false,
+            sort_reserved_label: false,
+            window_label: false,
+            interpolated: false,
         }
     }
 
@@ -244,9 +373,48 @@ impl SummaryBuilder {
     ///
     /// The name `"quantile"` is reserved for designating summary quantiles.
     pub fn label(&mut self, name: &str, value: &str) -> &mut Self {
+        if let Some(l) = self.labels.iter_mut().find(|l| l.0 == name) {
+            l.1 = value.to_string();
+        } else {
+            self.labels.push((name.to_string(), value.to_string()));
+        }
+        self
+    }
+
+    /// Adds labels.
+    ///
+    /// This is equivalent to calling `label` for each of the given name-value pairs.
+    pub fn labels<I, K, V>(&mut self, labels: I) -> &mut Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        for (name, value) in labels {
+            self.label(name.as_ref(), value.as_ref());
+        }
+        self
+    }
+
+    /// Removes the label named `name` if it has been added.
+    pub fn remove_label(&mut self, name: &str) -> &mut Self {
         self.labels.retain(|l| l.0 != name);
-        self.labels.push((name.to_string(), value.to_string()));
-        self.labels.sort();
+        self
+    }
+
+    /// Keeps the labels of the resulting summaries in insertion order instead of sorting them by name.
+    ///
+    /// This also applies to the labels inserted via `labels_mut` after the summary is built.
+    pub fn preserve_label_order(&mut self) -> &mut Self {
+        self.preserve_label_order = true;
+        self
+    }
+
+    /// Places the `quantile` label among the user defined labels in name order when exposing the resulting summaries.
+    ///
+    /// By default, the `quantile` label precedes the user defined labels.
+    pub fn sort_reserved_label(&mut self) -> &mut Self {
+        self.sort_reserved_label = true;
         self
     }
 
@@ -256,17 +424,110 @@ impl SummaryBuilder {
         self
     }
 
+    /// Clears the current registries, then sets `registry` as the new one.
+    pub fn set_registry(&mut self, registry: Registry) -> &mut Self {
+        self.registries = vec![registry];
+        self
+    }
+
     /// Adds the default registry.
+    #[cfg(not(feature = "no-default-registry"))]
     pub fn default_registry(&mut self) -> &mut Self {
         self.registry(default_registry())
     }
 
     /// Adds a quantile.
+    ///
+    /// The value of the quantile will be computed exactly.
     pub fn quantile(&mut self, quantile: f64) -> &mut Self {
-        self.quantiles.push(quantile);
+        self.quantile_with_error(quantile, 0.0)
+    }
+
+    /// Adds a quantile which has the tolerated error `error`.
+    ///
+    /// The error does not affect the text output,
+    /// but is available via `Quantile::error` for reporting the accuracy of the quantile.
+    pub fn quantile_with_error(&mut self, quantile: f64, error: f64) -> &mut Self {
+        self.quantiles.push((quantile, error));
         self
     }
 
+    /// Adds the `window_seconds` label whose value is the length of the window in seconds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use prometrics::metrics::SummaryBuilder;
+    ///
+    /// let summary = SummaryBuilder::new("foo", Duration::from_millis(1500))
+    ///     .window_label()
+    ///     .finish()
+    ///     .unwrap();
+    /// assert_eq!(summary.window(), Duration::from_millis(1500));
+    /// assert_eq!(summary.labels().get_value("window_seconds"), Some("1.5".to_owned()));
+    /// ```
+    pub fn window_label(&mut self) -> &mut Self {
+        self.window_label = true;
+        self
+    }
+
+    /// Makes resulting summaries calculate quantiles by linear interpolation.
+    ///
+    /// The value of the quantile `q` over `n` sorted samples `x[0], ..., x[n - 1]` is
+    /// interpolated between `x[floor(h)]` and `x[floor(h) + 1]` where `h = (n - 1) * q`
+    /// (i.e., the "type 7" definition, which is the default of R and NumPy).
+    ///
+    /// By default, the value is the sample `x[min(floor(n * q), n - 1)]`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use prometrics::metrics::SummaryBuilder;
+    ///
+    /// let summary = SummaryBuilder::new("foo", Duration::from_secs(10))
+    ///     .quantile(0.5)
+    ///     .interpolated()
+    ///     .finish()
+    ///     .unwrap();
+    /// summary.observe(10);
+    /// summary.observe(20);
+    /// assert_eq!(summary.quantiles()[0].1, 15.0);
+    /// ```
+    pub fn interpolated(&mut self) -> &mut Self {
+        self.interpolated = true;
+        self
+    }
+
+    /// Adds a sequence of quantiles.
+    ///
+    /// Both of owned values (e.g., `Vec<f64>`) and borrowed ones (e.g., `&[f64]`) are accepted.
+    pub fn quantiles<I>(&mut self, quantiles: I) -> &mut Self
+    where
+        I: IntoIterator,
+        I::Item: Borrow<f64>,
+    {
+        self.quantiles
+            .extend(quantiles.into_iter().map(|q| (*q.borrow(), 0.0)));
+        self
+    }
+
+    /// Validates the settings of this builder without building a summary.
+    ///
+    /// This performs the same checks as `finish`, but does not register anything.
+    ///
+    /// # Errors
+    ///
+    /// This method will return `Err(_)` if and only if `finish` would fail
+    /// for reasons other than the registration to the registries.
+    pub fn validate(&self) -> Result<()> {
+        track!(self.build_name())?;
+        track!(self.build_labels())?;
+        track!(self.build_quantiles())?;
+        Ok(())
+    }
+
     /// Builds a summary.
     ///
     /// # Errors
@@ -274,41 +535,74 @@ impl SummaryBuilder {
     /// This method will return `Err(_)` if one of the following conditions is satisfied:
     ///
     /// - Any of the name of the metric or labels is malformed
-    /// - There is a quantile whose value is less than `0.0` or greater than `1.0`
+    /// - There is a quantile whose value or error is less than `0.0` or greater than `1.0`
+    /// - Any of the registries has lost its gatherer (`ErrorKind::RegistrationFailed`)
+    ///
+    /// In the latter case, the summary is dropped, so it never appears in the other registries either.
     pub fn finish(&self) -> Result<Summary> {
+        let quantile_name = track!(self.build_name())?;
+        let labels = track!(self.build_labels())?;
+        let quantiles = track!(self.build_quantiles())?;
+        let inner = Inner {
+            quantile_name,
+            labels: Labels::new(labels, self.preserve_label_order),
+            help: self.help.clone(),
+            timestamp: Timestamp::new(),
+            window: self.window,
+            quantiles,
+            sort_reserved_label: self.sort_reserved_label,
+            interpolated: self.interpolated,
+            samples: (0..SAMPLE_SHARDS)
+                .map(|_| Mutex::new(VecDeque::new()))
+                .collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicCompensatedF64::new(0.0),
+            enabled: AtomicBool::new(true),
+            observed: AtomicBool::new(false),
+            evicted: AtomicU64::new(0),
+            cache: Mutex::new(None),
+        };
+        let summary = Summary(Arc::new(inner));
+        track!(Registry::register_to_all(&self.registries, || summary.collector()))?;
+        Ok(summary)
+    }
+
+    fn build_name(&self) -> Result<MetricName> {
         let namespace = self.namespace.as_ref().map(AsRef::as_ref);
         let subsystem = self.subsystem.as_ref().map(AsRef::as_ref);
-        let quantile_name = track!(MetricName::new(namespace, subsystem, &self.name))?;
-        let labels = track!(self
+        track!(MetricName::new(namespace, subsystem, &self.name))
+    }
+
+    fn build_labels(&self) -> Result<LabelVec> {
+        let mut labels: LabelVec = track!(self
             .labels
             .iter()
-            .map(|&(ref name, ref value)| {
+            .map(|(name, value)| {
                 track_assert_ne!(name, "quantile", ErrorKind::InvalidInput);
+                if self.window_label {
+                    track_assert_ne!(name, "window_seconds", ErrorKind::InvalidInput);
+                }
                 track!(Label::new(name, value))
             })
             .collect::<Result<_>>())?;
+        if self.window_label {
+            let seconds = MetricValue(timestamp::duration_to_seconds(self.window)).to_string();
+            labels.push(track!(Label::new("window_seconds", &seconds))?);
+        }
+        if !self.preserve_label_order {
+            labels.sort();
+        }
+        Ok(labels)
+    }
+
+    fn build_quantiles(&self) -> Result<Vec<Quantile>> {
         let mut quantiles = track!(self
             .quantiles
             .iter()
-            .map(|quantile| track!(Quantile::new(*quantile)))
+            .map(|&(quantile, error)| track!(Quantile::with_error(quantile, error)))
             .collect::<Result<Vec<_>>>())?;
-        quantiles.sort_by(|a, b| a.as_f64().partial_cmp(&b.as_f64()).expect("Never fails"));
-        let inner = Inner {
-            quantile_name,
-            labels: Labels::new(labels),
-            help: self.help.clone(),
-            timestamp: Timestamp::new(),
-            window: self.window,
-            quantiles,
-            samples: Mutex::new(VecDeque::new()),
-            count: AtomicU64::new(0),
-            sum: AtomicF64::new(0.0),
-        };
-        let summary = Summary(Arc::new(inner));
-        for r in &self.registries {
-            r.register(summary.collector());
-        }
-        Ok(summary)
+        quantiles.sort();
+        Ok(quantiles)
     }
 }
 
@@ -316,14 +610,34 @@ impl SummaryBuilder {
 #[derive(Debug, Clone)]
 pub struct SummaryCollector(Weak<Inner>);
 impl Collect for SummaryCollector {
-    type Metrics = iter::Once<Metric>;
+    type Metrics = option::IntoIter<Metric>;
     fn collect(&mut self) -> Option<Self::Metrics> {
-        self.0
-            .upgrade()
-            .map(|inner| iter::once(Metric::Summary(Summary(inner))))
+        self.0.upgrade().map(|inner| {
+            let enabled = inner.enabled.load(Ordering::Relaxed);
+            Some(Metric::Summary(Summary(inner)))
+                .filter(|_| enabled)
+                .into_iter()
+        })
     }
 }
 
+/// The number of shards of the sample buffer of a summary.
+///
+/// Each thread observes values into one of the shards, so that concurrent observers
+/// rarely contend for the same lock. The shards are merged when quantiles are calculated.
+const SAMPLE_SHARDS: usize = 16;
+
+type Samples = VecDeque<(SystemTime, f64)>;
+
+// The `(count, evicted)` pair at the time of the last `Summary::quantiles` invocation, and its result.
+type QuantileCache = ((u64, u64), Vec<(Quantile, f64)>);
+
+static NEXT_SHARD_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD_INDEX: usize = NEXT_SHARD_INDEX.fetch_add(1, Ordering::Relaxed) % SAMPLE_SHARDS;
+}
+
 #[derive(Debug)]
 struct Inner {
     quantile_name: MetricName,
@@ -332,9 +646,18 @@ struct Inner {
     timestamp: Timestamp,
     window: Duration,
     quantiles: Vec<Quantile>,
-    samples: Mutex<VecDeque<(SystemTime, f64)>>,
+    sort_reserved_label: bool,
+    interpolated: bool,
+    samples: Box<[Mutex<Samples>]>,
     count: AtomicU64,
-    sum: AtomicF64,
+    sum: AtomicCompensatedF64,
+    enabled: AtomicBool,
+    observed: AtomicBool,
+
+    // The number of the samples which have left the window so far.
+    evicted: AtomicU64,
+
+    cache: Mutex<Option<QuantileCache>>,
 }
 
 #[cfg(test)]
@@ -377,4 +700,224 @@ foo_sum 112
 foo_count 5"#
         );
     }
+
+    #[test]
+    fn quantiles_works() {
+        let summary = track_try_unwrap!(SummaryBuilder::new("foo", Duration::from_secs(10))
+            .quantiles(&[0.75, 0.25][..])
+            .quantile(0.5)
+            .finish());
+        summary.observe(1.0);
+        assert_eq!(
+            summary
+                .quantiles()
+                .into_iter()
+                .map(|(q, _)| q.as_f64())
+                .collect::<Vec<_>>(),
+            [0.25, 0.5, 0.75]
+        );
+    }
+
+    #[test]
+    fn interpolated_works() {
+        fn quantiles(values: &[f64], interpolated: bool) -> Vec<f64> {
+            let mut builder = SummaryBuilder::new("foo", Duration::from_secs(10));
+            builder.quantiles(&[0.0, 0.25, 0.5, 0.9, 1.0][..]);
+            if interpolated {
+                builder.interpolated();
+            }
+            let summary = track_try_unwrap!(builder.finish());
+            for &v in values {
+                summary.observe(v);
+            }
+            summary.quantiles().into_iter().map(|(_, v)| v).collect()
+        }
+        fn assert_approx_eq(actual: Vec<f64>, expected: &[f64]) {
+            assert_eq!(actual.len(), expected.len());
+            for (a, e) in actual.iter().zip(expected.iter()) {
+                assert!(
+                    (a - e).abs() < 1e-9,
+                    "actual={:?}, expected={:?}",
+                    actual,
+                    expected
+                );
+            }
+        }
+
+        // The expected values are the ones of `quantile(x, probs, type = 7)` in R.
+        assert_approx_eq(
+            quantiles(&[10.0, 20.0], true),
+            &[10.0, 12.5, 15.0, 19.0, 20.0],
+        );
+        assert_approx_eq(
+            quantiles(&[7.0, 3.0, 1.0, 9.0, 5.0, 2.0, 10.0, 4.0, 8.0, 6.0], true),
+            &[1.0, 3.25, 5.5, 9.1, 10.0],
+        );
+        assert_approx_eq(
+            quantiles(&[0.5, 1.5, 100.0, 2.0, 4.0], true),
+            &[0.5, 1.5, 2.0, 61.6, 100.0],
+        );
+        assert_approx_eq(quantiles(&[3.0], true), &[3.0, 3.0, 3.0, 3.0, 3.0]);
+
+        // Not interpolated.
+        assert_approx_eq(
+            quantiles(&[10.0, 20.0], false),
+            &[10.0, 10.0, 20.0, 20.0, 20.0],
+        );
+    }
+
+    #[test]
+    fn quantile_with_error_works() {
+        let summary = track_try_unwrap!(SummaryBuilder::new("foo", Duration::from_secs(10))
+            .quantile(0.5)
+            .quantile_with_error(0.99, 0.001)
+            .finish());
+        summary.observe(1.0);
+        assert_eq!(
+            summary
+                .quantiles()
+                .into_iter()
+                .map(|(q, _)| (q.as_f64(), q.error()))
+                .collect::<Vec<_>>(),
+            [(0.5, 0.0), (0.99, 0.001)]
+        );
+        assert_eq!(
+            summary.to_string().lines().next(),
+            Some(r#"foo{quantile="0.5"} 1"#)
+        );
+
+        assert!(SummaryBuilder::new("foo", Duration::from_secs(10))
+            .quantile_with_error(0.5, 1.5)
+            .finish()
+            .is_err());
+    }
+
+    #[test]
+    fn sum_is_compensated() {
+        let summary =
+            track_try_unwrap!(SummaryBuilder::new("foo", Duration::from_secs(10)).finish());
+        summary.observe(1e16);
+        for _ in 0..10_000 {
+            summary.observe(1.0);
+        }
+        assert_eq!(summary.sum(), 1e16 + 10_000.0);
+    }
+
+    #[test]
+    fn concurrent_observation_works() {
+        use std::thread;
+
+        let summary = track_try_unwrap!(SummaryBuilder::new("foo", Duration::from_secs(10))
+            .quantile(0.5)
+            .finish());
+        let handles = (0..4)
+            .map(|i| {
+                let summary = summary.clone();
+                thread::spawn(move || {
+                    for j in 0..25 {
+                        summary.observe((i * 25 + j) as f64);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(summary.count(), 100);
+        assert_eq!(summary.quantiles()[0].1, 50.0);
+    }
+
+    #[test]
+    fn window_works() {
+        use std::thread;
+
+        let summary = track_try_unwrap!(SummaryBuilder::new("foo", Duration::from_millis(1))
+            .quantile(0.5)
+            .finish());
+        summary.observe(1.0);
+        thread::sleep(Duration::from_millis(10));
+        assert!(summary.quantiles().is_empty());
+        assert_eq!(summary.count(), 1);
+    }
+
+    #[test]
+    fn observe_duration_works() {
+        let summary =
+            track_try_unwrap!(SummaryBuilder::new("foo", Duration::from_secs(60)).finish());
+        summary.observe_duration(Duration::from_millis(1500));
+        assert_eq!(summary.count(), 1);
+        assert_eq!(summary.sum(), 1.5);
+    }
+
+    #[test]
+    fn cached_quantiles_are_invalidated() {
+        use std::thread;
+
+        let summary = track_try_unwrap!(SummaryBuilder::new("foo", Duration::from_millis(100))
+            .quantile(0.5)
+            .finish());
+        let values = |s: &Summary| {
+            s.quantiles()
+                .into_iter()
+                .map(|(_, v)| v)
+                .collect::<Vec<_>>()
+        };
+
+        summary.observe(1.0);
+        assert_eq!(values(&summary), [1.0]);
+        assert_eq!(values(&summary), [1.0]);
+
+        summary.observe(3.0);
+        summary.observe(5.0);
+        assert_eq!(values(&summary), [3.0]);
+
+        thread::sleep(Duration::from_millis(150));
+        assert!(values(&summary).is_empty());
+    }
+
+    #[test]
+    fn nan_is_rejected() {
+        let summary = track_try_unwrap!(SummaryBuilder::new("foo", Duration::from_secs(10))
+            .quantile(0.5)
+            .finish());
+        summary.observe(1.0);
+        assert_eq!(
+            summary.try_observe(f64::NAN).err().map(|e| *e.kind()),
+            Some(ErrorKind::InvalidInput)
+        );
+        assert_eq!(summary.count(), 1);
+        assert_eq!(summary.sum(), 1.0);
+        assert_eq!(summary.quantiles().len(), 1);
+
+        summary.try_observe(2.0).unwrap();
+        summary.observe(3u32);
+        assert_eq!(summary.count(), 3);
+        assert_eq!(summary.sum(), 6.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn observe_nan_panics() {
+        let summary =
+            track_try_unwrap!(SummaryBuilder::new("foo", Duration::from_secs(10)).finish());
+        summary.observe(f64::NAN);
+    }
+
+    #[test]
+    #[should_panic]
+    fn observe_f32_nan_panics() {
+        let summary =
+            track_try_unwrap!(SummaryBuilder::new("foo", Duration::from_secs(10)).finish());
+        summary.observe(f32::NAN);
+    }
+
+    #[test]
+    fn observed_works() {
+        let summary =
+            track_try_unwrap!(SummaryBuilder::new("foo", Duration::from_secs(10)).finish());
+        assert!(summary.try_observe(f64::NAN).is_err());
+        assert!(!summary.observed());
+        summary.observe(1.0);
+        assert!(summary.observed());
+    }
 }
@@ -0,0 +1,106 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use metrics::{Counter, Gauge, Histogram};
+use timestamp;
+
+/// A future which measures the elapsed time until the inner future completes.
+///
+/// The measurement starts when this future is created (not when it is first polled),
+/// and includes the time spent while the inner future is suspended.
+///
+/// This is created by the `time_async` methods of `Counter`, `Gauge` and `Histogram`.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct TimedFuture<F> {
+    future: F,
+    start: Instant,
+    target: Target,
+}
+impl<F: Future> TimedFuture<F> {
+    pub(crate) fn counter(future: F, counter: Counter) -> Self {
+        Self::new(future, Target::Counter(counter))
+    }
+    pub(crate) fn gauge(future: F, gauge: Gauge) -> Self {
+        Self::new(future, Target::Gauge(gauge))
+    }
+    pub(crate) fn histogram(future: F, histogram: Histogram) -> Self {
+        Self::new(future, Target::Histogram(histogram))
+    }
+    fn new(future: F, target: Target) -> Self {
+        TimedFuture {
+            future,
+            start: Instant::now(),
+            target,
+        }
+    }
+}
+impl<F: Future> Future for TimedFuture<F> {
+    type Output = F::Output;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // SAFETY: `future` is never moved out of `self`, and the other fields are not pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        match future.poll(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(output) => {
+                let elapsed = timestamp::duration_to_seconds(this.start.elapsed());
+                this.target.record(elapsed);
+                Poll::Ready(output)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Target {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+impl Target {
+    fn record(&self, elapsed: f64) {
+        match *self {
+            Target::Counter(ref m) => m.add(elapsed).expect("Never fails"),
+            Target::Gauge(ref m) => m.set(elapsed),
+            Target::Histogram(ref m) => m.observe(elapsed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+
+    use metrics::HistogramBuilder;
+
+    struct YieldOnce(bool);
+    impl Future for YieldOnce {
+        type Output = usize;
+        fn poll(mut self: Pin<&mut Self>, _cx: &mut Context) -> Poll<usize> {
+            if self.0 {
+                Poll::Ready(10)
+            } else {
+                self.0 = true;
+                Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn it_works() {
+        let histogram = track_try_unwrap!(HistogramBuilder::new("foo").finish());
+        let mut future = Box::pin(histogram.time_async(YieldOnce(false)));
+        let mut cx = Context::from_waker(Waker::noop());
+
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(histogram.count(), 0);
+
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Ready(10));
+        assert_eq!(histogram.count(), 1);
+    }
+}
@@ -1,36 +1,197 @@
 //! Summary quantile.
 use std::cmp;
+use std::fmt;
 
 use {ErrorKind, Result};
 
 /// Summary quantile.
-#[derive(Debug, Clone, Copy, PartialOrd, PartialEq)]
-pub struct Quantile(f64);
+///
+/// A quantile consists of a rank (e.g., `0.99`) and a tolerated error of the rank (e.g., `0.001`).
+/// Note that quantiles are compared (and ordered) only by their ranks.
+#[derive(Debug, Clone, Copy)]
+pub struct Quantile {
+    rank: f64,
+    error: f64,
+}
 impl Quantile {
     /// Makes a new `Quantile` instance.
     ///
+    /// The error of the resulting instance is `0.0` (i.e., the quantile is computed exactly).
+    ///
     /// # Errors
     ///
     /// If `quantile` is not in the range `0.0...1.0`,
     /// this function will return `ErrorKind::InvalidInput` error.
     pub fn new(quantile: f64) -> Result<Self> {
-        track_assert!(
+        track!(Self::with_error(quantile, 0.0))
+    }
+
+    /// Makes a new `Quantile` instance without checking the range of `quantile`.
+    ///
+    /// This is intended for compile-time-known ranks (e.g., in `static` definitions).
+    /// Use `Quantile::new` for runtime inputs.
+    ///
+    /// The caller must ensure that `quantile` is in the range `0.0...1.0`;
+    /// it is only checked by a debug assertion.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::quantile::Quantile;
+    ///
+    /// static QUANTILES: [Quantile; 2] = [Quantile::new_unchecked(0.5), Quantile::new_unchecked(0.99)];
+    /// assert_eq!(QUANTILES[1], Quantile::new(0.99).unwrap());
+    /// assert_eq!(QUANTILES[1].error(), 0.0);
+    /// ```
+    pub const fn new_unchecked(quantile: f64) -> Self {
+        debug_assert!(
             0.0 <= quantile && quantile <= 1.0,
+            "quantile must be in the range 0.0...1.0"
+        );
+        Quantile {
+            rank: quantile,
+            error: 0.0,
+        }
+    }
+
+    /// Makes a new `Quantile` instance which has the tolerated error `error`.
+    ///
+    /// # Errors
+    ///
+    /// If `quantile` or `error` is not in the range `0.0...1.0`,
+    /// this function will return `ErrorKind::InvalidInput` error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::quantile::Quantile;
+    ///
+    /// let quantile = Quantile::with_error(0.99, 0.001).unwrap();
+    /// assert_eq!(quantile.as_f64(), 0.99);
+    /// assert_eq!(quantile.error(), 0.001);
+    /// assert_eq!(quantile, Quantile::new(0.99).unwrap());
+    ///
+    /// assert!(Quantile::with_error(0.99, -0.1).is_err());
+    /// ```
+    pub fn with_error(quantile: f64, error: f64) -> Result<Self> {
+        track_assert!(
+            (0.0..=1.0).contains(&quantile),
             ErrorKind::InvalidInput,
             "quantile:{}",
             quantile
         );
-        Ok(Quantile(quantile))
+        track_assert!(
+            (0.0..=1.0).contains(&error),
+            ErrorKind::InvalidInput,
+            "error:{}",
+            error
+        );
+        Ok(Quantile {
+            rank: quantile,
+            error,
+        })
     }
 
     /// Converts `Quantile` to `f64`.
     pub fn as_f64(&self) -> f64 {
-        self.0
+        self.rank
+    }
+
+    /// Returns the tolerated error of the rank of this quantile.
+    ///
+    /// `0.0` means that the quantile value is computed exactly.
+    pub fn error(&self) -> f64 {
+        self.error
+    }
+
+    // Returns the value of this quantile over `samples`, which must be sorted and non-empty.
+    //
+    // If `interpolated` is `true`, the value is linearly interpolated between
+    // the adjacent samples (i.e., the "type 7" definition).
+    pub(crate) fn value_of(&self, samples: &[f64], interpolated: bool) -> f64 {
+        let last = samples.len() - 1;
+        if interpolated {
+            let h = last as f64 * self.rank;
+            let lower = h.floor() as usize;
+            let upper = cmp::min(lower + 1, last);
+            samples[lower] + (h - lower as f64) * (samples[upper] - samples[lower])
+        } else {
+            let index = (self.rank * samples.len() as f64).floor() as usize;
+            samples[cmp::min(index, last)]
+        }
+    }
+}
+impl fmt::Display for Quantile {
+    /// Writes the canonical string form of the rank of this quantile.
+    ///
+    /// The rank is rendered as the shortest decimal string which round-trips to the same `f64`
+    /// (never in scientific notation and without trailing zeros), so a summary always yields
+    /// the same `quantile` label value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::quantile::Quantile;
+    ///
+    /// assert_eq!(Quantile::new(0.5).unwrap().to_string(), "0.5");
+    /// assert_eq!(Quantile::new(0.950).unwrap().to_string(), "0.95");
+    /// assert_eq!(Quantile::new(1.0).unwrap().to_string(), "1");
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.rank == 0.0 {
+            write!(f, "0")
+        } else {
+            write!(f, "{}", self.rank)
+        }
+    }
+}
+impl PartialEq for Quantile {
+    fn eq(&self, other: &Self) -> bool {
+        self.rank == other.rank
+    }
+}
+impl Eq for Quantile {}
+impl PartialOrd for Quantile {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
     }
 }
 impl Ord for Quantile {
     fn cmp(&self, other: &Self) -> cmp::Ordering {
-        self.partial_cmp(other).expect("Never fails")
+        self.rank.partial_cmp(&other.rank).expect("Never fails")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn new_unchecked_asserts_range() {
+        let _ = Quantile::new_unchecked(1.5);
+    }
+
+    #[test]
+    fn display_works() {
+        let ranks = [0.5, 0.95, 0.999, 1.0, 0.0, -0.0, 0.3333333333, 1.0 / 3.0];
+        let strings = ranks
+            .iter()
+            .map(|&r| Quantile::new(r).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            strings,
+            [
+                "0.5",
+                "0.95",
+                "0.999",
+                "1",
+                "0",
+                "0",
+                "0.3333333333",
+                "0.3333333333333333"
+            ]
+        );
     }
 }
-impl Eq for Quantile {}
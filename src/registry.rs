@@ -1,59 +1,340 @@
 use std::cmp;
+use std::collections::HashSet;
 use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::Instant;
 
-use metric::{Metric, MetricFamilies, MetricFamily};
-use Collect;
+use metric::{Metric, MetricFamilies, MetricFamily, MetricKind, MetricName, Metrics};
+use metrics::GaugeBuilder;
+use timestamp;
+use {BoxedCollect, Collect, ErrorKind, Result};
 
+#[cfg(not(feature = "no-default-registry"))]
 lazy_static! {
-    static ref DEFAULT_GATHERER: Mutex<Gatherer> = Mutex::new(Gatherer::new());
+    static ref DEFAULT_GATHERER: RwLock<Gatherer> = RwLock::new(Gatherer::new());
 }
 
 /// Returns the global default `Gatherer`.
-pub fn default_gatherer() -> &'static Mutex<Gatherer> {
+///
+/// Since `Gatherer::gather` takes `&self`, concurrent gatherings only need the read lock.
+/// The write lock is needed only for the setters (e.g., `Gatherer::set_suppress_zero`).
+///
+/// This is not available if the `no-default-registry` feature is enabled.
+#[cfg(not(feature = "no-default-registry"))]
+pub fn default_gatherer() -> &'static RwLock<Gatherer> {
     &DEFAULT_GATHERER
 }
 
 /// Returns the global default `Registry`.
+///
+/// This is not available if the `no-default-registry` feature is enabled.
+#[cfg(not(feature = "no-default-registry"))]
 pub fn default_registry() -> Registry {
-    if let Ok(gatherer) = default_gatherer().lock() {
+    if let Ok(gatherer) = default_gatherer().read() {
         gatherer.registry()
     } else {
         let (tx, _) = mpsc::channel();
-        Registry { tx }
+        Registry {
+            hub: Arc::new(Mutex::new(Hub::new(tx))),
+            scope: None,
+        }
     }
 }
 
 /// Collector registry.
 #[derive(Debug, Clone)]
 pub struct Registry {
-    tx: mpsc::Sender<Collector>,
+    hub: Arc<Mutex<Hub>>,
+
+    // The scope of the `ScopedRegistry` which made this registry.
+    scope: Option<Arc<Scope>>,
 }
 impl Registry {
     /// Registers a collector.
     ///
-    /// If `collector.collect()` returns `None`, it will be deregistered from this.
-    pub fn register<C>(&self, mut collector: C)
+    /// The collector is delivered to the gatherer associated with this registry
+    /// and to all gatherers subscribing to it (see `Gatherer::subscribe`).
+    ///
+    /// If `collector.collect()` returns `None`, it will be deregistered from the gatherer.
+    ///
+    /// If all of the gatherers have been dropped, the collector is silently discarded.
+    /// Use `try_register` to detect it.
+    pub fn register<C>(&self, collector: C)
     where
         C: Collect + Send + 'static,
     {
-        let f = move |metrics: &mut Vec<Metric>| {
-            if let Some(m) = collector.collect() {
-                metrics.extend(m);
-                true
-            } else {
-                false
-            }
+        let _ = self.try_register(collector);
+    }
+
+    /// Registers a collector, and reports whether it has been delivered to any gatherer.
+    ///
+    /// # Errors
+    ///
+    /// If the gatherer associated with this registry and all of its subscribers have been dropped,
+    /// this method returns `ErrorKind::RegistrationFailed` error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let counter = CounterBuilder::new("foo").finish().unwrap();
+    ///
+    /// let gatherer = Gatherer::new();
+    /// let registry = gatherer.registry();
+    /// assert!(registry.try_register(counter.collector()).is_ok());
+    ///
+    /// std::mem::drop(gatherer);
+    /// assert!(registry.try_register(counter.collector()).is_err());
+    /// ```
+    pub fn try_register<C>(&self, collector: C) -> Result<()>
+    where
+        C: Collect + Send + 'static,
+    {
+        let collector: Box<dyn ErasedCollect + Send> = if let Some(ref scope) = self.scope {
+            Box::new(Scoped {
+                collector,
+                scope: Arc::clone(scope),
+            })
+        } else {
+            Box::new(collector)
+        };
+        let collector = Collector(Arc::new(Mutex::new(collector)));
+        let delivered = self
+            .hub
+            .lock()
+            .map(|mut hub| hub.register(collector))
+            .unwrap_or(false);
+        track_assert!(
+            delivered,
+            ErrorKind::RegistrationFailed,
+            "The gatherer of the registry has been dropped"
+        );
+        Ok(())
+    }
+
+    // Registers the collectors made by `f` to each of `registries`.
+    //
+    // All the registrations are attempted even if some of them fail,
+    // and the positions of the failed registries are reported by the resulting error.
+    pub(crate) fn register_to_all<F, C>(registries: &[Registry], f: F) -> Result<()>
+    where
+        F: Fn() -> C,
+        C: Collect + Send + 'static,
+    {
+        let failed = registries
+            .iter()
+            .enumerate()
+            .filter(|&(_, r)| r.try_register(f()).is_err())
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        track_assert!(
+            failed.is_empty(),
+            ErrorKind::RegistrationFailed,
+            "Failed to register to the registries at {:?} (zero-origin, in the order they were added)",
+            failed
+        );
+        Ok(())
+    }
+
+    /// Registers a type-erased collector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::{BoxedCollect, Collect, Gatherer};
+    /// use prometrics::metrics::{CounterBuilder, GaugeBuilder};
+    ///
+    /// let gatherer = Gatherer::new();
+    /// let counter = CounterBuilder::new("foo").finish().unwrap();
+    /// let gauge = GaugeBuilder::new("bar").finish().unwrap();
+    ///
+    /// let collectors: Vec<BoxedCollect> = vec![counter.collector().boxed(), gauge.collector().boxed()];
+    /// for c in collectors {
+    ///     gatherer.registry().register_boxed(c);
+    /// }
+    /// assert_eq!(gatherer.gather().into_vec().len(), 2);
+    /// ```
+    pub fn register_boxed(&self, collector: BoxedCollect) {
+        self.register(collector);
+    }
+}
+
+/// A registry which deregisters everything registered through it when dropped.
+///
+/// All the collectors registered to the registries returned by `ScopedRegistry::registry`
+/// (e.g., via `CounterBuilder::registry`) are deregistered from the gatherers
+/// once this is dropped, regardless of whether the handles of the metrics are still alive.
+/// This is useful for metrics whose lifetimes are bounded by something like a connection.
+///
+/// This relies on the deregistration of collectors returning `None` (see `Registry::register`),
+/// so the collectors are actually removed from each gatherer by the next gathering.
+/// Collectors registered through the registries of a dropped scope are never gathered.
+///
+/// # Examples
+///
+/// ```
+/// use prometrics::{Gatherer, ScopedRegistry};
+/// use prometrics::metrics::CounterBuilder;
+///
+/// let gatherer = Gatherer::new();
+/// let _foo = CounterBuilder::new("foo").registry(gatherer.registry()).finish().unwrap();
+///
+/// let scope = ScopedRegistry::new(gatherer.registry());
+/// let bar = CounterBuilder::new("bar").registry(scope.registry()).finish().unwrap();
+/// assert_eq!(gatherer.gather().into_vec().len(), 2);
+///
+/// drop(scope);
+/// bar.increment();
+/// assert_eq!(gatherer.gather().to_text(), "# TYPE foo counter\nfoo 0\n");
+/// ```
+#[derive(Debug)]
+pub struct ScopedRegistry {
+    registry: Registry,
+}
+impl ScopedRegistry {
+    /// Makes a new `ScopedRegistry` which registers collectors to `registry`.
+    ///
+    /// If `registry` belongs to another `ScopedRegistry`,
+    /// the collectors are also deregistered when that one is dropped.
+    pub fn new(registry: Registry) -> Self {
+        let scope = Scope {
+            alive: AtomicBool::new(true),
+            parent: registry.scope,
         };
-        let _ = self.tx.send(Collector(Box::new(f)));
+        ScopedRegistry {
+            registry: Registry {
+                hub: registry.hub,
+                scope: Some(Arc::new(scope)),
+            },
+        }
+    }
+
+    /// Returns a `Registry` whose collectors belong to this scope.
+    pub fn registry(&self) -> Registry {
+        self.registry.clone()
+    }
+}
+impl Drop for ScopedRegistry {
+    fn drop(&mut self) {
+        if let Some(ref scope) = self.registry.scope {
+            scope.alive.store(false, Ordering::SeqCst);
+        }
     }
 }
 
-struct Collector(Box<dyn FnMut(&mut Vec<Metric>) -> bool + Send + 'static>);
+#[derive(Debug)]
+struct Scope {
+    alive: AtomicBool,
+    parent: Option<Arc<Scope>>,
+}
+impl Scope {
+    fn is_alive(&self) -> bool {
+        if !self.alive.load(Ordering::SeqCst) {
+            return false;
+        }
+        if let Some(ref parent) = self.parent {
+            parent.is_alive()
+        } else {
+            true
+        }
+    }
+}
+
+// A collector registered through the registry of a `ScopedRegistry`.
+struct Scoped<C> {
+    collector: C,
+    scope: Arc<Scope>,
+}
+impl<C: Collect> ErasedCollect for Scoped<C> {
+    fn collect_into(&mut self, metrics: &mut Vec<Metric>) -> bool {
+        self.scope.is_alive() && self.collector.collect_into(metrics)
+    }
+
+    fn describe(&self) -> Vec<(MetricName, MetricKind, Option<String>)> {
+        if self.scope.is_alive() {
+            Collect::describe(&self.collector)
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+type SharedCollect = Mutex<Box<dyn ErasedCollect + Send + 'static>>;
+
+#[derive(Clone)]
+struct Collector(Arc<SharedCollect>);
 impl Collector {
-    fn collect(&mut self, metrics: &mut Vec<Metric>) -> bool {
-        (self.0)(metrics)
+    fn collect(&self, metrics: &mut Vec<Metric>) -> bool {
+        self.0
+            .lock()
+            .map(|mut c| c.collect_into(metrics))
+            .unwrap_or(false)
+    }
+
+    fn describe(&self) -> Vec<(MetricName, MetricKind, Option<String>)> {
+        self.0.lock().map(|c| c.describe()).unwrap_or_default()
+    }
+}
+
+// The destinations of collectors registered to the registries of a gatherer.
+struct Hub {
+    senders: Vec<mpsc::Sender<Collector>>,
+
+    // Collectors registered so far, which are replayed to new subscribers.
+    collectors: Vec<Weak<SharedCollect>>,
+}
+impl Hub {
+    fn new(tx: mpsc::Sender<Collector>) -> Self {
+        Hub {
+            senders: vec![tx],
+            collectors: Vec::new(),
+        }
+    }
+
+    // Returns `false` if there are no live gatherers which received `collector`.
+    fn register(&mut self, collector: Collector) -> bool {
+        if self.collectors.len() == self.collectors.capacity() {
+            self.collectors.retain(|c| c.strong_count() > 0);
+        }
+        self.collectors.push(Arc::downgrade(&collector.0));
+        self.senders
+            .retain(|tx| tx.send(Collector(Arc::clone(&collector.0))).is_ok());
+        !self.senders.is_empty()
+    }
+
+    fn subscribe(&mut self, tx: mpsc::Sender<Collector>) {
+        for collector in self.collectors.iter().filter_map(|c| c.upgrade()) {
+            let _ = tx.send(Collector(collector));
+        }
+        self.senders.push(tx);
+    }
+}
+impl fmt::Debug for Hub {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hub {{ senders: {}, .. }}", self.senders.len())
+    }
+}
+
+trait ErasedCollect {
+    fn collect_into(&mut self, metrics: &mut Vec<Metric>) -> bool;
+    fn describe(&self) -> Vec<(MetricName, MetricKind, Option<String>)>;
+}
+impl<C: Collect> ErasedCollect for C {
+    fn collect_into(&mut self, metrics: &mut Vec<Metric>) -> bool {
+        if let Some(m) = self.collect() {
+            metrics.extend(m);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn describe(&self) -> Vec<(MetricName, MetricKind, Option<String>)> {
+        Collect::describe(self)
     }
 }
 impl fmt::Debug for Collector {
@@ -62,56 +343,460 @@ impl fmt::Debug for Collector {
     }
 }
 
+// The name and the kind which identify a metric family.
+type FamilyKey = (MetricName, MetricKind);
+
+// The name, the kind and the help of a metric family described by a collector.
+type Description = (MetricName, MetricKind, Option<String>);
+
+struct Hook(Box<dyn FnMut() + Send>);
+impl fmt::Debug for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hook(_)")
+    }
+}
+
+struct LintHook(Box<dyn FnMut(Lint) + Send>);
+impl fmt::Debug for LintHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LintHook(_)")
+    }
+}
+
+/// A suspicious configuration of a metric family, which is reported by `Gatherer::set_lint_hook`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lint {
+    /// The name of a counter does not end with `_total`.
+    MissingTotalSuffix(MetricName),
+
+    /// A histogram has no buckets other than the `+Inf` one.
+    SingleBucket(MetricName),
+
+    /// A metric family has no help.
+    MissingHelp(MetricName),
+}
+impl Lint {
+    /// Returns the name of the metric family which this lint is reported for.
+    pub fn metric_name(&self) -> &MetricName {
+        match *self {
+            Lint::MissingTotalSuffix(ref name) => name,
+            Lint::SingleBucket(ref name) => name,
+            Lint::MissingHelp(ref name) => name,
+        }
+    }
+
+    fn check(family: &MetricFamily) -> Vec<Lint> {
+        let mut lints = Vec::new();
+        let name = family.name();
+        if family.kind() == MetricKind::Counter && !name.to_string().ends_with("_total") {
+            lints.push(Lint::MissingTotalSuffix(name.clone()));
+        }
+        if let Metrics::Histogram(ref v) = *family.metrics() {
+            if v.iter().any(|h| h.cumulative_buckets().count() == 1) {
+                lints.push(Lint::SingleBucket(name.clone()));
+            }
+        }
+        if family.help().is_none() {
+            lints.push(Lint::MissingHelp(name.clone()));
+        }
+        lints
+    }
+}
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Lint::MissingTotalSuffix(ref name) => write!(
+                f,
+                "The name of the counter `{}` does not end with `_total`",
+                name
+            ),
+            Lint::SingleBucket(ref name) => write!(
+                f,
+                "The histogram `{}` has no buckets other than `+Inf`",
+                name
+            ),
+            Lint::MissingHelp(ref name) => write!(f, "The metric `{}` has no help", name),
+        }
+    }
+}
+
+// The collectors of a gatherer, and the receiver through which new ones are delivered.
+#[derive(Debug)]
+struct Collectors {
+    rx: mpsc::Receiver<Collector>,
+    list: Vec<Collector>,
+}
+impl Collectors {
+    fn snapshot(&mut self) -> Vec<Collector> {
+        while let Ok(collector) = self.rx.try_recv() {
+            self.list.push(collector);
+        }
+        self.list.clone()
+    }
+
+    fn remove(&mut self, dead: &HashSet<*const SharedCollect>) {
+        self.list.retain(|c| !dead.contains(&Arc::as_ptr(&c.0)));
+    }
+}
+
 /// Metrics gatherer.
 ///
 /// This can gather metrics that registered to registries which associated with the gatherer.
+///
+/// `gather` only requires a shared reference,
+/// so a gatherer wrapped in an `Arc` can be used by multiple threads concurrently.
 #[derive(Debug)]
 pub struct Gatherer {
     tx: mpsc::Sender<Collector>,
-    rx: mpsc::Receiver<Collector>,
-    collectors: Vec<Collector>,
+    hub: Arc<Mutex<Hub>>,
+    subscriptions: Vec<Arc<Mutex<Hub>>>,
+    collectors: Mutex<Collectors>,
+    dedup: bool,
+    suppress_zero: bool,
+
+    // The global namespace, which has been validated by `set_global_namespace`.
+    namespace: Option<String>,
+
+    hooks: Mutex<Vec<Hook>>,
+
+    // The duration (in seconds) and the series count of the previous gathering,
+    // if the self-metrics are enabled.
+    self_metrics: Option<Mutex<(f64, usize)>>,
+
+    // The hook receiving lints, and the families which have already been linted.
+    lint_hook: Option<Mutex<(LintHook, HashSet<FamilyKey>)>>,
 }
 impl Gatherer {
     /// Makes a new `Gatherer` instance.
     pub fn new() -> Self {
         let (tx, rx) = mpsc::channel();
         Gatherer {
+            hub: Arc::new(Mutex::new(Hub::new(tx.clone()))),
+            subscriptions: Vec::new(),
             tx,
-            rx,
-            collectors: Vec::new(),
+            collectors: Mutex::new(Collectors {
+                rx,
+                list: Vec::new(),
+            }),
+            dedup: false,
+            suppress_zero: false,
+            namespace: None,
+            hooks: Mutex::new(Vec::new()),
+            self_metrics: None,
+            lint_hook: None,
+        }
+    }
+
+    /// Sets whether duplicate metrics should be removed when gathering.
+    ///
+    /// By default (`false`), metrics that have the same name, kind and labels are aggregated
+    /// (e.g., the values of such counters are summed).
+    ///
+    /// If `true`, only the first one of such metrics is adopted and the rest are discarded.
+    /// This prevents double-counting when the same collector is registered more than once,
+    /// but also disables the aggregation of distinct metrics that have the same name and labels.
+    pub fn set_dedup(&mut self, dedup: bool) {
+        self.dedup = dedup;
+    }
+
+    /// Sets whether metrics which have never been updated should be omitted when gathering.
+    ///
+    /// If `true`, counters and gauges whose value is `0` and which have never been updated,
+    /// as well as histograms and summaries whose observation count is `0`, are omitted.
+    /// The `# HELP` and `# TYPE` lines of the families described by collectors are still emitted.
+    ///
+    /// This reduces the size of scrapes when there are many idle series, at the cost of
+    /// their continuity: a series which first appears with a non-zero value lacks its
+    /// starting point, so `rate()` and `increase()` over it underestimate the first increments.
+    /// Once a metric is updated, it is no longer omitted even if its value goes back to `0`.
+    ///
+    /// The default value is `false`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let mut gatherer = Gatherer::new();
+    /// gatherer.set_suppress_zero(true);
+    ///
+    /// let counter = CounterBuilder::new("foo").registry(gatherer.registry()).finish().unwrap();
+    /// assert_eq!(gatherer.gather().to_text(), "");
+    ///
+    /// counter.increment();
+    /// assert_eq!(gatherer.gather().to_text(), "# TYPE foo counter\nfoo 1\n");
+    /// ```
+    pub fn set_suppress_zero(&mut self, suppress_zero: bool) {
+        self.suppress_zero = suppress_zero;
+    }
+
+    /// Sets the namespace which is prepended to the name of every gathered metric.
+    ///
+    /// If a metric already has its own namespace, the global one is placed in front of it
+    /// (e.g., `{global}_{namespace}_{subsystem}_{name}`).
+    ///
+    /// By default, no global namespace is set.
+    ///
+    /// # Errors
+    ///
+    /// If `namespace` contains invalid characters,
+    /// this method returns `ErrorKind::InvalidInput` error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let mut gatherer = Gatherer::new();
+    /// gatherer.set_global_namespace("myapp").unwrap();
+    ///
+    /// let _foo = CounterBuilder::new("foo").registry(gatherer.registry()).finish().unwrap();
+    /// let _bar = CounterBuilder::new("bar")
+    ///     .namespace("http")
+    ///     .registry(gatherer.registry())
+    ///     .finish()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     gatherer.gather().to_text(),
+    ///     "# TYPE myapp_foo counter\nmyapp_foo 0\n# TYPE myapp_http_bar counter\nmyapp_http_bar 0\n"
+    /// );
+    /// ```
+    pub fn set_global_namespace(&mut self, namespace: &str) -> Result<()> {
+        track!(MetricName::validate_name(namespace), "{:?}", namespace)?;
+        self.namespace = Some(namespace.to_owned());
+        Ok(())
+    }
+
+    /// Sets whether the gatherer should expose metrics about its own gathering.
+    ///
+    /// If `true`, the result of `gather` includes the following gauges:
+    /// - `prometrics_gather_duration_seconds`: the time taken by the previous gathering
+    /// - `prometrics_gather_series`: the number of series produced by the previous gathering,
+    ///   excluding these gauges themselves
+    ///
+    /// These reflect the previous gathering rather than the ongoing one,
+    /// and are both zero in the first result.
+    /// They are not registered to any registry, so subscribers of this gatherer do not see them.
+    ///
+    /// The default value is `false`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let mut gatherer = Gatherer::new();
+    /// gatherer.set_self_metrics(true);
+    /// let _foo = CounterBuilder::new("foo").registry(gatherer.registry()).finish().unwrap();
+    ///
+    /// gatherer.gather();
+    /// let text = gatherer.gather().to_text_without_timestamps();
+    /// assert!(text.contains("# TYPE prometrics_gather_duration_seconds gauge\n"));
+    /// assert!(text.contains("\nprometrics_gather_series 1\n"));
+    /// ```
+    pub fn set_self_metrics(&mut self, enabled: bool) {
+        if !enabled {
+            self.self_metrics = None;
+        } else if self.self_metrics.is_none() {
+            self.self_metrics = Some(Mutex::new((0.0, 0)));
         }
     }
 
+    /// Sets the hook which receives the lints of suspicious metric configurations.
+    ///
+    /// Each metric family is checked when it first appears in the result of a gathering,
+    /// and `hook` is invoked with each of the detected lints (see `Lint` for the checks).
+    /// The lints are purely advisory: they never affect the gathered metrics.
+    /// This is useful for detecting instrumentation smells in tests.
+    ///
+    /// By default, no lints are reported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use prometrics::{Gatherer, Lint};
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let lints = Arc::new(Mutex::new(Vec::new()));
+    /// let mut gatherer = Gatherer::new();
+    /// let cloned = Arc::clone(&lints);
+    /// gatherer.set_lint_hook(move |lint: Lint| cloned.lock().unwrap().push(lint.to_string()));
+    ///
+    /// let _counter = CounterBuilder::new("requests")
+    ///     .help("Number of requests")
+    ///     .registry(gatherer.registry())
+    ///     .finish()
+    ///     .unwrap();
+    /// gatherer.gather();
+    /// gatherer.gather();
+    /// assert_eq!(
+    ///     *lints.lock().unwrap(),
+    ///     ["The name of the counter `requests` does not end with `_total`"]
+    /// );
+    /// ```
+    pub fn set_lint_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(Lint) + Send + 'static,
+    {
+        self.lint_hook = Some(Mutex::new((LintHook(Box::new(hook)), HashSet::new())));
+    }
+
     /// Returns a `Registry` associated with this gatherer.
     pub fn registry(&self) -> Registry {
         Registry {
-            tx: self.tx.clone(),
+            hub: Arc::clone(&self.hub),
+            scope: None,
+        }
+    }
+
+    /// Makes this gatherer also gather the collectors registered to `registry`.
+    ///
+    /// All the collectors registered to the gatherer associated with `registry`
+    /// (including ones registered before this invocation and ones registered to other registries
+    /// associated with the same gatherer) are shared with this gatherer.
+    /// Subscribing to the same gatherer more than once has no effect.
+    ///
+    /// Each gatherer invokes a shared collector independently.
+    /// When a collector returns `None`, it is deregistered only from the gatherer which received it.
+    /// Since the collectors of the built-in metrics hold weak references,
+    /// a dropped metric returns `None` to every gatherer, so it disappears from all of them.
+    /// Note that collectors which change state on collection are affected by each gatherer,
+    /// and the counters built with `CounterBuilder::reset_on_collect` are reset by
+    /// `gather_and_reset` of any of the gatherers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let primary = Gatherer::new();
+    /// let mut secondary = Gatherer::new();
+    /// let foo = CounterBuilder::new("foo").registry(primary.registry()).finish().unwrap();
+    ///
+    /// secondary.subscribe(&primary.registry());
+    /// let bar = CounterBuilder::new("bar").registry(primary.registry()).finish().unwrap();
+    /// foo.increment();
+    ///
+    /// let expected = "# TYPE bar counter\nbar 0\n# TYPE foo counter\nfoo 1\n";
+    /// assert_eq!(primary.gather().to_text(), expected);
+    /// assert_eq!(secondary.gather().to_text(), expected);
+    /// ```
+    pub fn subscribe(&mut self, registry: &Registry) {
+        let hub = &registry.hub;
+        if Arc::ptr_eq(hub, &self.hub) || self.subscriptions.iter().any(|h| Arc::ptr_eq(h, hub)) {
+            return;
         }
+        if let Ok(mut h) = hub.lock() {
+            h.subscribe(self.tx.clone());
+        }
+        self.subscriptions.push(Arc::clone(hub));
+    }
+
+    /// Registers a callback which is invoked at the start of every `gather` call.
+    ///
+    /// This is useful for updating the values of metrics exactly once per scrape.
+    /// If multiple callbacks are registered, they are invoked in registration order.
+    ///
+    /// The callbacks are run serially: while they are running, concurrent `gather` calls wait for them.
+    /// If a callback panics, the panic is propagated to the caller of `gather`,
+    /// but the callbacks are still invoked by the subsequent calls.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::GaugeBuilder;
+    ///
+    /// let mut gatherer = Gatherer::new();
+    /// let gauge = GaugeBuilder::new("foo").registry(gatherer.registry()).finish().unwrap();
+    ///
+    /// let cloned = gauge.clone();
+    /// gatherer.on_before_gather(move || {
+    ///     cloned.increment();
+    /// });
+    /// assert_eq!(gatherer.gather().to_text(), "# TYPE foo gauge\nfoo 1\n");
+    /// assert_eq!(gatherer.gather().to_text(), "# TYPE foo gauge\nfoo 2\n");
+    /// ```
+    pub fn on_before_gather<F>(&mut self, f: F)
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let mut hooks = self.hooks.lock().unwrap_or_else(|e| e.into_inner());
+        hooks.push(Hook(Box::new(f)));
     }
 
     /// Gathers metrics.
-    pub fn gather(&mut self) -> MetricFamilies {
-        while let Ok(collector) = self.rx.try_recv() {
-            self.collectors.push(collector);
+    ///
+    /// The resulting families are sorted by their names and kinds,
+    /// and the metrics in each family are sorted by their labels,
+    /// so the output does not depend on the order in which the metrics were registered.
+    ///
+    /// The collectors of the built-in metrics only hold weak references to them,
+    /// so a metric whose handles have all been dropped never appears in the result of
+    /// a subsequent invocation; its collector is deregistered during that invocation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let gatherer = Gatherer::new();
+    /// let counter = CounterBuilder::new("foo").registry(gatherer.registry()).finish().unwrap();
+    /// assert_eq!(gatherer.gather().to_text(), "# TYPE foo counter\nfoo 0\n");
+    ///
+    /// std::mem::drop(counter);
+    /// assert_eq!(gatherer.gather().to_text(), "");
+    /// ```
+    pub fn gather(&self) -> MetricFamilies {
+        self.gather_with(false)
+    }
+
+    // Gathers metrics, and also resets the `reset_on_collect` counters if `reset` is `true`.
+    fn gather_with(&self, reset: bool) -> MetricFamilies {
+        let start = Instant::now();
+        {
+            // A poisoned lock only means that a hook panicked in a previous gathering.
+            let mut hooks = self.hooks.lock().unwrap_or_else(|e| e.into_inner());
+            for hook in hooks.iter_mut() {
+                (hook.0)();
+            }
         }
 
-        let mut metrics = Vec::new();
-        let mut i = 0;
-        while i < self.collectors.len() {
-            if self.collectors[i].collect(&mut metrics) {
-                i += 1;
-            } else {
-                self.collectors.swap_remove(i);
+        let (mut metrics, descriptions) = self.collect(false);
+        if reset {
+            for metric in &mut metrics {
+                if let Metric::Counter(ref mut counter) = *metric {
+                    if let Some(taken) = counter.take_if_reset_on_collect() {
+                        *counter = taken;
+                    }
+                }
             }
         }
+        if self.suppress_zero {
+            metrics.retain(|m| !is_untouched(m));
+        }
         metrics.sort_by(|a, b| {
             let result = (a.name(), a.kind()).cmp(&(b.name(), b.kind()));
             if result == cmp::Ordering::Equal {
-                a.labels().iter().cmp(b.labels().iter())
+                a.labels()
+                    .with_labels(|x| b.labels().with_labels(|y| x.cmp(y)))
             } else {
                 result
             }
         });
+        if self.dedup {
+            metrics.dedup_by(|a, b| {
+                (a.name(), a.kind()) == (b.name(), b.kind())
+                    && a.labels()
+                        .with_labels(|x| b.labels().with_labels(|y| x.eq(y)))
+            });
+        }
 
         let mut families: Vec<MetricFamily> = Vec::new();
         for metric in metrics {
@@ -121,9 +806,265 @@ impl Gatherer {
                 families.last_mut().unwrap().push(metric);
             }
         }
+
+        // Only the user metrics are counted, so the self-metric gauges are added afterwards.
+        let series = families.iter().map(|f| f.metrics().len()).sum();
+        if let Some(ref previous) = self.self_metrics {
+            let (duration, previous_series) = previous.lock().map(|p| *p).unwrap_or_default();
+            let gauges = vec![
+                self_metric(
+                    "prometrics_gather_duration_seconds",
+                    "Time taken by the previous gathering in seconds",
+                    duration,
+                ),
+                self_metric(
+                    "prometrics_gather_series",
+                    "Number of series produced by the previous gathering",
+                    previous_series as f64,
+                ),
+            ];
+            for gauge in gauges {
+                let position = families
+                    .binary_search_by(|f| (f.name(), f.kind()).cmp(&(gauge.name(), gauge.kind())));
+                match position {
+                    Ok(i) => families[i].push(gauge),
+                    Err(i) => families.insert(i, MetricFamily::new(gauge)),
+                }
+            }
+        }
+        if !descriptions.is_empty() {
+            for (name, kind, help) in descriptions {
+                let position = families
+                    .iter()
+                    .position(|f| (f.name(), f.kind()) == (&name, kind));
+                if let Some(i) = position {
+                    families[i].set_help_if_absent(help);
+                } else {
+                    families.push(MetricFamily::empty(name, kind, help));
+                }
+            }
+            families.sort_by(|a, b| (a.name(), a.kind()).cmp(&(b.name(), b.kind())));
+        }
+        if let Some(ref namespace) = self.namespace {
+            for family in &mut families {
+                family.prepend_namespace(namespace);
+            }
+        }
+        if let Some(ref lint_hook) = self.lint_hook {
+            if let Ok(mut lint_hook) = lint_hook.lock() {
+                let (ref mut hook, ref mut linted) = *lint_hook;
+                for family in families.iter().filter(|f| !f.metrics().is_empty()) {
+                    if linted.insert((family.name().clone(), family.kind())) {
+                        for lint in Lint::check(family) {
+                            (hook.0)(lint);
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(ref previous) = self.self_metrics {
+            let duration = timestamp::duration_to_seconds(start.elapsed());
+            if let Ok(mut p) = previous.lock() {
+                *p = (duration, series);
+            }
+        }
         MetricFamilies(families)
     }
+
+    /// Gathers metrics, and aggregates the metrics in each family by the labels named in `keep`.
+    ///
+    /// The other labels are dropped, and the metrics which have the same remaining labels are merged
+    /// in the same way as the metrics which have the same name and labels are merged by `gather`
+    /// (i.e., the values of counters and gauges are summed up,
+    /// the buckets of histograms and the samples of summaries are combined).
+    /// This is the client-side equivalent of `sum by (keep...)` in PromQL.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let gatherer = Gatherer::new();
+    /// let mut counters = Vec::new();
+    /// for &(method, instance) in &[("GET", "a"), ("GET", "b"), ("PUT", "a")] {
+    ///     let counter = CounterBuilder::new("requests")
+    ///         .label("method", method)
+    ///         .label("instance_id", instance)
+    ///         .registry(gatherer.registry())
+    ///         .finish()
+    ///         .unwrap();
+    ///     counter.increment();
+    ///     counters.push(counter);
+    /// }
+    ///
+    /// assert_eq!(
+    ///     gatherer.gather_grouped_by(&["method"]).to_text(),
+    ///     "# TYPE requests counter\nrequests{method=\"GET\"} 2\nrequests{method=\"PUT\"} 1\n"
+    /// );
+    /// ```
+    pub fn gather_grouped_by(&self, keep: &[&str]) -> MetricFamilies {
+        let mut families = self.gather();
+        for family in &mut families.0 {
+            family.group_by(keep);
+        }
+        families
+    }
+
+    /// Gathers metrics, and resets the counters built with `CounterBuilder::reset_on_collect`.
+    ///
+    /// This is intended for push-style exporters: each result holds exactly the increments
+    /// which have been made to those counters since the previous invocation.
+    /// Other metrics are not affected, and are gathered as `gather` does.
+    /// Note that `gather` never resets counters.
+    ///
+    /// Each counter is read and reset by a single atomic swap, so an increment made by a
+    /// concurrent observer is included in either this result or the next one, but never lost
+    /// nor counted twice. However, the counters are not reset all at once;
+    /// an observer which updates two counters during a gathering may have only one of the
+    /// updates included in this result.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metrics::{CounterBuilder, GaugeBuilder};
+    ///
+    /// let gatherer = Gatherer::new();
+    /// let requests = CounterBuilder::new("requests")
+    ///     .reset_on_collect()
+    ///     .registry(gatherer.registry())
+    ///     .finish()
+    ///     .unwrap();
+    /// let total = CounterBuilder::new("total").registry(gatherer.registry()).finish().unwrap();
+    ///
+    /// requests.add_u64(3);
+    /// total.add_u64(3);
+    /// assert_eq!(
+    ///     gatherer.gather_and_reset().to_text(),
+    ///     "# TYPE requests counter\nrequests 3\n# TYPE total counter\ntotal 3\n"
+    /// );
+    ///
+    /// requests.increment();
+    /// total.increment();
+    /// assert_eq!(
+    ///     gatherer.gather_and_reset().to_text(),
+    ///     "# TYPE requests counter\nrequests 1\n# TYPE total counter\ntotal 4\n"
+    /// );
+    /// ```
+    pub fn gather_and_reset(&self) -> MetricFamilies {
+        self.gather_with(true)
+    }
+
+    /// Returns the names and kinds of the metric families which would be gathered.
+    ///
+    /// Unlike `gather`, this neither aggregates the collected metrics nor reads their values,
+    /// and the hooks registered by `on_before_gather` are not invoked.
+    /// As with `gather`, dead collectors (including the ones which describe their families)
+    /// are deregistered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prometrics::Gatherer;
+    /// use prometrics::metric::MetricKind;
+    /// use prometrics::metrics::{CounterBuilder, GaugeBuilder};
+    ///
+    /// let gatherer = Gatherer::new();
+    /// let _foo = CounterBuilder::new("foo").registry(gatherer.registry()).finish().unwrap();
+    /// let _bar = GaugeBuilder::new("bar").registry(gatherer.registry()).finish().unwrap();
+    ///
+    /// let names = gatherer
+    ///     .metric_names()
+    ///     .into_iter()
+    ///     .map(|(name, kind)| (name.to_string(), kind))
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(
+    ///     names,
+    ///     [
+    ///         ("bar".to_owned(), MetricKind::Gauge),
+    ///         ("foo".to_owned(), MetricKind::Counter)
+    ///     ]
+    /// );
+    /// ```
+    pub fn metric_names(&self) -> Vec<(MetricName, MetricKind)> {
+        let (metrics, descriptions) = self.collect(true);
+        let mut names = metrics
+            .iter()
+            .map(|m| (m.name().clone(), m.kind()))
+            .chain(descriptions.into_iter().map(|(name, kind, _)| (name, kind)))
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        if let Some(ref namespace) = self.namespace {
+            for name in &mut names {
+                name.0 = name.0.with_namespace_prefix(namespace);
+            }
+        }
+        names
+    }
+
+    /// Runs all of the collectors, and deregisters the dead ones.
+    ///
+    /// If `prefer_descriptions` is `true`, the metrics of the collectors which describe their families
+    /// are discarded, and their descriptions are returned instead.
+    fn collect(&self, prefer_descriptions: bool) -> (Vec<Metric>, Vec<Description>) {
+        // The lock is released while collecting, so that concurrent invocations do not block each other.
+        let collectors = self
+            .collectors
+            .lock()
+            .map(|mut c| c.snapshot())
+            .unwrap_or_default();
+        let mut metrics = Vec::new();
+        let mut descriptions = Vec::new();
+        let mut dead = HashSet::new();
+        for collector in &collectors {
+            if prefer_descriptions {
+                let described = collector.describe();
+                if !described.is_empty() {
+                    // The collector is still run to find out whether it is alive,
+                    // but the metrics it produces are discarded.
+                    if collector.collect(&mut Vec::new()) {
+                        descriptions.extend(described);
+                    } else {
+                        dead.insert(Arc::as_ptr(&collector.0));
+                    }
+                    continue;
+                }
+            }
+            if collector.collect(&mut metrics) {
+                descriptions.extend(collector.describe());
+            } else {
+                dead.insert(Arc::as_ptr(&collector.0));
+            }
+        }
+        if !dead.is_empty() {
+            if let Ok(mut c) = self.collectors.lock() {
+                c.remove(&dead);
+            }
+        }
+        (metrics, descriptions)
+    }
+}
+// Returns `true` if `metric` has neither a non-zero value nor been updated.
+fn is_untouched(metric: &Metric) -> bool {
+    match *metric {
+        Metric::Counter(ref m) => m.value() == 0.0 && !m.observed(),
+        Metric::Gauge(ref m) => m.value() == 0.0 && !m.observed(),
+        Metric::Summary(ref m) => m.count() == 0,
+        Metric::Histogram(ref m) => m.count() == 0,
+    }
+}
+
+// Makes a detached gauge which holds a value of the self-metrics of `Gatherer`.
+fn self_metric(name: &str, help: &str, value: f64) -> Metric {
+    let gauge = track_try_unwrap!(GaugeBuilder::new(name)
+        .help(help)
+        .initial_value(value)
+        .finish());
+    Metric::Gauge(gauge)
 }
+
 impl Default for Gatherer {
     fn default() -> Self {
         Self::new()
@@ -8,6 +8,9 @@ use atomic::AtomicI64;
 const NO_VALUE: i64 = std::i64::MIN;
 
 /// Unix timestamp in milliseconds.
+///
+/// Times before the Unix epoch are represented as negative values,
+/// and they are output to the text format as they are (e.g., `foo 1 -1500`).
 #[derive(Debug)]
 pub struct Timestamp(AtomicI64);
 impl Timestamp {
@@ -21,6 +24,27 @@ impl Timestamp {
         }
     }
 
+    /// Returns the value of this timestamp in seconds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// use prometrics::metrics::CounterBuilder;
+    ///
+    /// let counter = CounterBuilder::new("foo").finish().unwrap();
+    /// assert_eq!(counter.timestamp().as_seconds_f64(), None);
+    ///
+    /// counter.timestamp_mut().set(1500);
+    /// assert_eq!(counter.timestamp().as_seconds_f64(), Some(1.5));
+    ///
+    /// counter.timestamp_mut().set_time(UNIX_EPOCH - Duration::from_millis(1500));
+    /// assert_eq!(counter.timestamp().as_seconds_f64(), Some(-1.5));
+    /// ```
+    pub fn as_seconds_f64(&self) -> Option<f64> {
+        self.get().map(|v| v as f64 / 1000.0)
+    }
+
     pub(crate) fn new() -> Self {
         Timestamp(AtomicI64::new(NO_VALUE))
     }
@@ -35,15 +59,7 @@ impl Timestamp {
         self.0.set(timestamp);
     }
     fn set_time(&self, time: SystemTime) {
-        fn to_millis(d: Duration) -> i64 {
-            d.as_secs() as i64 * 1000 + i64::from(d.subsec_nanos()) / 1000 / 1000
-        }
-        if let Ok(duration) = time.duration_since(UNIX_EPOCH) {
-            self.set(to_millis(duration));
-        } else {
-            let duration = UNIX_EPOCH.duration_since(time).expect("Never fails");
-            self.set(-to_millis(duration));
-        }
+        self.set(unixtime_millis(time));
     }
     fn set_now(&self) {
         self.set_time(SystemTime::now());
@@ -55,6 +71,13 @@ impl Timestamp {
 pub struct TimestampMut<'a>(&'a Timestamp);
 impl<'a> TimestampMut<'a> {
     /// Sets the value of this timestamp to `timestamp`.
+    ///
+    /// Negative values designate times before the Unix epoch.
+    ///
+    /// # Panics
+    ///
+    /// `std::i64::MIN` is reserved for representing the absence of the value,
+    /// so this method will panic if `timestamp` is equal to it.
     pub fn set(&mut self, timestamp: i64) {
         self.0.set(timestamp)
     }
@@ -65,6 +88,9 @@ impl<'a> TimestampMut<'a> {
     }
 
     /// Sets the value of this timestamp to `time`.
+    ///
+    /// Sub-millisecond precision is truncated toward the Unix epoch,
+    /// so a time 1.5 milliseconds before the epoch is set to `-1`.
     pub fn set_time(&mut self, time: SystemTime) {
         self.0.set_time(time)
     }
@@ -85,6 +111,22 @@ impl<'a> Deref for TimestampMut<'a> {
     }
 }
 
+fn unixtime_millis(time: SystemTime) -> i64 {
+    fn to_millis(d: Duration) -> i64 {
+        d.as_secs() as i64 * 1000 + i64::from(d.subsec_nanos()) / 1000 / 1000
+    }
+    if let Ok(duration) = time.duration_since(UNIX_EPOCH) {
+        to_millis(duration)
+    } else {
+        let duration = UNIX_EPOCH.duration_since(time).expect("Never fails");
+        -to_millis(duration)
+    }
+}
+
+pub(crate) fn now_unixtime_millis() -> i64 {
+    unixtime_millis(SystemTime::now())
+}
+
 pub(crate) fn now_unixtime_seconds() -> f64 {
     let now = SystemTime::now();
     if let Ok(d) = now.duration_since(UNIX_EPOCH) {
@@ -99,3 +141,29 @@ pub(crate) fn now_unixtime_seconds() -> f64 {
 pub fn duration_to_seconds(d: Duration) -> f64 {
     d.as_secs() as f64 + f64::from(d.subsec_nanos()) / 1_000_000_000.0
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use metrics::CounterBuilder;
+
+    #[test]
+    fn negative_timestamp_works() {
+        let counter = track_try_unwrap!(CounterBuilder::new("foo").finish());
+
+        counter
+            .timestamp_mut()
+            .set_time(UNIX_EPOCH - Duration::from_millis(1500));
+        assert_eq!(counter.timestamp().get(), Some(-1500));
+        assert_eq!(counter.to_string(), "foo 0 -1500");
+
+        counter
+            .timestamp_mut()
+            .set_time(UNIX_EPOCH - Duration::from_micros(1500));
+        assert_eq!(counter.timestamp().get(), Some(-1));
+
+        counter.timestamp_mut().set(-1);
+        assert_eq!(counter.timestamp().as_seconds_f64(), Some(-0.001));
+    }
+}